@@ -0,0 +1,253 @@
+use async_std::task;
+use chrono::Local;
+use crate::error::Error;
+use crate::record::{RecordAt, record_api_usage};
+use ragit_fs::{WriteMode, join, write_log, write_string};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+/// A request to an OpenAI-compatible `/embeddings` endpoint.
+///
+/// Unlike `Request`, it's not bound to a `Model`, because embedding models are
+/// not in `models.json`. Any server that speaks the OpenAI embedding api works,
+/// including a local mock server.
+#[derive(Clone, Debug)]
+pub struct EmbeddingRequest {
+    pub input: Vec<String>,
+
+    /// The model name that the api understands, e.g. `text-embedding-3-small`.
+    pub model: String,
+
+    /// Full url of the endpoint, e.g. `https://api.openai.com/v1/embeddings`.
+    pub url: String,
+
+    /// If it's empty, it doesn't send an `Authorization` header.
+    pub api_key: String,
+
+    /// milliseconds
+    pub timeout: Option<u64>,
+
+    /// It tries 1 + max_retry times.
+    pub max_retry: usize,
+
+    /// milliseconds
+    pub sleep_between_retries: u64,
+    pub record_api_usage_at: Option<RecordAt>,
+
+    /// It's a directory, not a file. If given, it dumps `dir/request-<timestamp>.json` and `dir/response-<timestamp>.json`.
+    pub dump_json_at: Option<String>,
+}
+
+impl Default for EmbeddingRequest {
+    fn default() -> Self {
+        EmbeddingRequest {
+            input: vec![],
+            model: String::from("text-embedding-3-small"),
+            url: String::from("https://api.openai.com/v1/embeddings"),
+            api_key: String::new(),
+            timeout: None,
+            max_retry: 0,
+            sleep_between_retries: 0,
+            record_api_usage_at: None,
+            dump_json_at: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+    usage: Option<EmbeddingUsage>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingUsage {
+    prompt_tokens: usize,
+}
+
+impl EmbeddingResponse {
+    /// The api may return the vectors in any order, so it sorts them by `index`.
+    pub(crate) fn into_vectors(mut self, input_len: usize) -> Result<Vec<Vec<f32>>, Error> {
+        if self.data.len() != input_len {
+            return Err(Error::WrongEmbeddingResponse(format!(
+                "expected {input_len} vectors, got {}",
+                self.data.len(),
+            )));
+        }
+
+        self.data.sort_by_key(|data| data.index);
+
+        for (i, data) in self.data.iter().enumerate() {
+            if data.index != i {
+                return Err(Error::WrongEmbeddingResponse(format!("missing index {i}")));
+            }
+        }
+
+        Ok(self.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
+
+impl EmbeddingRequest {
+    pub fn build_json_body(&self) -> Value {
+        let mut result = Map::new();
+        result.insert(String::from("model"), self.model.clone().into());
+        result.insert(String::from("input"), self.input.clone().into());
+        result.into()
+    }
+
+    /// It returns one vector per input, in the same order as `self.input`.
+    pub async fn send(&self) -> Result<Vec<Vec<f32>>, Error> {
+        if self.input.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let client = reqwest::Client::new();
+        let mut curr_error = Error::NoTry;
+        let body = self.build_json_body();
+
+        if let Err(e) = self.dump_json(&body, "request") {
+            write_log(
+                "dump_json",
+                &format!("dump_json(\"request\", ..) failed with {e:?}"),
+            );
+        }
+
+        let body = serde_json::to_string(&body)?;
+        write_log(
+            "embedding_request::send",
+            &format!("entered embedding_request::send() with {} inputs, model: {}", self.input.len(), self.model),
+        );
+
+        for _ in 0..(self.max_retry + 1) {
+            let mut request = client.post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+
+            if !self.api_key.is_empty() {
+                request = request.bearer_auth(self.api_key.clone());
+            }
+
+            if let Some(t) = self.timeout {
+                request = request.timeout(Duration::from_millis(t));
+            }
+
+            match request.send().await {
+                Ok(response) => match response.status().as_u16() {
+                    200 => match response.text().await {
+                        Ok(text) => match serde_json::from_str::<Value>(&text) {
+                            Ok(v) => {
+                                if let Err(e) = self.dump_json(&v, "response") {
+                                    write_log(
+                                        "dump_json",
+                                        &format!("dump_json(\"response\", ..) failed with {e:?}"),
+                                    );
+                                }
+
+                                match serde_json::from_value::<EmbeddingResponse>(v) {
+                                    Ok(response) => {
+                                        let prompt_tokens = response.usage.as_ref().map(|usage| usage.prompt_tokens).unwrap_or(0);
+
+                                        match response.into_vectors(self.input.len()) {
+                                            Ok(vectors) => {
+                                                if let Some(key) = &self.record_api_usage_at {
+                                                    if let Err(e) = record_api_usage(
+                                                        key,
+                                                        prompt_tokens as u64,
+                                                        0,
+                                                        0,
+                                                        0,
+                                                        false,
+                                                    ) {
+                                                        write_log(
+                                                            "record_api_usage",
+                                                            &format!("record_api_usage({key:?}, ..) failed with {e:?}"),
+                                                        );
+                                                    }
+                                                }
+
+                                                return Ok(vectors);
+                                            },
+                                            Err(e) => {
+                                                curr_error = e;
+                                            },
+                                        }
+                                    },
+                                    Err(e) => {
+                                        curr_error = Error::JsonSerdeError(e);
+                                    },
+                                }
+                            },
+                            Err(e) => {
+                                curr_error = Error::JsonSerdeError(e);
+                            },
+                        },
+                        Err(e) => {
+                            curr_error = Error::ReqwestError(e);
+                        },
+                    },
+                    status_code => {
+                        curr_error = Error::ServerError {
+                            status_code,
+                            body: response.text().await,
+                        };
+                    },
+                },
+                Err(e) => {
+                    write_log(
+                        "request.send().await",
+                        &format!("request.send().await failed with {e:?}"),
+                    );
+                    curr_error = Error::ReqwestError(e);
+                },
+            }
+
+            task::sleep(Duration::from_millis(self.sleep_between_retries)).await
+        }
+
+        Err(curr_error)
+    }
+
+    fn dump_json(&self, j: &Value, header: &str) -> Result<(), Error> {
+        if let Some(dir) = &self.dump_json_at {
+            let path = join(
+                dir,
+                &format!("embedding-{header}-{}.json", Local::now().to_rfc3339()),
+            )?;
+            write_string(&path, &serde_json::to_string_pretty(j)?, WriteMode::AlwaysCreate)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// It returns 0.0 if either vector is a zero vector or the dimensions do not match.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    let mut dot = 0.0;
+    let mut norm_a = 0.0;
+    let mut norm_b = 0.0;
+
+    for (x, y) in a.iter().zip(b.iter()) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    }
+
+    else {
+        dot / (norm_a.sqrt() * norm_b.sqrt())
+    }
+}
@@ -31,6 +31,9 @@ pub enum Error {
     TeraError(tera::Error),
 
     WrongSchema(String),
+
+    /// The embedding api returned a response that does not match the request.
+    WrongEmbeddingResponse(String),
     ServerError {
         status_code: u16,
         body: Result<String, reqwest::Error>,
@@ -38,6 +41,9 @@ pub enum Error {
     UnsupportedMediaFormat {
         extension: Option<String>,
     },
+
+    /// The server closed or broke the stream in the middle of a response.
+    StreamError(String),
 }
 
 impl From<ragit_pdl::Error> for Error {
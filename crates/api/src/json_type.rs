@@ -60,6 +60,14 @@ impl JsonType {
                 Ok(v) => Ok(v),
                 Err(_) => Ok(Value::from(s)),
             },
+            JsonType::Array | JsonType::Object => match serde_json::from_str::<Value>(s) {
+                Ok(v) if JsonType::from(&v) == *self => Ok(v),
+                Ok(v) => Err(Error::JsonTypeError {
+                    expected: *self,
+                    got: (&v).into(),
+                }),
+                Err(e) => Err(e.into()),
+            },
             _ => todo!(),
         }
     }
@@ -1,6 +1,7 @@
 use ragit_fs::{WriteMode, read_string, write_string};
 
 mod api_provider;
+mod embedding;
 mod error;
 mod json_type;
 mod message;
@@ -8,8 +9,10 @@ mod model;
 pub mod record;
 mod request;
 mod response;
+mod stream;
 
 pub use crate::api_provider::ApiProvider;
+pub use crate::embedding::{EmbeddingRequest, cosine_similarity};
 pub use crate::error::Error;
 pub use crate::json_type::JsonType;
 pub use crate::message::message_contents_to_json_array;
@@ -17,6 +20,7 @@ pub use crate::model::{Model, ModelRaw, get_model_by_name};
 pub use crate::record::RecordAt;
 pub use crate::request::Request;
 pub use crate::response::Response;
+pub use crate::stream::ResponseStream;
 
 pub use ragit_pdl::{
     ImageType,
@@ -49,7 +53,8 @@ pub fn save_models(models: &[Model], path: &str) -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{ModelRaw, Request};
+    use crate::{ModelRaw, Request, cosine_similarity};
+    use crate::embedding::EmbeddingResponse;
     use ragit_fs::{
         WriteMode,
         create_dir_all,
@@ -114,4 +119,28 @@ What do you see in this picture?
 
         remove_dir_all("__tmp_pdl_test").unwrap();
     }
+
+    #[test]
+    fn embedding_response_test() {
+        // OpenAI-compatible servers may return the vectors in any order
+        let response = r#"{
+            "object": "list",
+            "data": [
+                { "object": "embedding", "index": 1, "embedding": [0.0, 1.0] },
+                { "object": "embedding", "index": 0, "embedding": [1.0, 0.0] }
+            ],
+            "model": "mock",
+            "usage": { "prompt_tokens": 4, "total_tokens": 4 }
+        }"#;
+        let vectors = serde_json::from_str::<EmbeddingResponse>(response).unwrap().into_vectors(2).unwrap();
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+        let response = r#"{ "data": [{ "index": 0, "embedding": [1.0] }] }"#;
+        assert!(serde_json::from_str::<EmbeddingResponse>(response).unwrap().into_vectors(2).is_err());
+
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[1.0, 0.0]), 1.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 1.0]), 0.0);
+    }
 }
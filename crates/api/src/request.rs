@@ -180,7 +180,6 @@ impl Request {
         let client = reqwest::Client::new();
         let mut curr_error = Error::NoTry;
 
-        let post_url = self.model.get_api_url();
         let body = self.build_json_body();
 
         if let Err(e) = self.dump_json(&body, "request") {
@@ -238,18 +237,7 @@ impl Request {
         );
 
         for _ in 0..(self.max_retry + 1) {
-            let mut request = client.post(post_url)
-                .header(reqwest::header::CONTENT_TYPE, "application/json")
-                .body(body.clone());
-
-            if let ApiProvider::Anthropic = &self.model.api_provider {
-                request = request.header("x-api-key", api_key.clone())
-                    .header("anthropic-version", "2023-06-01");
-            }
-
-            else if !api_key.is_empty() {
-                request = request.bearer_auth(api_key.clone());
-            }
+            let mut request = self.build_http_request(&client, &body, &api_key);
 
             if let Some(t) = self.timeout {
                 request = request.timeout(Duration::from_millis(t));
@@ -385,7 +373,24 @@ impl Request {
         Err(curr_error)
     }
 
-    fn dump_json(&self, j: &Value, header: &str) -> Result<(), Error> {
+    pub(crate) fn build_http_request(&self, client: &reqwest::Client, body: &str, api_key: &str) -> reqwest::RequestBuilder {
+        let mut request = client.post(self.model.get_api_url())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+
+        if let ApiProvider::Anthropic = &self.model.api_provider {
+            request = request.header("x-api-key", api_key)
+                .header("anthropic-version", "2023-06-01");
+        }
+
+        else if !api_key.is_empty() {
+            request = request.bearer_auth(api_key);
+        }
+
+        request
+    }
+
+    pub(crate) fn dump_json(&self, j: &Value, header: &str) -> Result<(), Error> {
         if let Some(dir) = &self.dump_json_at {
             let path = join(
                 &dir,
@@ -14,11 +14,11 @@ pub trait IntoChatResponse {
 }
 
 pub struct Response {
-    messages: Vec<String>,
-    reasonings: Vec<Option<String>>,
-    output_tokens: usize,
-    prompt_tokens: usize,
-    total_tokens: usize,
+    pub(crate) messages: Vec<String>,
+    pub(crate) reasonings: Vec<Option<String>>,
+    pub(crate) output_tokens: usize,
+    pub(crate) prompt_tokens: usize,
+    pub(crate) total_tokens: usize,
 }
 
 impl Response {
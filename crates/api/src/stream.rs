@@ -0,0 +1,438 @@
+use async_std::task;
+use crate::{ApiProvider, Error};
+use crate::record::{dump_pdl, record_api_usage};
+use crate::request::Request;
+use crate::response::Response;
+use futures::Stream;
+use ragit_fs::write_log;
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Deltas of a response that are sent by the server as they are generated. You get this from
+/// `Request::send_stream`.
+///
+/// Once the stream is over, it records the api usage and dumps the pdl log, just like
+/// `Request::send` does.
+pub struct ResponseStream {
+    request: Request,
+
+    // `None` if it's a test model
+    response: Option<reqwest::Response>,
+
+    // bytes that are not a complete line yet
+    buffer: Vec<u8>,
+    pending: VecDeque<String>,
+    message: String,
+    prompt_tokens: usize,
+    output_tokens: usize,
+    started_at: Instant,
+
+    // the server says the message is complete, or the connection is closed
+    finished: bool,
+
+    // `record_api_usage` and `dump_pdl` are called
+    recorded: bool,
+}
+
+impl ResponseStream {
+    /// It returns the next delta, or `None` if the stream is over.
+    pub async fn next(&mut self) -> Result<Option<String>, Error> {
+        loop {
+            if let Some(delta) = self.pending.pop_front() {
+                return Ok(Some(delta));
+            }
+
+            if self.finished {
+                self.record();
+                return Ok(None);
+            }
+
+            let Some(response) = &mut self.response else {
+                self.finished = true;
+                continue;
+            };
+
+            // `Request::timeout` is the maximum interval between chunks, not the time limit of the entire response.
+            let chunk = match self.request.timeout {
+                Some(t) => match tokio::time::timeout(Duration::from_millis(t), response.chunk()).await {
+                    Ok(chunk) => chunk?,
+                    Err(_) => {
+                        return Err(Error::StreamError(format!("no data for {t}ms")));
+                    },
+                },
+                None => response.chunk().await?,
+            };
+
+            match chunk {
+                Some(chunk) => {
+                    self.buffer.extend_from_slice(&chunk);
+
+                    while let Some(index) = self.buffer.iter().position(|b| *b == b'\n') {
+                        let line = self.buffer.drain(..(index + 1)).collect::<Vec<_>>();
+                        self.parse_line(&String::from_utf8_lossy(&line))?;
+                    }
+                },
+                None => {
+                    let line = std::mem::take(&mut self.buffer);
+                    self.parse_line(&String::from_utf8_lossy(&line))?;
+                    self.finished = true;
+                },
+            }
+        }
+    }
+
+    /// It consumes the remaining deltas, and returns the entire response.
+    pub async fn collect(mut self) -> Result<Response, Error> {
+        while self.next().await?.is_some() {}
+
+        Ok(self.into_response())
+    }
+
+    /// The deltas that are not consumed yet are not included.
+    pub fn into_response(self) -> Response {
+        Response {
+            messages: vec![self.message],
+            reasonings: vec![None],
+            output_tokens: self.output_tokens,
+            prompt_tokens: self.prompt_tokens,
+            total_tokens: self.output_tokens + self.prompt_tokens,
+        }
+    }
+
+    /// If you prefer `futures::Stream`. It ends after the first error.
+    pub fn into_stream(self) -> impl Stream<Item = Result<String, Error>> {
+        futures::stream::unfold(
+            Some(self),
+            |stream| async move {
+                let mut stream = stream?;
+
+                match stream.next().await {
+                    Ok(Some(delta)) => Some((Ok(delta), Some(stream))),
+                    Ok(None) => None,
+                    Err(e) => Some((Err(e), None)),
+                }
+            },
+        )
+    }
+
+    // Each provider sends server-sent events, but the schemas of the events are different.
+    fn parse_line(&mut self, line: &str) -> Result<(), Error> {
+        let Some(data) = line.trim().strip_prefix("data:") else { return Ok(()); };
+        let data = data.trim();
+
+        if data == "[DONE]" {
+            self.finished = true;
+            return Ok(());
+        }
+
+        let event = serde_json::from_str::<Value>(data)?;
+        let delta = match &self.request.model.api_provider {
+            ApiProvider::OpenAi { .. } => {
+                // some servers (e.g. groq) put the usage in a different place
+                for usage in [&event["usage"], &event["x_groq"]["usage"]] {
+                    if let (Some(prompt_tokens), Some(output_tokens)) = (usage["prompt_tokens"].as_u64(), usage["completion_tokens"].as_u64()) {
+                        self.prompt_tokens = prompt_tokens as usize;
+                        self.output_tokens = output_tokens as usize;
+                    }
+                }
+
+                event["choices"][0]["delta"]["content"].as_str()
+            },
+            ApiProvider::Anthropic => match event["type"].as_str() {
+                Some("message_start") => {
+                    let usage = &event["message"]["usage"];
+                    self.prompt_tokens = usage["input_tokens"].as_u64().unwrap_or(0) as usize;
+                    self.output_tokens = usage["output_tokens"].as_u64().unwrap_or(0) as usize;
+                    None
+                },
+                Some("content_block_delta") => event["delta"]["text"].as_str(),
+                Some("message_delta") => {
+                    if let Some(output_tokens) = event["usage"]["output_tokens"].as_u64() {
+                        self.output_tokens = output_tokens as usize;
+                    }
+
+                    None
+                },
+                Some("message_stop") => {
+                    self.finished = true;
+                    None
+                },
+                Some("error") => {
+                    return Err(Error::StreamError(event["error"].to_string()));
+                },
+                _ => None,
+            },
+            ApiProvider::Cohere => match event["type"].as_str() {
+                Some("content-delta") => event["delta"]["message"]["content"]["text"].as_str(),
+                Some("message-end") => {
+                    let tokens = &event["delta"]["usage"]["tokens"];
+                    self.prompt_tokens = tokens["input_tokens"].as_u64().unwrap_or(0) as usize;
+                    self.output_tokens = tokens["output_tokens"].as_u64().unwrap_or(0) as usize;
+                    self.finished = true;
+                    None
+                },
+                _ => None,
+            },
+            ApiProvider::Test(_) => unreachable!(),
+        };
+
+        if let Some(delta) = delta {
+            if !delta.is_empty() {
+                self.message.push_str(delta);
+                self.pending.push_back(delta.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record(&mut self) {
+        if self.recorded {
+            return;
+        }
+
+        self.recorded = true;
+
+        if let Err(e) = self.request.dump_json(
+            &json!({
+                "message": self.message,
+                "prompt_tokens": self.prompt_tokens,
+                "output_tokens": self.output_tokens,
+            }),
+            "response",
+        ) {
+            write_log(
+                "dump_json",
+                &format!("dump_json(\"response\", ..) failed with {e:?}"),
+            );
+        }
+
+        if let Some(key) = &self.request.record_api_usage_at {
+            if let Err(e) = record_api_usage(
+                key,
+                self.prompt_tokens as u64,
+                self.output_tokens as u64,
+                self.request.model.dollars_per_1b_input_tokens,
+                self.request.model.dollars_per_1b_output_tokens,
+                false,
+            ) {
+                write_log(
+                    "record_api_usage",
+                    &format!("record_api_usage({key:?}, ..) failed with {e:?}"),
+                );
+            }
+        }
+
+        if let Some(path) = &self.request.dump_pdl_at {
+            if let Err(e) = dump_pdl(
+                &self.request.messages,
+                &self.message,
+                &None,
+                path,
+                format!(
+                    "model: {}, input_tokens: {}, output_tokens: {}, took: {}ms",
+                    self.request.model.name,
+                    self.prompt_tokens,
+                    self.output_tokens,
+                    Instant::now().duration_since(self.started_at).as_millis(),
+                ),
+            ) {
+                write_log(
+                    "dump_pdl",
+                    &format!("dump_pdl({path:?}, ..) failed with {e:?}"),
+                );
+            }
+        }
+    }
+}
+
+impl Request {
+    /// It's like `send`, but it returns the response as soon as the server starts sending it.
+    /// It retries only when it fails to connect. Once it's connected, an error is returned
+    /// as it is. `timeout` is the maximum interval between chunks.
+    ///
+    /// It panics if its fields are not complete. If you're not sure, run `self.is_valid()` before sending a request.
+    pub async fn send_stream(&self) -> Result<ResponseStream, Error> {
+        let started_at = Instant::now();
+        let mut stream = ResponseStream {
+            request: self.clone(),
+            response: None,
+            buffer: vec![],
+            pending: VecDeque::new(),
+            message: String::new(),
+            prompt_tokens: 0,
+            output_tokens: 0,
+            started_at,
+            finished: false,
+            recorded: false,
+        };
+
+        if let ApiProvider::Test(test_model) = &self.model.api_provider {
+            let response = test_model.get_dummy_response(&self.messages);
+            stream.message = response.clone();
+            stream.pending.push_back(response);
+            return Ok(stream);
+        }
+
+        let client = reqwest::Client::new();
+        let mut curr_error = Error::NoTry;
+        let mut body = self.build_json_body();
+
+        if let Value::Object(body) = &mut body {
+            body.insert(String::from("stream"), true.into());
+
+            // Otherwise, OpenAI doesn't tell you how many tokens are used.
+            if let ApiProvider::OpenAi { .. } = &self.model.api_provider {
+                body.insert(String::from("stream_options"), json!({ "include_usage": true }));
+            }
+        }
+
+        if let Err(e) = self.dump_json(&body, "request") {
+            write_log(
+                "dump_json",
+                &format!("dump_json(\"request\", ..) failed with {e:?}"),
+            );
+        }
+
+        let body = serde_json::to_string(&body)?;
+        let api_key = self.model.get_api_key()?;
+        write_log(
+            "chat_request::send_stream",
+            &format!("entered chat_request::send_stream() with {} bytes, model: {}", body.len(), self.model.name),
+        );
+
+        for _ in 0..(self.max_retry + 1) {
+            let request = self.build_http_request(&client, &body, &api_key);
+            let response = match self.timeout {
+                Some(t) => match tokio::time::timeout(Duration::from_millis(t), request.send()).await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        curr_error = Error::StreamError(format!("no response for {t}ms"));
+                        task::sleep(Duration::from_millis(self.sleep_between_retries)).await;
+                        continue;
+                    },
+                },
+                None => request.send().await,
+            };
+
+            match response {
+                Ok(response) => match response.status().as_u16() {
+                    200 => {
+                        stream.response = Some(response);
+                        return Ok(stream);
+                    },
+                    status_code => {
+                        curr_error = Error::ServerError {
+                            status_code,
+                            body: response.text().await,
+                        };
+
+                        if let Some(path) = &self.dump_pdl_at {
+                            if let Err(e) = dump_pdl(
+                                &self.messages,
+                                "",
+                                &None,
+                                path,
+                                format!("{}# error: {curr_error:?} #{}", '{', '}'),
+                            ) {
+                                write_log(
+                                    "dump_pdl",
+                                    &format!("dump_pdl({path:?}, ..) failed with {e:?}"),
+                                );
+                            }
+                        }
+                    },
+                },
+                Err(e) => {
+                    write_log(
+                        "request.send().await",
+                        &format!("request.send().await failed with {e:?}"),
+                    );
+                    curr_error = Error::ReqwestError(e);
+                },
+            }
+
+            task::sleep(Duration::from_millis(self.sleep_between_retries)).await
+        }
+
+        Err(curr_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ModelRaw;
+
+    fn parse(api_provider: ApiProvider, events: &str) -> (Vec<String>, Response) {
+        let mut model: crate::Model = (&ModelRaw::llama_70b()).try_into().unwrap();
+        model.api_provider = api_provider;
+        let request = Request { model, ..Request::default() };
+        let mut stream = ResponseStream {
+            request,
+            response: None,
+            buffer: vec![],
+            pending: VecDeque::new(),
+            message: String::new(),
+            prompt_tokens: 0,
+            output_tokens: 0,
+            started_at: Instant::now(),
+            finished: false,
+            recorded: true,
+        };
+
+        for line in events.lines() {
+            stream.parse_line(line).unwrap();
+        }
+
+        (stream.pending.drain(..).collect(), stream.into_response())
+    }
+
+    #[test]
+    fn stream_event_test() {
+        let (deltas, response) = parse(
+            ApiProvider::OpenAi { url: String::new() },
+            r#"data: {"choices":[{"delta":{"role":"assistant","content":""}}]}
+
+data: {"choices":[{"delta":{"content":"Hello"}}]}
+
+data: {"choices":[{"delta":{"content":", world"}}],"usage":null}
+
+data: {"choices":[],"usage":{"prompt_tokens":10,"completion_tokens":3,"total_tokens":13}}
+
+data: [DONE]"#,
+        );
+        assert_eq!(deltas, vec!["Hello", ", world"]);
+        assert_eq!(response.get_message(0), Some("Hello, world"));
+        assert_eq!((response.get_prompt_token_count(), response.get_output_token_count()), (10, 3));
+
+        let (deltas, response) = parse(
+            ApiProvider::Anthropic,
+            r#"event: message_start
+data: {"type":"message_start","message":{"usage":{"input_tokens":25,"output_tokens":1}}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hello"}}
+
+event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":15}}
+
+event: message_stop
+data: {"type":"message_stop"}"#,
+        );
+        assert_eq!(deltas, vec!["Hello"]);
+        assert_eq!((response.get_prompt_token_count(), response.get_output_token_count()), (25, 15));
+
+        let (deltas, response) = parse(
+            ApiProvider::Cohere,
+            r#"event: content-delta
+data: {"type":"content-delta","index":0,"delta":{"message":{"content":{"text":"Hi"}}}}
+
+event: message-end
+data: {"type":"message-end","delta":{"finish_reason":"COMPLETE","usage":{"billed_units":{"input_tokens":3,"output_tokens":1},"tokens":{"input_tokens":9,"output_tokens":1}}}}"#,
+        );
+        assert_eq!(deltas, vec!["Hi"]);
+        assert_eq!((response.get_prompt_token_count(), response.get_output_token_count()), (9, 1));
+    }
+}
@@ -0,0 +1,120 @@
+/*
+rules
+
+1. It splits a string into runs of the same script. CJK punctuation marks split the runs and are removed.
+2. A run of 한자, ひらがな or カタカナ is split into characters (unigrams) and overlapping bigrams, like
+   Lucene's CJKBigramFilter with `outputUnigrams`. The unigrams make single-character words searchable.
+   A run with only 1 character is a unigram.
+3. A run of the other scripts is left as it is.
+*/
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Script {
+    Han,
+    Hiragana,
+    Katakana,
+
+    /// Everything else, including 한글 and ascii.
+    Other,
+}
+
+impl Script {
+    pub fn is_cjk(&self) -> bool {
+        !matches!(self, Script::Other)
+    }
+}
+
+/// It returns `None` for CJK punctuation marks.
+pub fn get_script(ch: char) -> Option<Script> {
+    match ch {
+        // 々, 〆 and 〇 are parts of words
+        '\u{3005}'..='\u{3007}' => Some(Script::Han),
+
+        // CJK symbols and punctuation
+        '\u{3000}'..='\u{303f}'
+        // katakana middle dot
+        | '\u{30fb}'
+        // fullwidth and halfwidth punctuation
+        | '\u{ff01}'..='\u{ff0f}'
+        | '\u{ff1a}'..='\u{ff20}'
+        | '\u{ff3b}'..='\u{ff40}'
+        | '\u{ff5b}'..='\u{ff65}' => None,
+
+        '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{20000}'..='\u{2fa1f}' => Some(Script::Han),
+
+        '\u{3040}'..='\u{309f}' => Some(Script::Hiragana),
+
+        // `ー` (U+30FC) is included
+        '\u{30a0}'..='\u{30ff}'
+        | '\u{31f0}'..='\u{31ff}'
+        | '\u{ff66}'..='\u{ff9f}' => Some(Script::Katakana),
+
+        _ => Some(Script::Other),
+    }
+}
+
+/// It splits `s` into runs of the same script.
+pub fn split_by_script(s: &str) -> Vec<(Script, &str)> {
+    let mut result = vec![];
+    let mut curr: Option<(Script, usize)> = None;
+
+    for (index, ch) in s.char_indices() {
+        let script = get_script(ch);
+
+        match (curr, script) {
+            (Some((curr_script, _)), Some(script)) if curr_script == script => {},
+            (Some((curr_script, start)), script) => {
+                result.push((curr_script, &s[start..index]));
+                curr = script.map(|script| (script, index));
+            },
+            (None, script) => {
+                curr = script.map(|script| (script, index));
+            },
+        }
+    }
+
+    if let Some((script, start)) = curr {
+        result.push((script, &s[start..]));
+    }
+
+    result
+}
+
+pub fn tokenize(s: &str) -> Vec<String> {
+    let mut result = vec![];
+
+    for (script, run) in split_by_script(s) {
+        if script.is_cjk() {
+            result.extend(unigrams_and_bigrams(run));
+        }
+
+        else {
+            result.push(run.to_string());
+        }
+    }
+
+    result
+}
+
+/// `run` is a run of 한자, ひらがな or カタカナ (see `split_by_script`).
+/// "東京都" -> "東", "東京", "京", "京都", "都"
+pub fn unigrams_and_bigrams(run: &str) -> Vec<String> {
+    let chars = run.chars().collect::<Vec<_>>();
+    let mut result = Vec::with_capacity(chars.len() * 2);
+
+    for (index, ch) in chars.iter().enumerate() {
+        result.push(ch.to_string());
+
+        if let Some(next) = chars.get(index + 1) {
+            result.push(format!("{ch}{next}"));
+        }
+    }
+
+    result
+}
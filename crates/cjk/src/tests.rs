@@ -0,0 +1,45 @@
+use crate::{Script, split_by_script, tokenize, unigrams_and_bigrams};
+
+#[test]
+fn tokenize_cjk() {
+    let sample = [
+        ("東京都庁", vec!["東", "東京", "京", "京都", "都", "都庁", "庁"]),
+        ("東京の天気", vec!["東", "東京", "京", "の", "天", "天気", "気"]),
+        ("コンピュータ", vec!["コ", "コン", "ン", "ンピ", "ピ", "ピュ", "ュ", "ュー", "ー", "ータ", "タ"]),
+        ("我爱北京。", vec!["我", "我爱", "爱", "爱北", "北", "北京", "京"]),
+        ("「人々」", vec!["人", "人々", "々"]),
+        ("日", vec!["日"]),
+        ("ragitは速い", vec!["ragit", "は", "速", "い"]),
+        ("한국語", vec!["한국", "語"]),
+        ("abc", vec!["abc"]),
+        ("", vec![]),
+        ("、。", vec![]),
+    ];
+
+    for (s, answer) in sample.into_iter() {
+        assert_eq!(tokenize(s), answer, "{s:?}");
+    }
+}
+
+#[test]
+fn single_character_words() {
+    // "京" is a word by itself, and it has to match "東京都".
+    assert!(unigrams_and_bigrams("東京都").contains(&String::from("京")));
+    assert_eq!(unigrams_and_bigrams("京"), vec!["京"]);
+    assert!(unigrams_and_bigrams("").is_empty());
+}
+
+#[test]
+fn split_scripts() {
+    assert_eq!(
+        split_by_script("rustで書かれたプログラム、東京"),
+        vec![
+            (Script::Other, "rust"),
+            (Script::Hiragana, "で"),
+            (Script::Han, "書"),
+            (Script::Hiragana, "かれた"),
+            (Script::Katakana, "プログラム"),
+            (Script::Han, "東京"),
+        ],
+    );
+}
@@ -12,6 +12,7 @@ rules
 4. If it fails due to a 한글 character, it doesn't do anything.
 */
 
+use crate::jamo::into_자모s;
 use crate::term_kind::{TermKind, get_term_kind};
 
 mod fsm;
@@ -30,3 +31,23 @@ pub fn tokenize(term: &str) -> Vec<String> {
         TermKind::Only한글(js) => vec![fsm::fsm(js)],
     }
 }
+
+/// It decomposes 한글 syllables into 자모s (e.g. "한글" -> "ㅎㅏㄴㄱㅡㄹ"), and
+/// leaves the other characters as they are. It's useful for comparing 한글 terms:
+/// "한그" is not a prefix of "한글", but its 자모s are.
+pub fn decompose(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() * 3);
+
+    for ch in s.chars() {
+        match ch {
+            '가'..='힣' => {
+                for 자모 in into_자모s(vec![ch]) {
+                    result.push(자모.into_char());
+                }
+            },
+            _ => { result.push(ch); },
+        }
+    }
+
+    result
+}
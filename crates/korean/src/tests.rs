@@ -1,4 +1,4 @@
-use crate::tokenize;
+use crate::{decompose, tokenize};
 
 #[test]
 fn tokenize_korean() {
@@ -42,3 +42,21 @@ fn tokenize_korean() {
         assert_eq!(tokenized, answer);
     }
 }
+
+#[test]
+fn decompose_korean() {
+    let sample = [
+        ("한글", "ㅎㅏㄴㄱㅡㄹ"),
+        ("가", "ㄱㅏ"),
+        ("값", "ㄱㅏㅄ"),
+        ("abc가", "abcㄱㅏ"),
+        ("ㄱ", "ㄱ"),
+        ("", ""),
+    ];
+
+    for (s, answer) in sample.into_iter() {
+        assert_eq!(decompose(s), answer);
+    }
+
+    assert!(decompose("한글").starts_with(&decompose("한그")));
+}
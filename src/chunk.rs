@@ -1,6 +1,6 @@
 use chrono::offset::Local;
 use crate::error::Error;
-use crate::index::{Index, tfidf};
+use crate::index::{Index, Tokenizer, tfidf};
 use crate::index::file::{AtomicToken, Image};
 use crate::uid::Uid;
 use flate2::Compression;
@@ -54,6 +54,21 @@ pub struct Chunk {
     pub summary: String,
 
     pub source: ChunkSource,
+
+    /// Heading path of the section that this chunk belongs to, like `# A > ## B`.
+    /// Markdown files with `structural_markdown_chunking`, source code files and pdf
+    /// files have this. In source code, it's the signatures of the enclosing items, like
+    /// `impl Index > pub fn build(&mut self)`. In a pdf file, it's the page where the
+    /// chunk starts, like `page 3`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+
+    /// Name of the item (function, class, ...) that this chunk belongs to, like
+    /// `Index::build`. Only source code files have this. It's searched as a part
+    /// of the title (see `TfidfField::Title`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+
     pub uid: Uid,
     pub build_info: ChunkBuildInfo,
     pub timestamp: i64,
@@ -96,7 +111,9 @@ pub fn save_to_file(
     compression_threshold: u64,
     compression_level: u32,
     root_dir: &str,
-    create_tfidf: bool,
+
+    // if it's set, it creates a tfidf file with this tokenizer
+    tokenizer: Option<&Tokenizer>,
 ) -> Result<(), Error> {
     let mut result = serde_json::to_vec_pretty(chunk)?;
     let tfidf_path = set_extension(path, "tfidf")?;
@@ -106,11 +123,12 @@ pub fn save_to_file(
         try_create_dir(&parent_path)?;
     }
 
-    if create_tfidf {
+    if let Some(tokenizer) = tokenizer {
         tfidf::save_to_file(
             &tfidf_path,
             chunk,
             root_dir,
+            tokenizer,
         )?;
     }
 
@@ -156,6 +174,8 @@ impl Chunk {
             build_info: ChunkBuildInfo::dummy(),
             data,
             source,
+            heading: None,
+            symbol: None,
         };
 
         result.uid = Uid::new_chunk(&result);
@@ -184,7 +204,7 @@ impl Chunk {
                     approx_data_len += 10;
                     chunk.push(format!("<|raw_media({}:{})|>", image_type.to_extension(), encode_base64(&bytes)));
                 },
-                AtomicToken::Separator => {
+                AtomicToken::Separator | AtomicToken::Heading { .. } => {
                     // invisible
                 },
 
@@ -225,6 +245,8 @@ impl Chunk {
         let mut images = vec![];
         let mut char_len = 0;
         let mut image_count = 0;
+        let mut heading = None;
+        let mut symbol = None;
 
         for r in tokens.iter() {
             match r {
@@ -240,6 +262,10 @@ impl Chunk {
                 AtomicToken::Separator => {
                     // invisible
                 },
+                AtomicToken::Heading { path, symbol: s } => {
+                    heading = Some(path.to_string());
+                    symbol = s.clone();
+                },
 
                 // If this branch is reached, that means `FileReader::generate_chunk` has
                 // failed to fetch the image from web.
@@ -281,6 +307,8 @@ impl Chunk {
             title: response.title,
             summary: response.summary,
             source: ChunkSource::File { path: normalize(&file)?, index: file_index },
+            heading,
+            symbol,
             searchable: true,
             uid: Uid::dummy(),
             build_info,
@@ -292,7 +320,10 @@ impl Chunk {
     }
 
     pub fn render_source(&self) -> String {
-        self.source.render()
+        match &self.heading {
+            Some(heading) => format!("{} ({heading})", self.source.render()),
+            None => self.source.render(),
+        }
     }
 
     pub(crate) fn get_approx_size(&self) -> usize {  // in bytes
@@ -301,17 +332,32 @@ impl Chunk {
 }
 
 pub fn merge_and_convert_chunks(index: &Index, chunks: Vec<Chunk>) -> Result<Vec<RenderableChunk>, Error> {
+    Ok(merge_and_convert_chunks_with_uids(index, chunks)?.into_iter().map(|(chunk, _)| chunk).collect())
+}
+
+/// A merged chunk doesn't have a uid, so each chunk comes with the uids of the original chunks.
+pub(crate) fn merge_and_convert_chunks_with_uids(index: &Index, chunks: Vec<Chunk>) -> Result<Vec<(RenderableChunk, Vec<Uid>)>, Error> {
+    let chunks = chunks.into_iter().map(
+        |chunk| {
+            let uid = chunk.uid;
+            (chunk, vec![uid])
+        }
+    ).collect();
+    merge_and_convert_chunks_worker(index, chunks)
+}
+
+fn merge_and_convert_chunks_worker(index: &Index, chunks: Vec<(Chunk, Vec<Uid>)>) -> Result<Vec<(RenderableChunk, Vec<Uid>)>, Error> {
     let mut merge_candidates = HashSet::new();
     let mut curr_chunks = HashMap::new();
 
-    for chunk in chunks.into_iter() {
+    for (chunk, uids) in chunks.into_iter() {
         match &chunk.source {
             ChunkSource::File { path, index } if *index > 0 => {
                 merge_candidates.insert((path.clone(), *index - 1));
-                curr_chunks.insert((path.clone(), *index), chunk);
+                curr_chunks.insert((path.clone(), *index), (chunk, uids));
             },
             ChunkSource::File { path, index } => {
-                curr_chunks.insert((path.clone(), *index), chunk);
+                curr_chunks.insert((path.clone(), *index), (chunk, uids));
             },
             ChunkSource::Chunks { .. } => {},  // it's unsearchable
         }
@@ -323,11 +369,12 @@ pub fn merge_and_convert_chunks(index: &Index, chunks: Vec<Chunk>) -> Result<Vec
 
     for candidate in merge_candidates.iter() {
         if curr_chunks.contains_key(candidate) {
-            let pre = curr_chunks.remove(candidate).unwrap();
-            let post = curr_chunks.remove(&(candidate.0.clone(), candidate.1 + 1)).unwrap();
-            curr_chunks.insert((candidate.0.clone(), candidate.1), merge_chunks(pre, post));
+            let (pre, mut pre_uids) = curr_chunks.remove(candidate).unwrap();
+            let (post, post_uids) = curr_chunks.remove(&(candidate.0.clone(), candidate.1 + 1)).unwrap();
+            pre_uids.extend(post_uids);
+            curr_chunks.insert((candidate.0.clone(), candidate.1), (merge_chunks(pre, post, index.build_config.slide_len), pre_uids));
 
-            return merge_and_convert_chunks(index, curr_chunks.into_values().collect());
+            return merge_and_convert_chunks_worker(index, curr_chunks.into_values().collect());
         }
     }
 
@@ -336,13 +383,13 @@ pub fn merge_and_convert_chunks(index: &Index, chunks: Vec<Chunk>) -> Result<Vec
     // 2. sort by file name: In order to run tests, the order has to be deterministic.
     let mut curr_chunks = curr_chunks.into_values().collect::<Vec<_>>();
     curr_chunks.sort_by_key(
-        |chunk| match &chunk.source {
+        |(chunk, _)| match &chunk.source {
             ChunkSource::File { index, .. } => *index,
             ChunkSource::Chunks { .. } => 0,  // unreachable
         }
     );
     curr_chunks.sort_by_key(
-        |chunk| match &chunk.source {
+        |(chunk, _)| match &chunk.source {
             ChunkSource::File { path, .. } => path.to_string(),
             ChunkSource::Chunks { .. } => String::new(),  // unreachable
         }
@@ -350,14 +397,14 @@ pub fn merge_and_convert_chunks(index: &Index, chunks: Vec<Chunk>) -> Result<Vec
 
     let mut result = Vec::with_capacity(curr_chunks.len());
 
-    for chunk in curr_chunks.into_iter() {
-        result.push(chunk.into_renderable(index)?);
+    for (chunk, uids) in curr_chunks.into_iter() {
+        result.push((chunk.into_renderable(index)?, uids));
     }
 
     Ok(result)
 }
 
-fn merge_chunks(pre: Chunk, post: Chunk) -> Chunk {
+fn merge_chunks(pre: Chunk, post: Chunk, slide_len: usize) -> Chunk {
     let ChunkSource::File { path: pre_path, index: pre_index } = pre.source.clone() else { unreachable!() };
     let ChunkSource::File { path: post_path, index: post_index } = post.source.clone() else { unreachable!() };
     assert_eq!(pre_path, post_path);
@@ -365,6 +412,8 @@ fn merge_chunks(pre: Chunk, post: Chunk) -> Chunk {
     let Chunk {
         data: data_pre,
         images: images_pre,
+        heading,
+        symbol,
         ..
     } = pre;
     let Chunk {
@@ -373,7 +422,7 @@ fn merge_chunks(pre: Chunk, post: Chunk) -> Chunk {
         ..
     } = post;
 
-    let new_data = merge_overlapping_strings(data_pre.as_bytes(), data_post.as_bytes());
+    let new_data = merge_overlapping_strings(data_pre.as_bytes(), data_post.as_bytes(), slide_len);
 
     // dedup
     let new_images = images_pre.into_iter().chain(images_post.into_iter()).collect::<HashSet<_>>().into_iter().collect::<Vec<_>>();
@@ -387,6 +436,8 @@ fn merge_chunks(pre: Chunk, post: Chunk) -> Chunk {
 
         // When 1st and 2nd chunks are merged, the result is 1st, not 2nd.
         source: ChunkSource::File { path: pre_path, index: pre_index },
+        heading,
+        symbol,
 
         // If source is `File`, it must be searchable
         searchable: true,
@@ -399,14 +450,23 @@ fn merge_chunks(pre: Chunk, post: Chunk) -> Chunk {
     }
 }
 
-fn merge_overlapping_strings(s1: &[u8], s2: &[u8]) -> String {
+// Adjacent chunks of a file share a sliding window: the beginning of `s2` is the end
+// of `s1`. `FileReader::next_chunk` stops growing the window as soon as it reaches
+// `slide_len`, so the window is the shortest overlap that has at least `slide_len`
+// characters. If the text repeats itself, there can be a longer overlap, and removing
+// that would remove the text. If there's no such overlap (e.g. the window has images,
+// or there's no window), it removes the longest overlap.
+fn merge_overlapping_strings(s1: &[u8], s2: &[u8], slide_len: usize) -> String {
     let min_len = s1.len().min(s2.len());
     let mut index = 0;
 
-    for i in (0..=min_len).rev() {
+    for i in 0..=min_len {
         if s1.ends_with(&s2[..i]) {
             index = i;
-            break;
+
+            if String::from_utf8_lossy(&s2[..i]).chars().count() >= slide_len {
+                break;
+            }
         }
     }
 
@@ -1,6 +1,7 @@
 use crate::chunk::{Chunk, ChunkSource, RenderableChunk};
 use crate::index::Index;
-use super::merge_and_convert_chunks;
+use crate::uid::Uid;
+use super::{merge_and_convert_chunks, merge_and_convert_chunks_with_uids, merge_overlapping_strings};
 
 #[test]
 fn test_merge_and_convert_chunks() {
@@ -51,3 +52,70 @@ fn test_merge_and_convert_chunks() {
         assert_eq!(result, answer);
     }
 }
+
+#[test]
+fn test_merge_sliding_window() {
+    // adjacent chunks share a sliding window, which appears only once in the merged chunk
+    let samples = vec![
+        (vec![("abcde", 0, 0), ("defgh", 0, 1)], vec![("abcdefgh", 0, 0)]),
+        (vec![("abcde", 0, 0), ("defgh", 0, 1), ("ghijk", 0, 2)], vec![("abcdefghijk", 0, 0)]),
+        (vec![("가나다라", 0, 0), ("다라마바", 0, 1)], vec![("가나다라마바", 0, 0)]),
+    ];
+    let index = Index::dummy();
+
+    for (sample, answer) in samples.into_iter() {
+        let sample = sample.into_iter().map(
+            |(content, file, index)| Chunk::dummy(content.to_string(), ChunkSource::File { path: file.to_string(), index })
+        ).collect::<Vec<_>>();
+        let result = merge_and_convert_chunks(&index, sample).unwrap();
+        let answer = answer.into_iter().map(
+            |(data, file, index)| RenderableChunk { data: data.to_string(), source: ChunkSource::File { path: file.to_string(), index }.render() }
+        ).collect::<Vec<_>>();
+
+        assert_eq!(result, answer);
+    }
+}
+
+#[test]
+fn test_merge_overlapping_strings() {
+    let samples = vec![
+        ("abc", "def", 3, "abcdef"),
+        ("abcde", "defgh", 3, "abcdefgh"),
+
+        // there's no overlap that is long enough, so it removes the longest one
+        ("abcde", "defgh", 100, "abcdefgh"),
+
+        // the window is the shortest overlap that is long enough
+        ("x. a a a a", "a a a a b", 3, "x. a a a a a a b"),
+        ("x. a a a a", "a a a a b", 5, "x. a a a a a b"),
+        ("x. a a a a", "a a a a b", 7, "x. a a a a b"),
+        ("가나다라", "다라마바", 2, "가나다라마바"),
+    ];
+
+    for (s1, s2, slide_len, answer) in samples.into_iter() {
+        assert_eq!(merge_overlapping_strings(s1.as_bytes(), s2.as_bytes(), slide_len), answer);
+    }
+}
+
+#[test]
+fn test_merge_and_convert_chunks_with_uids() {
+    let chunks = vec![
+        Chunk::dummy(String::from("abc"), ChunkSource::File { path: String::from("0"), index: 1 }),
+        Chunk::dummy(String::from("def"), ChunkSource::File { path: String::from("0"), index: 2 }),
+        Chunk::dummy(String::from("ghi"), ChunkSource::File { path: String::from("1"), index: 0 }),
+    ];
+    let uids = chunks.iter().map(|chunk| chunk.uid).collect::<Vec<_>>();
+    let index = Index::dummy();
+    let result = merge_and_convert_chunks_with_uids(&index, chunks).unwrap().into_iter().map(
+        |(chunk, uids)| (chunk.data, uids)
+    ).collect::<Vec<(String, Vec<Uid>)>>();
+
+    // a merged chunk remembers the uids of the original chunks
+    assert_eq!(
+        result,
+        vec![
+            (String::from("abcdef"), vec![uids[0], uids[1]]),
+            (String::from("ghi"), vec![uids[2]]),
+        ],
+    );
+}
@@ -3,7 +3,10 @@ pub const ARCHIVE_DIR_NAME: &str = "archives";
 pub const BUILD_CONFIG_FILE_NAME: &str = "build.json";
 pub const CHUNK_DIR_NAME: &str = "chunks";
 pub const CONFIG_DIR_NAME: &str = "configs";
+pub const CORPUS_STATS_FILE_NAME: &str = "corpus_stats.json";
+pub const EMBEDDING_STORE_FILE_NAME: &str = "embeddings";
 pub const FILE_INDEX_DIR_NAME: &str = "files";
+pub const II_CHUNK_TABLE_FILE_NAME: &str = "chunk_table";
 pub const II_DIR_NAME: &str = "ii";
 pub const IMAGE_DIR_NAME: &str = "images";
 pub const INDEX_DIR_NAME: &str = ".ragit";
@@ -13,3 +16,4 @@ pub const METADATA_FILE_NAME: &str = "meta.json";
 pub const MODEL_FILE_NAME: &str = "models.json";
 pub const PROMPT_DIR_NAME: &str = "prompts";
 pub const QUERY_CONFIG_FILE_NAME: &str = "query.json";
+pub const SESSION_DIR_NAME: &str = "sessions";
@@ -14,21 +14,33 @@ pub enum Error {
     },
     IndexAlreadyExists(Path),
     InvalidConfigKey(String),
+    InvalidConfigValue {
+        key: String,
+        message: String,
+    },
+    InvalidStemmer(String),
     InvalidImageType(String),
+    ModelCannotReadImages(String),
     InvalidUid(String),
     PromptMissing(String),
     IndexNotFound,
     NoSuchChunk(Uid),
     NoSuchFile { path: Option<Path>, uid: Option<Uid> },
     NoSuchMeta(String),
+    NoSuchSession(String),
+    InvalidSessionName(String),
     CorruptedFile { path: Path, message: Option<String> },
     CliError {
         message: String,
         span: (String, usize, usize),  // (args, error_from, error_to)
     },
     UidQueryError(String),
+    BooleanQueryError(String),
     BrokenHash(String),
     BrokenPrompt(String),
+
+    /// The model's response doesn't match the schema, even after retries.
+    CannotValidateResponse(String),  // schema
     BrokenArchive(String),
     CloneRequestError {
         code: Option<u16>,
@@ -47,6 +59,7 @@ pub enum Error {
     CannotExtractArchive(String),
     CannotClone(String),
     CannotPush(String),
+    CannotWatch(String),
     CannotBuild(Vec<(String, String)>),  // Vec<(file_name, error_message)>
     CannotUpdateII(IIStatus),
     CannotAddFile {
@@ -83,6 +96,9 @@ pub enum Error {
     /// see <https://docs.rs/url/latest/url/enum.ParseError.html>
     UrlParseError(url::ParseError),
 
+    /// see <https://docs.rs/lopdf/latest/lopdf/enum.Error.html>
+    PdfError(lopdf::Error),
+
     FileError(FileError),
     StdIoError(std::io::Error),
     Utf8Error(FromUtf8Error),
@@ -142,6 +158,12 @@ impl From<url::ParseError> for Error {
     }
 }
 
+impl From<lopdf::Error> for Error {
+    fn from(e: lopdf::Error) -> Error {
+        Error::PdfError(e)
+    }
+}
+
 impl From<ApiError> for Error {
     fn from(e: ApiError) -> Self {
         match e {
@@ -17,7 +17,15 @@ use crate::constant::{
 };
 use crate::error::Error;
 use crate::prompts::PROMPTS;
-use crate::query::{Keywords, QueryConfig};
+use crate::query::{
+    EmbeddingMode,
+    Keywords,
+    QueryConfig,
+    RetrievalCandidate,
+    RetrievalExplanation,
+    RetrievalFilter,
+    RetrievalStage,
+};
 use crate::uid::{self, Uid, UidWriteMode};
 use ragit_api::{
     Model,
@@ -58,6 +66,8 @@ use std::collections::{HashMap, HashSet};
 mod auth;
 mod commands;
 mod config;
+mod corpus;
+mod embedding;
 pub mod file;
 mod ii;
 pub mod tfidf;
@@ -69,13 +79,29 @@ pub use commands::{
     MergeResult,
     RecoverResult,
     RemoveResult,
+    Session,
+    Status,
     VersionInfo,
     get_compatibility_warning,
 };
 pub use config::BuildConfig;
-pub use file::{FileReader, ImageDescription};
-pub use ii::IIStatus;
-pub use tfidf::{ProcessedDoc, TfidfResult, TfidfState, consume_processed_doc};
+pub use corpus::CorpusStats;
+use corpus::CorpusStatsCache;
+use embedding::EmbeddingStoreCache;
+pub use file::{FileReader, ImageDescription, normalize_image};
+pub use ii::{IIFormat, IIStatus};
+use ii::ChunkTableCache;
+pub use tfidf::{
+    FieldFrequency,
+    ProcessedDoc,
+    STEMMERS,
+    TfidfField,
+    TfidfResult,
+    TfidfState,
+    Tokenizer,
+    TokenizerConfig,
+    consume_processed_doc,
+};
 
 pub type Path = String;
 
@@ -100,6 +126,24 @@ pub struct Index {
     /// `ii` stands for `inverted-index`.
     pub ii_status: IIStatus,
 
+    /// Whether the ii has positional postings. See `QueryConfig::ii_positions`.
+    #[serde(default)]
+    pub ii_positions: bool,
+
+    /// Format of the ii files. See `IIFormat`.
+    #[serde(default)]
+    pub ii_format: IIFormat,
+
+    /// How the tfidf files are tokenized. It's copied from `BuildConfig` when the
+    /// tfidf files are (re)built, and queries are tokenized the same way.
+    #[serde(default = "TokenizerConfig::legacy")]
+    pub tokenizer_config: TokenizerConfig,
+
+    /// Whether the tfidf files have the term frequency of each field (see `TfidfField`).
+    /// If they're built by an older version of ragit, `rag migrate` rebuilds them.
+    #[serde(default)]
+    pub tfidf_fields: bool,
+
     // it's not used by code, but used by serde
     // users modify json file, which is deserialized to `ApiConfigRaw`,
     // which is then converted to `ApiConfig` by `.init_api_config()`
@@ -118,6 +162,12 @@ pub struct Index {
     pub prompts: HashMap<String, String>,
     #[serde(skip)]
     pub models: Vec<Model>,
+    #[serde(skip)]
+    corpus_stats: CorpusStatsCache,
+    #[serde(skip)]
+    embedding_store: EmbeddingStoreCache,
+    #[serde(skip)]
+    chunk_table: ChunkTableCache,
 }
 
 /// 1. If you want to do something with chunks, use `LoadMode::QuickCheck`.
@@ -150,7 +200,14 @@ impl Index {
             curr_processing_file: None,
             repo_url: None,
             ii_status: IIStatus::None,
+            ii_positions: false,
+            ii_format: IIFormat::Compact,
+            tokenizer_config: TokenizerConfig::default(),
+            tfidf_fields: true,
             api_config_raw: ApiConfigRaw::default(),
+            corpus_stats: CorpusStatsCache::default(),
+            embedding_store: EmbeddingStoreCache::default(),
+            chunk_table: ChunkTableCache::default(),
             root_dir: String::from("."),
             build_config: BuildConfig::default(),
             query_config: QueryConfig::default(),
@@ -206,8 +263,15 @@ impl Index {
             root_dir: root_dir.clone(),
             repo_url: None,
             ii_status: IIStatus::None,
+            ii_positions: false,
+            ii_format: IIFormat::Compact,
+            tokenizer_config: build_config.tokenizer_config(),
+            tfidf_fields: true,
             prompts: PROMPTS.clone(),
             models: vec![],
+            corpus_stats: CorpusStatsCache::default(),
+            embedding_store: EmbeddingStoreCache::default(),
+            chunk_table: ChunkTableCache::default(),
         };
         
         // Try to load build config from home directory and apply to defaults
@@ -222,6 +286,7 @@ impl Index {
             partial_query_config.apply_to(&mut query_config);
         }
         
+        let tokenizer_config = build_config.tokenizer_config();
         let mut result = Index {
             ragit_version: crate::VERSION.to_string(),
             chunk_count: 0,
@@ -235,8 +300,15 @@ impl Index {
             root_dir,
             repo_url: None,
             ii_status: IIStatus::None,
+            ii_positions: false,
+            ii_format: IIFormat::Compact,
+            tokenizer_config,
+            tfidf_fields: true,
             prompts: PROMPTS.clone(),
             models: vec![],
+            corpus_stats: CorpusStatsCache::default(),
+            embedding_store: EmbeddingStoreCache::default(),
+            chunk_table: ChunkTableCache::default(),
         };
 
         // Load models first so we can choose an appropriate default model
@@ -259,6 +331,7 @@ impl Index {
             &serde_json::to_vec_pretty(&result.api_config_raw)?,
             WriteMode::AlwaysCreate,
         )?;
+        result.save_corpus_stats(&CorpusStats::default())?;
         result.save_to_file()?;
 
         Ok(result)
@@ -365,6 +438,11 @@ impl Index {
     pub fn save_to_file(&self) -> Result<(), Error> {
         self.save_prompts()?;
 
+        // While a build is running, the corpus stats are written at the end of the build.
+        if self.curr_processing_file.is_none() {
+            self.flush_corpus_stats()?;
+        }
+
         Ok(write_bytes(
             &Index::get_rag_path(
                 &self.root_dir,
@@ -375,24 +453,101 @@ impl Index {
         )?)
     }
 
+    /// If `explanation` is given, it records the candidates (and their scores) of this stage.
     pub(crate) async fn load_chunks_or_tfidf(
         &self,
         query: &str,
+        filter: &RetrievalFilter,
+        mut explanation: Option<&mut RetrievalExplanation>,
     ) -> Result<Vec<Chunk>, Error> {
+        let allowed = self.resolve_retrieval_filter(filter)?;
+
+        if let (Some(explanation), Some(allowed)) = (&mut explanation, &allowed) {
+            explanation.filtered_chunks = Some(allowed.len());
+        }
+
+        // If there're only a few chunks that pass the filter, it doesn't have to run tfidf.
+        if let Some(allowed) = &allowed {
+            if allowed.len() <= self.query_config.max_titles {
+                let mut uids = allowed.iter().copied().collect::<Vec<_>>();
+
+                // the result has to be deterministic
+                uids.sort();
+                let mut chunks = Vec::with_capacity(uids.len());
+
+                for uid in uids.into_iter() {
+                    chunks.push(self.get_chunk_by_uid(uid)?);
+                }
+
+                if let Some(explanation) = explanation {
+                    explanation.stages.push(RetrievalStage::new("filter", &chunks));
+                }
+
+                return Ok(chunks);
+            }
+        }
+
         if self.chunk_count > self.query_config.max_titles {
-            let keywords = self.extract_keywords(query).await?;
-            let tfidf_results = self.run_tfidf(
-                keywords,
-                self.query_config.max_summaries,
-            )?;
-            let mut chunks = Vec::with_capacity(tfidf_results.len());
+            // It extracts keywords in advance, so that the explanation has the same keywords as the hybrid search.
+            let keywords = match &explanation {
+                Some(_) => Some(self.extract_keywords(query).await?),
+                None => None,
+            };
+            // If the results are diversified, it searches more candidates and selects `max_summaries` of them.
+            let diversify = self.query_config.mmr_lambda.0 < 1.0 || self.query_config.max_chunks_per_file > 0;
+            let limit = if diversify {
+                self.query_config.max_summaries * self.query_config.mmr_pool_coeff.max(1)
+            } else {
+                self.query_config.max_summaries
+            };
+            let uids = self.run_hybrid_search_worker(
+                query,
+                keywords.clone(),
+                limit,
+                allowed.as_ref(),
+            ).await?;
+            let mut chunks = Vec::with_capacity(uids.len());
+
+            for (uid, score) in uids.iter() {
+                chunks.push((self.get_chunk_by_uid(*uid)?, *score));
+            }
 
-            for tfidf_result in tfidf_results.into_iter() {
-                let uid = tfidf_result.id;
-                chunks.push(self.get_chunk_by_uid(uid)?);
+            if let (Some(explanation), Some(keywords)) = (&mut explanation, keywords) {
+                let tfidf_results = if self.query_config.embedding_mode != EmbeddingMode::Only && self.query_config.tfidf_weight > 0 {
+                    self.run_tfidf_worker(keywords.clone(), limit, allowed.as_ref())?
+                } else {
+                    vec![]
+                };
+
+                explanation.keywords = Some(keywords);
+                explanation.stages.push(RetrievalStage {
+                    name: String::from("hybrid search"),
+                    picked: None,
+                    candidates: chunks.iter().map(
+                        |(chunk, score)| RetrievalCandidate::new(
+                            chunk,
+                            Some(*score),
+                            tfidf_results.iter().find(|result| result.id == chunk.uid),
+                        )
+                    ).collect(),
+                });
             }
 
-            Ok(chunks)
+            if diversify {
+                chunks = self.select_diverse_chunks(chunks, self.query_config.max_summaries)?;
+
+                if let Some(explanation) = explanation {
+                    explanation.stages.push(RetrievalStage {
+                        name: String::from("diversity"),
+                        picked: None,
+                        candidates: chunks.iter().map(
+                            |(chunk, score)| RetrievalCandidate::new(chunk, Some(*score), None)
+                        ).collect(),
+                    });
+                }
+            }
+
+            Ok(chunks.into_iter().map(|(chunk, _)| chunk).collect())
         }
 
         else {
@@ -402,6 +557,10 @@ impl Index {
                 chunks.push(chunk::load_from_file(&chunk_path)?);
             }
 
+            if let Some(explanation) = explanation {
+                explanation.stages.push(RetrievalStage::new("all chunks", &chunks));
+            }
+
             Ok(chunks)
         }
     }
@@ -504,7 +663,6 @@ impl Index {
         }
 
         let image_bytes = read_bytes(&image_path)?;
-        let image_bytes = encode_base64(&image_bytes);
 
         if let Ok(j) = read_string(&description_path) {
             if serde_json::from_str::<Value>(&j).is_ok() {
@@ -516,6 +674,21 @@ impl Index {
             }
         }
 
+        let result = self.describe_image(&image_bytes).await?;
+
+        write_bytes(
+            &description_path,
+            &serde_json::to_vec_pretty(&result)?,
+            WriteMode::Atomic,
+        )?;
+
+        Ok(())
+    }
+
+    /// It asks the model to describe an image, using `prompts/describe_image.pdl`.
+    /// `image_bytes` must be a png file. Use `normalize_image` if it's not.
+    pub async fn describe_image(&self, image_bytes: &[u8]) -> Result<ImageDescription, Error> {
+        let image_bytes = encode_base64(image_bytes);
         let mut context = tera::Context::new();
         context.insert("image_type", "png");
         context.insert("image_bytes", &image_bytes);
@@ -523,7 +696,7 @@ impl Index {
         let Pdl { messages, schema } = parse_pdl(
             &pdl,
             &context,
-            "/",  // TODO: `<|media|>` is not supported for this prompt
+            "/",  // the image is given with `<|raw_media|>`, so it doesn't need `curr_dir`
             true,
             true,
         )?;
@@ -545,15 +718,8 @@ impl Index {
             schema,
             schema_max_try: 3,
         };
-        let result = request.send_and_validate::<ImageDescription>(ImageDescription::default()).await?;
 
-        write_bytes(
-            &description_path,
-            &serde_json::to_vec_pretty(&result)?,
-            WriteMode::Atomic,
-        )?;
-
-        Ok(())
+        Ok(request.send_and_validate::<ImageDescription>(ImageDescription::default()).await?)
     }
 
     pub fn run_tfidf(
@@ -561,15 +727,56 @@ impl Index {
         keywords: Keywords,
         limit: usize,
     ) -> Result<Vec<TfidfResult<Uid>>, Error> {
-        let mut tfidf_state = TfidfState::new(&keywords);
+        self.run_tfidf_worker(keywords, limit, None)
+    }
+
+    /// If `allowed` is set, the other chunks are not scored at all.
+    pub(crate) fn run_tfidf_worker(
+        &self,
+        keywords: Keywords,
+        limit: usize,
+        allowed: Option<&HashSet<Uid>>,
+    ) -> Result<Vec<TfidfResult<Uid>>, Error> {
+        let tokenizer = self.tokenizer();
+        let mut tfidf_state = TfidfState::new(&keywords, &tokenizer);
+        tfidf_state.set_bm25_params(self.query_config.bm25_k1.0, self.query_config.bm25_b.0);
+        tfidf_state.set_field_weights(self.query_config.field_weights());
+
+        // Expanded terms have to be added before `set_corpus_stats` and `get_search_candidates_worker`.
+        if self.query_config.fuzzy_search {
+            let original_terms = tfidf_state.terms.clone();
+
+            for (term, expanded_terms) in self.expand_terms(&original_terms)? {
+                let weight = original_terms[&term];
+
+                for expanded in expanded_terms.iter() {
+                    if original_terms.contains_key(&expanded.term) {
+                        continue;
+                    }
+
+                    let expanded_weight = weight * expanded.weight;
+
+                    // If a term is expanded from multiple terms, it takes the highest weight.
+                    match tfidf_state.terms.get_mut(&expanded.term) {
+                        Some(w) => { *w = w.max(expanded_weight); },
+                        None => { tfidf_state.terms.insert(expanded.term.clone(), expanded_weight); },
+                    }
+                }
+            }
+        }
 
-        // TODO: it must be configurable, or at least do much more experiment on this
-        let ii_coeff = 20;
+        if let Some(corpus_stats) = self.get_corpus_stats()? {
+            tfidf_state.set_corpus_stats(&corpus_stats);
+        }
+
+        let phrase_matches = self.match_phrases(&keywords.phrases(&tokenizer))?;
 
         if self.query_config.enable_ii && self.is_ii_built() {
-            for chunk_uid in self.get_search_candidates(
+            for chunk_uid in self.get_search_candidates_worker(
                 &tfidf_state.terms,
-                limit * ii_coeff,
+                phrase_matches.as_ref(),
+                allowed,
+                limit * self.query_config.ii_coeff,
             )? {
                 let processed_doc = self.get_tfidf_by_chunk_uid(chunk_uid)?;
                 consume_processed_doc(
@@ -582,6 +789,19 @@ impl Index {
         else {
             for tfidf_file in self.get_all_tfidf_files()? {
                 let processed_doc = tfidf::load_from_file(&tfidf_file)?;
+
+                if let (Some(phrase_matches), Some(uid)) = (&phrase_matches, &processed_doc.uid) {
+                    if !phrase_matches.is_allowed(uid) {
+                        continue;
+                    }
+                }
+
+                if let (Some(allowed), Some(uid)) = (allowed, &processed_doc.uid) {
+                    if !allowed.contains(uid) {
+                        continue;
+                    }
+                }
+
                 consume_processed_doc(
                     processed_doc,
                     &mut tfidf_state,
@@ -589,6 +809,10 @@ impl Index {
             }
         }
 
+        if let Some(phrase_matches) = phrase_matches {
+            tfidf_state.set_proximity(phrase_matches.proximity, self.query_config.proximity_boost.0);
+        }
+
         Ok(tfidf_state.get_top(limit))
     }
 
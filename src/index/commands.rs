@@ -15,9 +15,14 @@ mod migrate;
 mod push;
 mod recover;
 mod remove;
+mod session;
+mod status;
+mod watch;
 
 pub use add::{AddMode, AddResult};
 pub use merge::{MergeMode, MergeResult};
 pub use migrate::{VersionInfo, get_compatibility_warning};
 pub use recover::RecoverResult;
 pub use remove::RemoveResult;
+pub use session::Session;
+pub use status::Status;
@@ -24,13 +24,22 @@ impl AddMode {
 
 #[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct AddResult {
-    staged: usize,
-    ignored: usize,
+    pub staged: usize,
+    pub ignored: usize,
+
+    /// `rag add --update` removes deleted files from the knowledge-base.
+    pub removed: usize,
 }
 
 impl fmt::Display for AddResult {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(fmt, "{} files staged, {} files ignored", self.staged, self.ignored)
+        write!(fmt, "{} files staged, {} files ignored", self.staged, self.ignored)?;
+
+        if self.removed > 0 {
+            write!(fmt, ", {} files removed", self.removed)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -197,6 +206,53 @@ impl Index {
         Ok(result)
     }
 
+    /// rag add --update
+    ///
+    /// It's like `git add --update`. It stages processed files that are modified, and
+    /// removes staged or processed files that are deleted from the file system. It
+    /// doesn't look at new files, and it doesn't care whether the files are ignored or not.
+    pub fn add_update(&mut self, dry_run: bool) -> Result<AddResult, Error> {
+        if let Some(file) = &self.curr_processing_file {
+            return Err(Error::CannotAddFile {
+                file: file.to_string(),
+                message: format!("A build process has been interrupted while processing `{file}`. Please clean it up."),
+            });
+        }
+
+        let (modified, deleted) = self.get_changed_files()?;
+        let mut deleted_staged_files = vec![];
+
+        for file in self.staged_files.iter() {
+            if !exists(&Index::get_data_path(&self.root_dir, file)?) && !deleted.contains(file) {
+                deleted_staged_files.push(file.to_string());
+            }
+        }
+
+        let result = AddResult {
+            staged: modified.len(),
+            ignored: 0,
+            removed: deleted.len() + deleted_staged_files.len(),
+        };
+
+        if !dry_run {
+            for file in deleted.iter().chain(deleted_staged_files.iter()) {
+                self.remove_file(
+                    Index::get_data_path(&self.root_dir, file)?,
+                    false,  // dry_run
+                    false,  // recursive
+                    false,  // auto
+                    true,   // staged
+                    true,   // processed
+                )?;
+            }
+
+            self.staged_files.extend(modified);
+            self.save_to_file()?;
+        }
+
+        Ok(result)
+    }
+
     pub fn read_ignore_file(&self) -> Result<Ignore, Error> {
         let mut ignore_file_at = String::new();
 
@@ -1,7 +1,7 @@
 use super::{BlockType, compress};
 use crate::constant::{INDEX_DIR_NAME, INDEX_FILE_NAME};
 use crate::error::Error;
-use crate::index::{ii::IIStatus, Index, LoadMode};
+use crate::index::{ii::{IIFormat, IIStatus}, Index, LoadMode};
 use crate::uid::{self, Uid};
 use ragit_fs::{
     FileError,
@@ -452,6 +452,8 @@ fn event_loop(
 
                         // archive does not include ii
                         index.ii_status = IIStatus::None;
+                        index.ii_positions = false;
+                        index.ii_format = IIFormat::Compact;
 
                         let index_json = serde_json::to_vec(&index)?;
                         compress(&index_json, compression_level)?
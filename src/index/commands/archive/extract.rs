@@ -348,7 +348,7 @@ fn event_loop(
                                 0,
                                 3,
                                 &root_dir,
-                                false,  // create tfidf
+                                None,  // don't create tfidf
                             )?;
                         }
                     },
@@ -5,7 +5,6 @@ use crate::error::Error;
 use crate::index::{
     ChunkBuildInfo,
     FileReader,
-    IIStatus,
     LoadMode,
 };
 use crate::uid::Uid;
@@ -22,8 +21,37 @@ use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+/// How `Index::build` reports its progress.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum BuildOutput {
+    Quiet,
+
+    /// It clears the screen and renders a dashboard on every tick.
+    Dashboard,
+
+    /// It prints a line when a file is processed. `rag build --watch` uses this, so
+    /// that the previous builds' logs are not cleared.
+    Log,
+}
+
 impl Index {
     pub async fn build(&mut self, workers: usize, quiet: bool) -> Result<(), Error> {
+        self.build_with_output(
+            workers,
+            if quiet { BuildOutput::Quiet } else { BuildOutput::Dashboard },
+        ).await
+    }
+
+    pub(crate) async fn build_with_output(&mut self, workers: usize, output: BuildOutput) -> Result<(), Error> {
+        // The workers load `index.json`, so it has to be done before the workers are initialized.
+        if self.is_tfidf_outdated() {
+            let count = self.rebuild_tfidf_files()?;
+
+            if output != BuildOutput::Quiet {
+                println!("tokenizer config has changed: rebuilt {count} tfidf files");
+            }
+        }
+
         let mut remaining_chunks = 0;
         let started_at = Instant::now();
         let mut errors = vec![];
@@ -31,7 +59,7 @@ impl Index {
         for (index, file) in self.staged_files.iter().enumerate() {
             let elapsed_time = Instant::now().duration_since(started_at).as_secs();
 
-            if !quiet {
+            if output == BuildOutput::Dashboard {
                 clearscreen::clear().expect("failed to clear screen");
                 println!("elapsed time: {:02}:{:02}", elapsed_time / 60, elapsed_time % 60);
                 println!("counting chunks... {index}/{}", self.staged_files.len());
@@ -71,7 +99,7 @@ impl Index {
 
         let mut workers = init_workers(workers, self.root_dir.clone());
 
-        match self.build_worker(&mut workers, remaining_chunks, started_at, quiet) {
+        match self.build_worker(&mut workers, remaining_chunks, started_at, output) {
             Ok(()) => Ok(()),
             Err(e) => {
                 for worker in workers.iter_mut() {
@@ -88,7 +116,7 @@ impl Index {
         workers: &mut Vec<Channel>,
         mut remaining_chunks: usize,
         started_at: Instant,
-        quiet: bool,
+        output: BuildOutput,
     ) -> Result<(), Error> {
         let mut killed_workers = vec![];
         let mut staged_files = self.staged_files.clone();
@@ -123,7 +151,7 @@ impl Index {
         self.save_to_file()?;
 
         loop {
-            if !quiet {
+            if output == BuildOutput::Dashboard {
                 self.render_build_dashboard(
                     &buffer,
                     &completed_files,
@@ -217,7 +245,8 @@ impl Index {
                 ).map(
                     |staged_file| staged_file.to_string()
                 ).collect();
-                let mut ii_buffer = HashMap::new();
+                let mut added_chunks = vec![];
+                let mut added_docs = vec![];
 
                 for file in completed_files.iter() {
                     let real_path = Index::get_data_path(
@@ -245,26 +274,20 @@ impl Index {
                     self.add_file_index(file_uid, &chunk_uids)?;
                     self.processed_files.insert(file.to_string(), file_uid);
 
-                    match self.ii_status {
-                        IIStatus::Complete => {
-                            for chunk_uid in chunk_uids.iter() {
-                                self.update_ii_buffer(&mut ii_buffer, *chunk_uid)?;
-                            }
-                        },
-                        IIStatus::Ongoing(_)
-                        | IIStatus::Outdated => {
-                            self.ii_status = IIStatus::Outdated;
-                        },
-                        IIStatus::None => {},
+                    if output == BuildOutput::Log {
+                        println!("processed `{file}`: {} chunks", chunk_uids.len());
                     }
 
-                    buffer.remove(file);
-                }
+                    for chunk_uid in chunk_uids.iter() {
+                        added_docs.push(self.get_tfidf_by_chunk_uid(*chunk_uid)?);
+                    }
 
-                if let IIStatus::Complete = self.ii_status {
-                    self.flush_ii_buffer(ii_buffer)?;
+                    added_chunks.extend(chunk_uids);
+                    buffer.remove(file);
                 }
 
+                self.update_ii(&added_chunks, &[])?;
+                self.update_corpus_stats(&added_docs, &[])?;
                 self.chunk_count += buffered_chunk_count;
                 self.save_to_file()?;
 
@@ -273,7 +296,7 @@ impl Index {
                 flush_count += 1;
 
                 if killed_workers.len() == workers.len() {
-                    if !quiet {
+                    if output == BuildOutput::Dashboard {
                         self.render_build_dashboard(
                             &buffer,
                             &completed_files,
@@ -292,6 +315,12 @@ impl Index {
 
         self.curr_processing_file = None;
         self.save_to_file()?;
+
+        // knowledge-bases created by older versions of ragit don't have corpus stats
+        if self.get_corpus_stats()?.is_none() {
+            self.rebuild_corpus_stats()?;
+        }
+
         Ok(())
     }
 
@@ -373,6 +402,7 @@ async fn event_loop(
         root_dir,
         LoadMode::OnlyJson,
     )?;
+    let tokenizer = index.tokenizer();
     let prompt = index.get_prompt("summarize")?;
     let mut hasher = Sha3_256::new();
     hasher.update(prompt.as_bytes());
@@ -442,7 +472,7 @@ async fn event_loop(
                         index.build_config.compression_threshold,
                         index.build_config.compression_level,
                         &index.root_dir,
-                        true,  // create tfidf
+                        Some(&tokenizer),  // create tfidf
                     )?;
                     tx_to_main.send(Response::ChunkComplete {
                         file: file.clone(),
@@ -1,6 +1,7 @@
 use super::{BuildConfig, Index};
 use crate::{ApiConfigRaw, QueryConfig};
 use crate::error::Error;
+use crate::index::IIStatus;
 use ragit_api::JsonType;
 use ragit_fs::{WriteMode, read_string, write_bytes, write_string};
 use serde_json::Value;
@@ -97,6 +98,8 @@ impl Index {
     }
 
     fn set_config_by_key_worker(&mut self, key: String, value: String) -> Result<Option<String>, Error> {  // returns the previous value, if exists
+        // `self.build_config` is not reloaded until the end of this function, even if it calls itself.
+        let previous_tokenizer_config = self.build_config.tokenizer_config();
         let mut updated = false;
         let mut previous_value = None;
 
@@ -143,6 +146,30 @@ impl Index {
         }
 
         if !updated {
+            // Knowledge-bases created by older versions of ragit may not have
+            // newer config keys in their json files. If the key is valid, it
+            // adds the key with its default value and tries again.
+            for (path, default) in [
+                (self.get_build_config_path()?, serde_json::to_value(BuildConfig::default())?),
+                (self.get_api_config_path()?, serde_json::to_value(ApiConfigRaw::default())?),
+                (self.get_query_config_path()?, serde_json::to_value(QueryConfig::default())?),
+            ] {
+                if let Some(default_value) = default.get(&key) {
+                    let j = read_string(&path)?;
+                    let mut j = serde_json::from_str::<Value>(&j)?;
+
+                    if let Value::Object(ref mut obj) = &mut j {
+                        obj.insert(key.clone(), default_value.clone());
+                        write_bytes(
+                            &path,
+                            &serde_json::to_vec_pretty(&j)?,
+                            WriteMode::CreateOrTruncate,
+                        )?;
+                        return self.set_config_by_key_worker(key, value);
+                    }
+                }
+            }
+
             return Err(Error::InvalidConfigKey(key));
         }
 
@@ -156,6 +183,17 @@ impl Index {
             &read_string(&self.get_api_config_path()?)?,
         )?;
         self.api_config = self.init_api_config(&self.api_config_raw)?;
+        self.query_config.check_field_weights()?;
+
+        // The tfidf files are rebuilt by the next `rag build`, and the ii has to be rebuilt after that.
+        if self.build_config.tokenizer_config() != previous_tokenizer_config {
+            self.build_config.tokenizer_config().get_stemmer()?;
+
+            if self.ii_status != IIStatus::None && self.ii_status != IIStatus::Outdated {
+                self.ii_status = IIStatus::Outdated;
+                self.save_to_file()?;
+            }
+        }
 
         Ok(previous_value)
     }
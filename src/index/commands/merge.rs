@@ -2,7 +2,7 @@ use super::Index;
 use chrono::offset::Local;
 use crate::chunk;
 use crate::error::Error;
-use crate::index::{CHUNK_DIR_NAME, IIStatus, IMAGE_DIR_NAME, LoadMode};
+use crate::index::{CHUNK_DIR_NAME, IMAGE_DIR_NAME, LoadMode, embedding};
 use crate::uid::{Uid, UidType};
 use ragit_fs::{
     copy_file,
@@ -68,7 +68,10 @@ impl Index {
     ) -> Result<MergeResult, Error> {
         let mut result = MergeResult::default();
         let mut old_images = HashSet::new();
+        let mut added_docs = vec![];
+        let mut added_embeddings = vec![];
         let other = Index::load(path, LoadMode::OnlyJson)?;
+        let tokenizer = self.tokenizer();
 
         for (rel_path, uid_other) in other.processed_files.iter() {
             let mut new_file_path = rel_path.clone();
@@ -204,8 +207,28 @@ impl Index {
                         self.build_config.compression_threshold,
                         self.build_config.compression_level,
                         &self.root_dir,
-                        true,  // create tfidf
+                        Some(&tokenizer),  // create tfidf
                     )?;
+                    added_docs.push(self.get_tfidf_by_chunk_uid(new_chunk.uid)?);
+
+                    // The path of the chunk is changed, but the vector is still good enough.
+                    let embedding_other = Index::get_uid_path(
+                        &other.root_dir,
+                        CHUNK_DIR_NAME,
+                        *new_chunk_uid,
+                        Some("embedding"),
+                    )?;
+
+                    if exists(&embedding_other) {
+                        let embedding_self = Index::get_uid_path(
+                            &self.root_dir,
+                            CHUNK_DIR_NAME,
+                            new_chunk.uid,
+                            Some("embedding"),
+                        )?;
+                        copy_file(&embedding_other, &embedding_self)?;
+                        added_embeddings.push((new_chunk.uid, embedding::load_from_file(&embedding_self)?));
+                    }
                 }
 
                 if !quiet {
@@ -277,11 +300,12 @@ impl Index {
             result.overriden_images += 1;
         }
 
-        if !dry_run && (result.added_chunks > 0 || result.removed_chunks > 0) && self.ii_status != IIStatus::None {
-            self.ii_status = IIStatus::Outdated;
-        }
-
         if !dry_run {
+            // removed chunks are already removed from the ii by `self.remove_file`
+            let added_chunks = added_docs.iter().filter_map(|doc| doc.uid).collect::<Vec<_>>();
+            self.update_ii(&added_chunks, &[])?;
+            self.update_corpus_stats(&added_docs, &[])?;
+            self.add_to_embedding_store(&added_embeddings)?;
             self.save_to_file()?;
         }
 
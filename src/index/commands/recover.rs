@@ -66,6 +66,9 @@ impl Index {
     /// - Recover C: If there's a broken tfidf file, it creates a new one.
     /// - Recover D: If there's a broken config file, it replaces the file with a default one.
     /// - Recover E: If self.curr_processing_file is not None, the file is staged.
+    /// - Recover F: It creates corpus stats from scratch.
+    /// - Recover G: If the tokenizer config has changed, it creates all the tfidf files from scratch.
+    /// - Recover H: If there is an embedding store, it creates the store from scratch, so that it doesn't have vectors of removed chunks.
     pub fn recover(&mut self) -> Result<RecoverResult, Error> {
         let mut processed_files: HashMap<Path, Vec<(Uid, usize)>> = HashMap::new();
         let mut chunk_count = 0;
@@ -75,6 +78,7 @@ impl Index {
             replaced_configs: vec![],
             staged_files: vec![],
         };
+        let tokenizer = self.tokenizer();
 
         for chunk_file in self.get_all_chunk_files()? {
             let chunk_ = chunk::load_from_file(&chunk_file)?;
@@ -90,6 +94,12 @@ impl Index {
                             remove_file(&tfidf_file)?;
                         }
 
+                        let embedding_file = set_extension(&chunk_file, "embedding")?;
+
+                        if exists(&embedding_file) {
+                            remove_file(&embedding_file)?;
+                        }
+
                         result.removed_chunks += 1;
                         continue;
                     }
@@ -108,7 +118,7 @@ impl Index {
                     self.build_config.compression_threshold,
                     self.build_config.compression_level,
                     &self.root_dir,
-                    true,  // create tfidf
+                    Some(&tokenizer),  // create tfidf
                 )?;
                 result.created_tfidfs += 1;
             }
@@ -145,6 +155,12 @@ impl Index {
                             for uid in uids.iter() {
                                 if !self.check_chunk_by_uid(*uid) {
                                     remove_file(&chunk_path)?;
+                                    let embedding_path = set_extension(&chunk_path, "embedding")?;
+
+                                    if exists(&embedding_path) {
+                                        remove_file(&embedding_path)?;
+                                    }
+
                                     result.removed_chunks += 1;
                                     chunk_count -= 1;
                                     continue 'gc_loop;
@@ -267,6 +283,18 @@ impl Index {
             self.ii_status = IIStatus::Outdated;
         }
 
+        // Recover G
+        // It doesn't touch the tfidf files if the new config is invalid.
+        if self.is_tfidf_outdated() && self.build_config.tokenizer_config().get_stemmer().is_ok() {
+            result.created_tfidfs += self.rebuild_tfidf_files()?;
+        }
+
+        // Recover F
+        self.rebuild_corpus_stats()?;
+
+        // Recover H
+        self.rebuild_embedding_store()?;
+
         self.save_to_file()?;
         Ok(result)
     }
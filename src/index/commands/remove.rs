@@ -1,6 +1,6 @@
 use super::Index;
 use crate::error::Error;
-use crate::index::{CHUNK_DIR_NAME, IIStatus};
+use crate::index::{CHUNK_DIR_NAME, IIStatus, tfidf};
 use ragit_fs::{exists, get_relative_path, remove_file, set_extension};
 use std::collections::HashSet;
 
@@ -108,7 +108,10 @@ impl Index {
                 |file| file.to_string()
             ).collect();
 
-            self.ii_status = IIStatus::Outdated;
+            let mut removed_docs = vec![];
+
+            // If a tfidf file is missing, it cannot tell which terms to remove from the ii.
+            let mut missing_tfidf = false;
 
             for file in processed_candidates.iter() {
                 match self.processed_files.get(file).map(|uid| *uid) {
@@ -125,8 +128,23 @@ impl Index {
                             let tfidf_path = set_extension(&chunk_path, "tfidf")?;
 
                             if exists(&tfidf_path) {
+                                match tfidf::load_from_file(&tfidf_path) {
+                                    Ok(processed_doc) => { removed_docs.push(processed_doc); },
+                                    Err(_) => { missing_tfidf = true; },
+                                }
+
                                 remove_file(&tfidf_path)?;
                             }
+
+                            else {
+                                missing_tfidf = true;
+                            }
+
+                            let embedding_path = set_extension(&chunk_path, "embedding")?;
+
+                            if exists(&embedding_path) {
+                                remove_file(&embedding_path)?;
+                            }
                         }
 
                         self.processed_files.remove(file).unwrap();
@@ -136,6 +154,12 @@ impl Index {
                 }
             }
 
+            if missing_tfidf && self.ii_status != IIStatus::None {
+                self.ii_status = IIStatus::Outdated;
+            }
+
+            self.update_ii(&[], &removed_docs)?;
+            self.update_corpus_stats(&[], &removed_docs)?;
             self.save_to_file()?;
         }
 
@@ -0,0 +1,132 @@
+use super::Index;
+use chrono::offset::Local;
+use crate::constant::SESSION_DIR_NAME;
+use crate::error::Error;
+use crate::query::QueryTurn;
+use ragit_fs::{
+    WriteMode,
+    create_dir_all,
+    exists,
+    extension,
+    file_name,
+    join,
+    read_dir,
+    read_string,
+    remove_file,
+    write_string,
+};
+use serde::{Deserialize, Serialize};
+
+/// A multi-turn conversation that's saved at `.ragit/sessions/<name>.json`, so that
+/// `rag query --session <name>` can continue it later.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Session {
+    pub name: String,
+    pub turns: Vec<QueryTurn>,
+
+    /// unix timestamp, in seconds
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Session {
+    pub fn new(name: String) -> Self {
+        let now = Local::now().timestamp();
+
+        Session {
+            name,
+            turns: vec![],
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+impl Index {
+    /// A name is a non-empty string of ascii alphanumerics, `-` and `_`.
+    pub fn is_valid_session_name(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    pub fn has_session(&self, name: &str) -> Result<bool, Error> {
+        Ok(exists(&self.get_session_path(name)?))
+    }
+
+    pub fn load_session(&self, name: &str) -> Result<Session, Error> {
+        let path = self.get_session_path(name)?;
+
+        if !exists(&path) {
+            return Err(Error::NoSuchSession(name.to_string()));
+        }
+
+        Ok(serde_json::from_str(&read_string(&path)?)?)
+    }
+
+    /// It creates a new session if it doesn't exist.
+    pub fn load_or_create_session(&self, name: &str) -> Result<Session, Error> {
+        if self.has_session(name)? {
+            self.load_session(name)
+        }
+
+        else {
+            Ok(Session::new(name.to_string()))
+        }
+    }
+
+    /// It updates `session.updated_at`.
+    pub fn save_session(&self, session: &mut Session) -> Result<(), Error> {
+        let path = self.get_session_path(&session.name)?;
+        let session_dir = Index::get_rag_path(&self.root_dir, &SESSION_DIR_NAME.to_string())?;
+
+        if !exists(&session_dir) {
+            create_dir_all(&session_dir)?;
+        }
+
+        session.updated_at = Local::now().timestamp();
+        Ok(write_string(
+            &path,
+            &serde_json::to_string_pretty(session)?,
+            WriteMode::Atomic,
+        )?)
+    }
+
+    /// Sessions are sorted by name.
+    pub fn get_all_sessions(&self) -> Result<Vec<Session>, Error> {
+        let session_dir = Index::get_rag_path(&self.root_dir, &SESSION_DIR_NAME.to_string())?;
+        let mut result = vec![];
+
+        if !exists(&session_dir) {
+            return Ok(result);
+        }
+
+        for path in read_dir(&session_dir, true)? {
+            if extension(&path)?.unwrap_or_default() == "json" {
+                result.push(self.load_session(&file_name(&path)?)?);
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn remove_session(&self, name: &str) -> Result<(), Error> {
+        let path = self.get_session_path(name)?;
+
+        if !exists(&path) {
+            return Err(Error::NoSuchSession(name.to_string()));
+        }
+
+        Ok(remove_file(&path)?)
+    }
+
+    fn get_session_path(&self, name: &str) -> Result<String, Error> {
+        // otherwise, `name` can point to a file outside `.ragit/sessions/`
+        if !Index::is_valid_session_name(name) {
+            return Err(Error::InvalidSessionName(name.to_string()));
+        }
+
+        Ok(join(
+            &Index::get_rag_path(&self.root_dir, &SESSION_DIR_NAME.to_string())?,
+            &format!("{name}.json"),
+        )?)
+    }
+}
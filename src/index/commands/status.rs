@@ -0,0 +1,97 @@
+use super::Index;
+use crate::error::Error;
+use crate::uid::Uid;
+use ragit_fs::{exists, get_relative_path};
+use ragit_ignore::Ignore;
+use serde::{Deserialize, Serialize};
+
+pub type Path = String;
+
+/// It's like `git status`. All the paths are relative to the root of the knowledge-base,
+/// and each list is sorted.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Status {
+    /// Staged files that exist in the file system. `rag build` will process them.
+    pub staged: Vec<Path>,
+
+    /// Processed files whose contents have changed since they're processed. If a
+    /// modified file is staged again, it's in `staged`, not here.
+    pub modified: Vec<Path>,
+
+    /// Staged or processed files that do not exist in the file system anymore.
+    pub deleted: Vec<Path>,
+
+    /// Files that are neither staged nor processed, and not ignored.
+    pub new: Vec<Path>,
+}
+
+impl Status {
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty() && self.modified.is_empty() && self.deleted.is_empty() && self.new.is_empty()
+    }
+}
+
+impl Index {
+    /// It compares the files in the file system with the index. A processed file is
+    /// modified if its `Uid::new_file` is different from the uid in the index.
+    pub fn get_status(&self, ignore: &Ignore) -> Result<Status, Error> {
+        let (mut modified, mut deleted) = self.get_changed_files()?;
+        let mut staged = vec![];
+        let mut new = vec![];
+
+        for file in self.staged_files.iter() {
+            if exists(&Index::get_data_path(&self.root_dir, file)?) {
+                staged.push(file.to_string());
+            }
+
+            else {
+                deleted.push(file.to_string());
+            }
+        }
+
+        for (ignored, file) in ignore.walk_tree(&self.root_dir, &self.root_dir, false /* follow symlink */, true /* skip ignored dirs */)? {
+            if ignored {
+                continue;
+            }
+
+            let file = get_relative_path(&self.root_dir, &file)?;
+
+            if !self.staged_files.contains(&file) && !self.processed_files.contains_key(&file) {
+                new.push(file);
+            }
+        }
+
+        staged.sort();
+        modified.sort();
+        deleted.sort();
+        deleted.dedup();
+        new.sort();
+
+        Ok(Status {
+            staged,
+            modified,
+            deleted,
+            new,
+        })
+    }
+
+    /// It returns processed files that are (modified, deleted). Staged files are not included.
+    pub(crate) fn get_changed_files(&self) -> Result<(Vec<Path>, Vec<Path>), Error> {
+        let mut modified = vec![];
+        let mut deleted = vec![];
+
+        for (file, uid) in self.processed_files.iter() {
+            let data_path = Index::get_data_path(&self.root_dir, file)?;
+
+            if !exists(&data_path) {
+                deleted.push(file.to_string());
+            }
+
+            else if !self.staged_files.contains(file) && Uid::new_file(&self.root_dir, &data_path)? != *uid {
+                modified.push(file.to_string());
+            }
+        }
+
+        Ok((modified, deleted))
+    }
+}
@@ -0,0 +1,172 @@
+use super::Index;
+use super::build::BuildOutput;
+use crate::error::Error;
+use crate::index::LoadMode;
+use chrono::offset::Local;
+use notify::{
+    EventKind,
+    RecommendedWatcher,
+    RecursiveMode,
+    Watcher,
+    event::{AccessKind, AccessMode, ModifyKind},
+};
+use ragit_fs::{is_dir, is_symlink, read_dir};
+use ragit_ignore::Ignore;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+impl Index {
+    /// rag build --watch
+    ///
+    /// It stages new, modified and deleted files and builds them. Then it waits until
+    /// a file in the knowledge-base changes, and does that again. Files that match
+    /// `.ragignore` are not watched. It waits until there's no change for `debounce`,
+    /// so that it doesn't build a file multiple times while you're saving it.
+    ///
+    /// It never returns unless there's an error.
+    pub async fn watch(&mut self, workers: usize, debounce: Duration, quiet: bool) -> Result<(), Error> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        // The watcher calls this closure in its own thread, so nothing blocks the runtime.
+        let mut watcher = notify::recommended_watcher(
+            move |event| { let _ = tx.send(event); }
+        ).map_err(|e| Error::CannotWatch(e.to_string()))?;
+        watch_tree(&mut watcher, &self.root_dir, &self.root_dir, &self.read_ignore_file()?)?;
+        self.sync_and_build(workers, quiet).await?;
+
+        // It blocks until there's a change.
+        while let Some(event) = rx.recv().await {
+            let mut events = vec![event];
+
+            // debounce
+            while let Ok(Some(event)) = tokio::time::timeout(debounce, rx.recv()).await {
+                events.push(event);
+            }
+
+            let ignore = self.read_ignore_file()?;
+            let mut has_change = false;
+
+            for event in events.into_iter() {
+                match event {
+                    Ok(event) => {
+                        // Some events are lost. It has to look at all the files.
+                        if event.need_rescan() {
+                            has_change = true;
+                            continue;
+                        }
+
+                        if !is_change(&event.kind) {
+                            continue;
+                        }
+
+                        for path in event.paths.iter() {
+                            let path = path.to_string_lossy().to_string();
+
+                            if ignore.is_match(&self.root_dir, &path) || ignore.is_strong_match(&self.root_dir, &path) {
+                                continue;
+                            }
+
+                            // a new directory, or a directory that's moved here
+                            if is_dir(&path) && !is_symlink(&path) && matches!(event.kind, EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))) {
+                                watch_tree(&mut watcher, &self.root_dir, &path, &ignore)?;
+                            }
+
+                            has_change = true;
+                        }
+                    },
+
+                    // For example, a directory is removed before it's watched. It looks at all the files.
+                    Err(_) => {
+                        has_change = true;
+                    },
+                }
+            }
+
+            if has_change {
+                self.sync_and_build(workers, quiet).await?;
+            }
+        }
+
+        Err(Error::CannotWatch(String::from("the watcher has stopped")))
+    }
+
+    // It's like `rag add --all && rag add --update && rag build`. If a file cannot be
+    // built, it prints the error and keeps watching, so that you can fix the file.
+    // It reloads the knowledge-base first, because the user might have changed it
+    // (e.g. `rag config --set`) while it's watching.
+    async fn sync_and_build(&mut self, workers: usize, quiet: bool) -> Result<(), Error> {
+        *self = Index::load(self.root_dir.clone(), LoadMode::QuickCheck)?;
+        let ignore = self.read_ignore_file()?;
+        let added = self.add_files(&vec![self.root_dir.clone()], None, false, &ignore)?;
+        let updated = self.add_update(false)?;
+        let now = Local::now().format("%H:%M:%S");
+
+        if self.staged_files.is_empty() {
+            if !quiet && updated.removed > 0 {
+                println!("[{now}] removed {} files", updated.removed);
+            }
+
+            return Ok(());
+        }
+
+        if !quiet {
+            println!(
+                "[{now}] staged {} files, removed {} files",
+                added.staged + updated.staged,
+                updated.removed,
+            );
+        }
+
+        match self.build_with_output(
+            workers,
+            if quiet { BuildOutput::Quiet } else { BuildOutput::Log },
+        ).await {
+            Ok(()) => {
+                if !quiet {
+                    println!("[{}] build complete: {} files, {} chunks", Local::now().format("%H:%M:%S"), self.processed_files.len(), self.chunk_count);
+                }
+
+                Ok(())
+            },
+            Err(Error::CannotBuild(errors)) => {
+                eprintln!("[{}] cannot build knowledge-base due to {} errors", Local::now().format("%H:%M:%S"), errors.len());
+
+                for (file, error) in errors.iter() {
+                    eprintln!("    {file}: {error}");
+                }
+
+                Ok(())
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// It watches `dir` and its sub directories, except the ignored ones. A directory
+// is watched without its sub directories, so that the ignored ones (e.g. `.ragit/`,
+// which `rag build` writes to) are not watched.
+fn watch_tree(watcher: &mut RecommendedWatcher, root_dir: &str, dir: &str, ignore: &Ignore) -> Result<(), Error> {
+    watcher.watch(Path::new(dir), RecursiveMode::NonRecursive).map_err(
+        |e| Error::CannotWatch(format!("cannot watch `{dir}`: {e}"))
+    )?;
+
+    for entry in read_dir(dir, false)? {
+        // ragit doesn't follow symlinks
+        if is_dir(&entry) && !is_symlink(&entry) && !ignore.is_match(root_dir, &entry) && !ignore.is_strong_match(root_dir, &entry) {
+            watch_tree(watcher, root_dir, &entry, ignore)?;
+        }
+    }
+
+    Ok(())
+}
+
+// `rag build` reads the files, so reading a file is not a change.
+fn is_change(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        EventKind::Access(_) => false,
+        EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        _ => true,
+    }
+}
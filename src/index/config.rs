@@ -1,3 +1,4 @@
+use super::tfidf::TokenizerConfig;
 use serde::{Deserialize, Serialize};
 
 // This struct is used for loading partial configurations from ~/.config/ragit/build.json
@@ -11,6 +12,10 @@ pub struct PartialBuildConfig {
     pub strict_file_reader: Option<bool>,
     pub compression_threshold: Option<u64>,
     pub compression_level: Option<u32>,
+    pub stemmer: Option<String>,
+    pub stopwords: Option<Vec<String>>,
+    pub structural_markdown_chunking: Option<bool>,
+    pub split_cjk: Option<bool>,
 }
 
 impl PartialBuildConfig {
@@ -40,6 +45,18 @@ impl PartialBuildConfig {
         if let Some(compression_level) = self.compression_level {
             config.compression_level = compression_level;
         }
+        if let Some(stemmer) = &self.stemmer {
+            config.stemmer = stemmer.clone();
+        }
+        if let Some(stopwords) = &self.stopwords {
+            config.stopwords = stopwords.clone();
+        }
+        if let Some(structural_markdown_chunking) = self.structural_markdown_chunking {
+            config.structural_markdown_chunking = structural_markdown_chunking;
+        }
+        if let Some(split_cjk) = self.split_cjk {
+            config.split_cjk = split_cjk;
+        }
     }
 }
 
@@ -72,6 +89,49 @@ pub struct BuildConfig {
 
     // 0 ~ 9
     pub compression_level: u32,
+
+    // stemming algorithm of tf-idf and the inverted index: "arabic", "danish", "dutch",
+    // "english", "finnish", "french", "german", "greek", "hungarian", "italian",
+    // "norwegian", "portuguese", "romanian", "russian", "spanish", "swedish",
+    // "tamil", "turkish" or "none"
+    // if you change this, `rag build` rebuilds the tfidf files and you have to
+    // run `rag ii-build` again
+    #[serde(default = "default_stemmer")]
+    pub stemmer: String,
+
+    // words that are not searchable, like `["der", "die", "das"]`
+    // they're case-insensitive and compared before stemming
+    // changing this works like changing `stemmer`
+    #[serde(default)]
+    pub stopwords: Vec<String>,
+
+    // If it's set, markdown files are chunked by their structure. A chunk prefers
+    // to end right before a heading, and a code fence or a table is never splitted.
+    // Each chunk remembers its heading path, like `# A > ## B`. A chunk with a
+    // very long code fence can be bigger than chunk_size * 2.
+    #[serde(default)]
+    pub structural_markdown_chunking: bool,
+
+    // If it's set, Chinese and Japanese texts are split into characters and
+    // bigrams, so that their words are searchable. It's set by `rag init`, but
+    // knowledge-bases created by older versions of ragit don't have this key.
+    // changing this works like changing `stemmer`
+    #[serde(default)]
+    pub split_cjk: bool,
+}
+
+fn default_stemmer() -> String {
+    TokenizerConfig::default().stemmer
+}
+
+impl BuildConfig {
+    pub fn tokenizer_config(&self) -> TokenizerConfig {
+        TokenizerConfig {
+            stemmer: self.stemmer.clone(),
+            stopwords: self.stopwords.clone(),
+            split_cjk: self.split_cjk,
+        }
+    }
 }
 
 impl Default for BuildConfig {
@@ -85,6 +145,10 @@ impl Default for BuildConfig {
             strict_file_reader: false,
             compression_threshold: 2048,
             compression_level: 3,
+            stemmer: default_stemmer(),
+            stopwords: vec![],
+            structural_markdown_chunking: false,
+            split_cjk: true,
         }
     }
 }
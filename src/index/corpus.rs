@@ -0,0 +1,254 @@
+// Corpus-wide statistics for BM25.
+//
+// When the inverted index is used, tf-idf only sees a few candidates, and
+// document frequencies and lengths of the candidates are very different from
+// those of the entire knowledge-base. So ragit keeps the statistics of the
+// entire knowledge-base in `.ragit/corpus_stats.json`.
+//
+// `rag build`, `rag rm` and `rag merge` update the file, and `rag ii-build` and
+// `rag check --recover` create it from scratch. If the file is missing or its
+// `doc_count` doesn't match `chunk_count`, it's not used at all and the scorer
+// falls back to the statistics of the documents it has seen.
+//
+// `Index` caches the statistics, so the file is parsed at most once per process.
+// `update_corpus_stats` only updates the cache, and `Index::save_to_file` writes
+// it back. While a build is running, it's written once at the end of the build,
+// not on every flush. If the build is interrupted, `doc_count` of the file
+// doesn't match `chunk_count` anymore, so a stale file is never used.
+
+use super::{Index, ProcessedDoc};
+use crate::constant::CORPUS_STATS_FILE_NAME;
+use crate::error::Error;
+use crate::query::TermDictionary;
+use ragit_fs::{
+    WriteMode,
+    exists,
+    read_string,
+    write_bytes,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CorpusStats {
+    pub doc_count: usize,
+
+    /// Sum of the lengths (number of terms) of all the documents.
+    pub total_len: usize,
+    pub document_frequency: HashMap<String, usize>,
+}
+
+impl CorpusStats {
+    pub fn add_doc(&mut self, doc: &ProcessedDoc) {
+        self.doc_count += 1;
+        self.total_len += doc.length();
+
+        for term in doc.term_frequency.keys() {
+            match self.document_frequency.get_mut(term) {
+                Some(n) => { *n += 1; },
+                None => { self.document_frequency.insert(term.to_string(), 1); },
+            }
+        }
+    }
+
+    pub fn remove_doc(&mut self, doc: &ProcessedDoc) {
+        self.doc_count = self.doc_count.saturating_sub(1);
+        self.total_len = self.total_len.saturating_sub(doc.length());
+
+        for term in doc.term_frequency.keys() {
+            if let Some(n) = self.document_frequency.get_mut(term) {
+                *n = n.saturating_sub(1);
+
+                if *n == 0 {
+                    self.document_frequency.remove(term);
+                }
+            }
+        }
+    }
+
+    pub fn avg_len(&self) -> f32 {
+        if self.doc_count == 0 {
+            0.0
+        }
+
+        else {
+            self.total_len as f32 / self.doc_count as f32
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+enum CachedStats {
+    #[default]
+    NotLoaded,
+
+    /// The file doesn't exist.
+    Missing,
+    Loaded(Arc<CorpusStats>),
+}
+
+#[derive(Default)]
+struct CacheState {
+    stats: CachedStats,
+
+    /// The cached statistics are newer than the file.
+    dirty: bool,
+
+    /// `Index::get_term_dictionary` and `chunk_count` when it's created. It's
+    /// dropped when the statistics change.
+    dictionary: Option<(usize, Arc<TermDictionary>)>,
+}
+
+impl std::fmt::Debug for CacheState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CacheState")
+            .field("stats", &self.stats)
+            .field("dirty", &self.dirty)
+            .field("dictionary", &self.dictionary.as_ref().map(|(_, dictionary)| dictionary.len()))
+            .finish()
+    }
+}
+
+/// In-memory copy of `corpus_stats.json`. It's not a part of the index's state,
+/// so it's not serialized and it's ignored by `==`.
+#[derive(Debug, Default)]
+pub(crate) struct CorpusStatsCache(Mutex<CacheState>);
+
+impl Clone for CorpusStatsCache {
+    fn clone(&self) -> Self {
+        let state = self.0.lock().unwrap();
+
+        CorpusStatsCache(Mutex::new(CacheState {
+            stats: state.stats.clone(),
+            dirty: state.dirty,
+            dictionary: state.dictionary.clone(),
+        }))
+    }
+}
+
+impl PartialEq for CorpusStatsCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for CorpusStatsCache {}
+
+impl Index {
+    /// It returns `None` if the statistics are missing or out of date.
+    pub fn get_corpus_stats(&self) -> Result<Option<Arc<CorpusStats>>, Error> {
+        let mut state = self.corpus_stats.0.lock().unwrap();
+
+        if let CachedStats::NotLoaded = &state.stats {
+            let path = self.get_corpus_stats_path()?;
+
+            state.stats = if exists(&path) {
+                CachedStats::Loaded(Arc::new(serde_json::from_str::<CorpusStats>(&read_string(&path)?)?))
+            } else {
+                CachedStats::Missing
+            };
+        }
+
+        match &state.stats {
+            CachedStats::Loaded(stats) if stats.doc_count == self.chunk_count => Ok(Some(stats.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    /// It reads the tfidf files of all the chunks and creates the statistics from scratch.
+    pub fn rebuild_corpus_stats(&self) -> Result<CorpusStats, Error> {
+        let mut stats = CorpusStats::default();
+
+        for uid in self.get_all_chunk_uids()? {
+            stats.add_doc(&self.get_tfidf_by_chunk_uid(uid)?);
+        }
+
+        self.save_corpus_stats(&stats)?;
+        Ok(stats)
+    }
+
+    /// It does nothing if the statistics file doesn't exist. If `added` or `removed`
+    /// is not empty, the caller must update `self.chunk_count` accordingly.
+    /// It only updates the cache. `Index::save_to_file` writes the result.
+    pub(crate) fn update_corpus_stats(
+        &self,
+        added: &[ProcessedDoc],
+        removed: &[ProcessedDoc],
+    ) -> Result<(), Error> {
+        if added.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        // It doesn't care whether `doc_count` matches `chunk_count`, because the caller
+        // is in the middle of updating `chunk_count`. It only loads the cache.
+        self.get_corpus_stats()?;
+        let mut state = self.corpus_stats.0.lock().unwrap();
+
+        let CachedStats::Loaded(stats) = &mut state.stats else {
+            return Ok(());
+        };
+        let stats = Arc::make_mut(stats);
+
+        for doc in removed.iter() {
+            stats.remove_doc(doc);
+        }
+
+        for doc in added.iter() {
+            stats.add_doc(doc);
+        }
+
+        state.dirty = true;
+        state.dictionary = None;
+        Ok(())
+    }
+
+    /// It writes the statistics immediately, and replaces the cache.
+    pub(crate) fn save_corpus_stats(&self, stats: &CorpusStats) -> Result<(), Error> {
+        write_bytes(
+            &self.get_corpus_stats_path()?,
+            &serde_json::to_vec(stats)?,
+            WriteMode::Atomic,
+        )?;
+        *self.corpus_stats.0.lock().unwrap() = CacheState {
+            stats: CachedStats::Loaded(Arc::new(stats.clone())),
+            dirty: false,
+            dictionary: None,
+        };
+        Ok(())
+    }
+
+    /// It writes the cached statistics if they're modified.
+    pub(crate) fn flush_corpus_stats(&self) -> Result<(), Error> {
+        let mut state = self.corpus_stats.0.lock().unwrap();
+
+        if let (CachedStats::Loaded(stats), true) = (&state.stats, state.dirty) {
+            write_bytes(
+                &self.get_corpus_stats_path()?,
+                &serde_json::to_vec(stats.as_ref())?,
+                WriteMode::Atomic,
+            )?;
+            state.dirty = false;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn get_cached_term_dictionary(&self) -> Option<Arc<TermDictionary>> {
+        match &self.corpus_stats.0.lock().unwrap().dictionary {
+            Some((chunk_count, dictionary)) if *chunk_count == self.chunk_count => Some(dictionary.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn cache_term_dictionary(&self, dictionary: Arc<TermDictionary>) {
+        self.corpus_stats.0.lock().unwrap().dictionary = Some((self.chunk_count, dictionary));
+    }
+
+    fn get_corpus_stats_path(&self) -> Result<String, Error> {
+        Index::get_rag_path(
+            &self.root_dir,
+            &CORPUS_STATS_FILE_NAME.to_string(),
+        )
+    }
+}
@@ -0,0 +1,408 @@
+// Embedding vectors are an optional retrieval stage. Each chunk's vector
+// lives next to its tfidf file, at `.ragit/chunks/xx/yyyy.embedding`.
+// The file also records which model created the vector, so that changing
+// `embedding_model` doesn't silently mix vectors from different models.
+//
+// Vector search doesn't read the `.embedding` files. `rag embedding-build`
+// collects the vectors of the current model into a single file,
+// `.ragit/embeddings`, and vector search scans the file, which is loaded at
+// most once per process. `rag rm` doesn't touch the file, so it may have
+// vectors of removed chunks, but vector search skips them. `rag merge` adds
+// the vectors of the merged chunks to the file, and `rag embedding-build` and
+// `rag check --recover` create it from scratch. If the file is missing (e.g.
+// the vectors are created by an older version of ragit), vector search reads
+// the `.embedding` files instead.
+
+use super::Index;
+use crate::constant::{CHUNK_DIR_NAME, EMBEDDING_STORE_FILE_NAME};
+use crate::error::Error;
+use crate::uid::Uid;
+use flate2::Compression;
+use flate2::read::{GzDecoder, GzEncoder};
+use ragit_api::{EmbeddingRequest, RecordAt, cosine_similarity};
+use ragit_fs::{
+    WriteMode,
+    exists,
+    read_bytes,
+    write_bytes,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+// how many chunks are sent in a single api call
+const EMBEDDING_BATCH_SIZE: usize = 32;
+
+const UID_BYTE_LEN: usize = 32;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Embedding {
+    pub model: String,
+    pub vector: Vec<f32>,
+}
+
+// embedding files are always compressed
+pub fn load_from_file(path: &str) -> Result<Embedding, Error> {
+    let content = read_bytes(path)?;
+    let mut decompressed = vec![];
+    let mut gz = GzDecoder::new(&content[..]);
+    gz.read_to_end(&mut decompressed)?;
+
+    Ok(serde_json::from_slice(&decompressed)?)
+}
+
+pub fn save_to_file(path: &str, embedding: &Embedding) -> Result<(), Error> {
+    let result = serde_json::to_vec(embedding)?;
+    let mut compressed = vec![];
+    let mut gz = GzEncoder::new(&result[..], Compression::best());
+    gz.read_to_end(&mut compressed)?;
+
+    Ok(write_bytes(
+        path,
+        &compressed,
+        WriteMode::CreateOrTruncate,
+    )?)
+}
+
+/// Vectors of a single model, in a flat matrix. The vector of `uids[i]` is
+/// `vectors[(i * dimension)..((i + 1) * dimension)]`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct EmbeddingStore {
+    pub model: String,
+    pub dimension: usize,
+    uids: Vec<Uid>,
+    vectors: Vec<f32>,
+}
+
+impl EmbeddingStore {
+    pub fn new(model: String) -> Self {
+        EmbeddingStore {
+            model,
+            ..EmbeddingStore::default()
+        }
+    }
+
+    /// The dimension of the first vector is the dimension of the store. It
+    /// ignores vectors with another dimension.
+    pub fn push(&mut self, uid: Uid, vector: &[f32]) {
+        if self.uids.is_empty() {
+            self.dimension = vector.len();
+        }
+
+        if vector.len() == self.dimension {
+            self.uids.push(uid);
+            self.vectors.extend_from_slice(vector);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Uid, &[f32])> {
+        // `chunks_exact(0)` panics
+        let dimension = self.dimension.max(1);
+        self.uids.iter().copied().zip(self.vectors.chunks_exact(dimension))
+    }
+
+    // [model len: u32][model][dimension: u32][([uid; 32], [f32; dimension]) * n], big endian
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let bytes = read_bytes(path)?;
+        let broken = |message: &str| Error::CorruptedFile { path: path.to_string(), message: Some(message.to_string()) };
+        let read_u32 = |at: usize| -> Result<usize, Error> {
+            match bytes.get(at..(at + 4)) {
+                Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize),
+                None => Err(broken("unexpected end of file")),
+            }
+        };
+
+        let model_len = read_u32(0)?;
+        let model = match bytes.get(4..(4 + model_len)) {
+            Some(model) => String::from_utf8(model.to_vec()).map_err(|_| broken("the model name is not a valid utf-8"))?,
+            None => {
+                return Err(broken("unexpected end of file"));
+            },
+        };
+        let dimension = read_u32(4 + model_len)?;
+        let body = &bytes[(8 + model_len)..];
+        let entry_len = UID_BYTE_LEN + dimension * 4;
+
+        if body.len() % entry_len != 0 {
+            return Err(broken(&format!("the vectors are {} bytes, which is not {entry_len} * n", body.len())));
+        }
+
+        let mut result = EmbeddingStore {
+            model,
+            dimension,
+            uids: Vec::with_capacity(body.len() / entry_len),
+            vectors: Vec::with_capacity(body.len() / entry_len * dimension),
+        };
+
+        for entry in body.chunks_exact(entry_len) {
+            result.uids.push(Uid::decode(&entry[..UID_BYTE_LEN])?);
+
+            for f in entry[UID_BYTE_LEN..].chunks_exact(4) {
+                result.vectors.push(f32::from_be_bytes([f[0], f[1], f[2], f[3]]));
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let mut bytes = Vec::with_capacity(8 + self.model.len() + self.uids.len() * (UID_BYTE_LEN + self.dimension * 4));
+        bytes.extend_from_slice(&(self.model.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.model.as_bytes());
+        bytes.extend_from_slice(&(self.dimension as u32).to_be_bytes());
+
+        for (uid, vector) in self.iter() {
+            uid.encode(UID_BYTE_LEN, &mut bytes);
+
+            for f in vector.iter() {
+                bytes.extend_from_slice(&f.to_be_bytes());
+            }
+        }
+
+        Ok(write_bytes(
+            path,
+            &bytes,
+            WriteMode::Atomic,
+        )?)
+    }
+}
+
+/// In-memory copy of `.ragit/embeddings`. Like `CorpusStatsCache`, it's not
+/// serialized and it's ignored by `==`.
+#[derive(Debug, Default)]
+pub(crate) struct EmbeddingStoreCache(Mutex<Option<Arc<EmbeddingStore>>>);
+
+impl Clone for EmbeddingStoreCache {
+    fn clone(&self) -> Self {
+        EmbeddingStoreCache(Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
+
+impl PartialEq for EmbeddingStoreCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for EmbeddingStoreCache {}
+
+impl Index {
+    /// `rag embedding-build`
+    ///
+    /// It creates an embedding vector for every chunk that doesn't have one,
+    /// or whose vector was created by a different model. It returns how many
+    /// vectors it created.
+    pub async fn build_embeddings(&self, quiet: bool) -> Result<usize, Error> {
+        let mut targets = vec![];
+        let mut store = EmbeddingStore::new(self.query_config.embedding_model.clone());
+
+        for uid in self.get_all_chunk_uids()? {
+            match self.get_embedding_by_chunk_uid(uid) {
+                Ok(Some(embedding)) if embedding.model == self.query_config.embedding_model => {
+                    store.push(uid, &embedding.vector);
+                },
+                _ => { targets.push(uid); },
+            }
+        }
+
+        let mut count = 0;
+
+        for batch in targets.chunks(EMBEDDING_BATCH_SIZE) {
+            let mut input = Vec::with_capacity(batch.len());
+
+            for uid in batch.iter() {
+                input.push(self.get_chunk_by_uid(*uid)?.into_tfidf_haystack(&self.root_dir)?);
+            }
+
+            let vectors = self.get_embedding_request(input).send().await?;
+
+            for (uid, vector) in batch.iter().zip(vectors) {
+                store.push(*uid, &vector);
+                save_to_file(
+                    &Index::get_uid_path(
+                        &self.root_dir,
+                        CHUNK_DIR_NAME,
+                        *uid,
+                        Some("embedding"),
+                    )?,
+                    &Embedding {
+                        model: self.query_config.embedding_model.clone(),
+                        vector,
+                    },
+                )?;
+            }
+
+            count += batch.len();
+
+            if !quiet {
+                println!("created {count}/{} embeddings", targets.len());
+            }
+        }
+
+        self.save_embedding_store(store)?;
+        Ok(count)
+    }
+
+    /// It returns `Ok(None)` if the chunk doesn't have an embedding vector.
+    pub fn get_embedding_by_chunk_uid(&self, uid: Uid) -> Result<Option<Embedding>, Error> {
+        let embedding_at = Index::get_uid_path(
+            &self.root_dir,
+            CHUNK_DIR_NAME,
+            uid,
+            Some("embedding"),
+        )?;
+
+        if exists(&embedding_at) {
+            Ok(Some(load_from_file(&embedding_at)?))
+        }
+
+        else {
+            Ok(None)
+        }
+    }
+
+    /// It embeds `query` and returns the `limit` most similar chunks, in descending order.
+    /// Chunks without a vector (or with a vector from another model) are ignored.
+    pub async fn run_vector_search(&self, query: &str, limit: usize) -> Result<Vec<(Uid, f32)>, Error> {
+        self.run_vector_search_worker(query, limit, None).await
+    }
+
+    /// If `allowed` is set, the other chunks are not compared at all.
+    pub(crate) async fn run_vector_search_worker(
+        &self,
+        query: &str,
+        limit: usize,
+        allowed: Option<&HashSet<Uid>>,
+    ) -> Result<Vec<(Uid, f32)>, Error> {
+        let query_vector = match self.get_embedding_request(vec![query.to_string()]).send().await?.pop() {
+            Some(vector) => vector,
+            None => {
+                return Ok(vec![]);
+            },
+        };
+        let store = self.get_embedding_store()?;
+
+        if store.model != self.query_config.embedding_model {
+            return Ok(vec![]);
+        }
+
+        let mut result = vec![];
+
+        for (uid, vector) in store.iter() {
+            if let Some(allowed) = allowed {
+                if !allowed.contains(&uid) {
+                    continue;
+                }
+            }
+
+            result.push((uid, cosine_similarity(&query_vector, vector)));
+        }
+
+        // The store is sorted by uid, and it's a stable sort, so the result is deterministic.
+        result.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        // The store may have vectors of removed chunks.
+        Ok(result.into_iter().filter(
+            |(uid, _)| self.check_chunk_by_uid(*uid)
+        ).take(limit).collect())
+    }
+
+    /// If `.ragit/embeddings` is missing, it collects the vectors of the current
+    /// model from the `.embedding` files.
+    pub(crate) fn get_embedding_store(&self) -> Result<Arc<EmbeddingStore>, Error> {
+        let mut cache = self.embedding_store.0.lock().unwrap();
+
+        if let Some(store) = cache.as_ref() {
+            return Ok(store.clone());
+        }
+
+        let path = self.get_embedding_store_path()?;
+        let store = if exists(&path) {
+            EmbeddingStore::load(&path)?
+        } else {
+            self.collect_embeddings()?
+        };
+        let store = Arc::new(store);
+        *cache = Some(store.clone());
+        Ok(store)
+    }
+
+    /// It reads the `.embedding` files of all the chunks and creates `.ragit/embeddings` from scratch.
+    /// It does nothing if the file doesn't exist.
+    pub(crate) fn rebuild_embedding_store(&self) -> Result<(), Error> {
+        if !exists(&self.get_embedding_store_path()?) {
+            return Ok(());
+        }
+
+        self.save_embedding_store(self.collect_embeddings()?)
+    }
+
+    /// It adds vectors to `.ragit/embeddings`. It ignores vectors of another model.
+    /// If the file doesn't exist, it does nothing because vector search reads the
+    /// `.embedding` files anyway.
+    pub(crate) fn add_to_embedding_store(&self, embeddings: &[(Uid, Embedding)]) -> Result<(), Error> {
+        if embeddings.is_empty() || !exists(&self.get_embedding_store_path()?) {
+            return Ok(());
+        }
+
+        let mut store = self.get_embedding_store()?.as_ref().clone();
+
+        for (uid, embedding) in embeddings.iter() {
+            if embedding.model == store.model {
+                store.push(*uid, &embedding.vector);
+            }
+        }
+
+        self.save_embedding_store(store)
+    }
+
+    fn collect_embeddings(&self) -> Result<EmbeddingStore, Error> {
+        let mut store = EmbeddingStore::new(self.query_config.embedding_model.clone());
+
+        for uid in self.get_all_chunk_uids()? {
+            if let Some(embedding) = self.get_embedding_by_chunk_uid(uid)? {
+                if embedding.model == store.model {
+                    store.push(uid, &embedding.vector);
+                }
+            }
+        }
+
+        Ok(store)
+    }
+
+    fn save_embedding_store(&self, store: EmbeddingStore) -> Result<(), Error> {
+        store.save(&self.get_embedding_store_path()?)?;
+        *self.embedding_store.0.lock().unwrap() = Some(Arc::new(store));
+        Ok(())
+    }
+
+    fn get_embedding_store_path(&self) -> Result<String, Error> {
+        Index::get_rag_path(
+            &self.root_dir,
+            &EMBEDDING_STORE_FILE_NAME.to_string(),
+        )
+    }
+
+    fn get_embedding_request(&self, input: Vec<String>) -> EmbeddingRequest {
+        let api_key = if self.query_config.embedding_api_env_var.is_empty() {
+            String::new()
+        } else {
+            std::env::var(&self.query_config.embedding_api_env_var).unwrap_or_default()
+        };
+
+        EmbeddingRequest {
+            input,
+            model: self.query_config.embedding_model.clone(),
+            url: self.query_config.embedding_url.clone(),
+            api_key,
+            timeout: self.api_config.timeout,
+            max_retry: self.api_config.max_retry,
+            sleep_between_retries: self.api_config.sleep_between_retries,
+            record_api_usage_at: self.api_config.dump_api_usage_at.clone().map(
+                |path| RecordAt { path, id: String::from("embedding") }
+            ),
+            dump_json_at: self.api_config.dump_log_at.clone(),
+        }
+    }
+}
@@ -7,6 +7,7 @@ use ragit_fs::extension;
 use ragit_pdl::{MessageContent, ImageType};
 use std::collections::{HashMap, VecDeque};
 
+mod code;
 mod csv;
 mod image;
 mod line;
@@ -14,6 +15,7 @@ mod markdown;
 mod plain_text;
 mod pdf;
 
+pub use code::CodeReader;
 pub use csv::CsvReader;
 pub use image::{Image, ImageDescription, ImageReader, normalize_image};
 pub use line::LineReader;
@@ -47,6 +49,9 @@ pub struct FileReader {  // of a single file
     pub images: HashMap<Uid, Vec<u8>>,
     config: BuildConfig,
 
+    // (heading path, symbol) of the section that the first token of `buffer` belongs to
+    curr_heading: Option<(String, Option<String>)>,
+
     // this is a cache, purely for optimizing `fetch_images_from_web()`
     fetched_images: HashMap<String, Uid>,  // HashMap<hash, image_uid>
 }
@@ -60,8 +65,10 @@ impl FileReader {
             "jsonl" => Box::new(LineReader::new(&real_path, &config)?),
             "csv" => Box::new(CsvReader::new(&real_path, &config)?),
             "pdf" => Box::new(PdfReader::new(&real_path, &config)?),
-
-            // "py" | "rs" => Box::new(CodeReader::new(&real_path, &config)?),
+            "rs" | "py" | "pyi" | "go"
+            | "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "mts" | "cts"
+            | "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx"
+            | "java" | "kt" | "kts" | "scala" | "cs" | "swift" => Box::new(CodeReader::new(&real_path, &config)?),
 
             // all the unknown extensions are treated as plain texts
             _ => Box::new(PlainTextReader::new(&real_path, &config)?),
@@ -74,6 +81,7 @@ impl FileReader {
             curr_buffer_size: 0,
             images: HashMap::new(),
             config,
+            curr_heading: None,
             fetched_images: HashMap::new(),
         })
     }
@@ -83,7 +91,8 @@ impl FileReader {
     }
 
     /// It moves the cursor and generates `Vec<AtomicToken>` for the next chunk.
-    /// It also collects images in the next chunk.
+    /// It also collects images in the next chunk. If the chunk belongs to a section,
+    /// the first token is `AtomicToken::Heading`.
     pub fn next_chunk(&mut self) -> Result<Vec<AtomicToken>, Error> {
         self.fill_buffer_until_chunks(2)?;
 
@@ -112,7 +121,48 @@ impl FileReader {
             chunk_deque.push_back(token);
         }
 
-        // step 2. create a sliding window
+        // If the chunk is full right before a separator, the separator belongs to
+        // this chunk. Otherwise, the next chunk would be nothing but the sliding
+        // window of this chunk.
+        if !has_separator && matches!(self.buffer.front(), Some(AtomicToken::Separator)) {
+            self.buffer.pop_front();
+            has_separator = true;
+        }
+
+        // step 2. if the last section of the chunk is cut in the middle, it cuts the
+        // chunk right before the heading of the section instead, so that the section
+        // is not splitted. It also does so if the chunk is too big because of a big
+        // code fence or a table. It doesn't cut the chunk if that makes the chunk too small.
+        if !has_separator && (self.is_section_cut() || curr_chunk_size > self.config.chunk_size * 2) {
+            let mut size_before = 0;
+            let mut cut_at = None;
+
+            for (i, token) in chunk_deque.iter().enumerate() {
+                if let AtomicToken::Heading { .. } = token {
+                    if i > 0 && size_before * 4 >= next_chunk_size {
+                        cut_at = Some(i);
+                    }
+                }
+
+                size_before += token.len(self.config.image_size);
+            }
+
+            if let Some(cut_at) = cut_at {
+                for token in chunk_deque.split_off(cut_at).into_iter().rev() {
+                    self.curr_buffer_size += token.len(self.config.image_size);
+                    self.buffer.push_front(token);
+                }
+
+                // there's no need for sliding window between sections
+                has_separator = true;
+            }
+        }
+
+        let is_in_section = self.curr_heading.is_some() || chunk_deque.iter().any(
+            |token| matches!(token, AtomicToken::Heading { .. })
+        );
+
+        // step 3. create a sliding window
         // if there's no remaining token, there's no need for sliding window
         // if the chunk consists of a single token, there's no point in making a sliding window
         if !has_separator && (!self.buffer.is_empty() || chunk_deque.len() == 1) {
@@ -120,6 +170,17 @@ impl FileReader {
             let mut curr_sliding_window_size = 0;
 
             while curr_sliding_window_size < self.config.slide_len && !chunk_deque.is_empty() {
+                match chunk_deque.back() {
+                    // A sliding window never goes beyond a heading, so that the
+                    // tokens in the window belong to the same section.
+                    Some(AtomicToken::Heading { .. }) => { break; },
+
+                    // A code fence or a table in a section is a single big token.
+                    // It's not worth duplicating the entire block for a sliding window.
+                    Some(token) if is_in_section && token.len(self.config.image_size) > self.config.slide_len => { break; },
+                    _ => {},
+                }
+
                 let token = chunk_deque.pop_back().unwrap();
                 curr_sliding_window_size += token.len(self.config.image_size);
                 self.buffer.push_front(token.clone());
@@ -138,7 +199,30 @@ impl FileReader {
             }
         }
 
-        let tokens = merge_tokens(chunk_deque);
+        // A heading before any visible token is the heading of this chunk.
+        // Otherwise, the chunk continues the section of the previous chunk.
+        let mut heading = self.curr_heading.clone();
+
+        for token in chunk_deque.iter() {
+            match token {
+                AtomicToken::Heading { path, symbol } => { heading = get_section(path, symbol); },
+                AtomicToken::Separator => {},
+                AtomicToken::String { data, .. } if data.trim().is_empty() => {},
+                _ => { break; },
+            }
+        }
+
+        for token in chunk_deque.iter() {
+            if let AtomicToken::Heading { path, symbol } = token {
+                self.curr_heading = get_section(path, symbol);
+            }
+        }
+
+        let mut tokens = merge_tokens(chunk_deque);
+
+        if let Some((path, symbol)) = heading {
+            tokens.insert(0, AtomicToken::Heading { path, symbol });
+        }
 
         for token in tokens.iter() {
             if let AtomicToken::Image(Image { uid, bytes, .. }) = token {
@@ -149,6 +233,19 @@ impl FileReader {
         Ok(tokens)
     }
 
+    // If there's a visible token in `buffer` before the next heading, the section
+    // that the last chunk belongs to is not finished yet.
+    fn is_section_cut(&self) -> bool {
+        self.buffer.iter().take_while(
+            |token| !matches!(token, AtomicToken::Heading { .. } | AtomicToken::Separator)
+        ).any(
+            |token| match token {
+                AtomicToken::String { data, .. } => !data.trim().is_empty(),
+                _ => true,
+            }
+        )
+    }
+
     pub async fn generate_chunk(
         &mut self,
         index: &Index,
@@ -246,6 +343,17 @@ impl FileReader {
     }
 }
 
+// An empty path means that the tokens don't belong to any section.
+fn get_section(path: &str, symbol: &Option<String>) -> Option<(String, Option<String>)> {
+    if path.is_empty() {
+        None
+    }
+
+    else {
+        Some((path.to_string(), symbol.clone()))
+    }
+}
+
 fn merge_tokens(tokens: VecDeque<AtomicToken>) -> Vec<AtomicToken> {
     let mut buffer = vec![];
     let mut result = vec![];
@@ -267,7 +375,7 @@ fn merge_tokens(tokens: VecDeque<AtomicToken>) -> Vec<AtomicToken> {
             },
 
             // not rendered
-            AtomicToken::Separator => {},
+            AtomicToken::Separator | AtomicToken::Heading { .. } => {},
         }
     }
 
@@ -319,6 +427,20 @@ pub enum AtomicToken {
     /// after a separator will never belong to the
     /// same chunk.
     Separator,
+
+    /// It's an invisible AtomicToken. It marks the start of
+    /// a section, and `path` is the heading path of the
+    /// section, like `# A > ## B`. `FileReader` prefers to
+    /// start a new chunk at this token, and the chunk
+    /// remembers `path` and `symbol`.
+    ///
+    /// In source code, a section is an item (e.g. a function),
+    /// `path` is the signatures of the item and its enclosing
+    /// items, and `symbol` is the name of the item, like
+    /// `Index::build`. In a pdf file, a section is a page, and
+    /// `path` is like `page 3`. An empty `path` means that the
+    /// following tokens don't belong to any section.
+    Heading { path: String, symbol: Option<String> },
 }
 
 impl AtomicToken {
@@ -327,7 +449,7 @@ impl AtomicToken {
             AtomicToken::String { char_len, .. } => *char_len,
             AtomicToken::Image(_) => image_size,
             AtomicToken::WebImage { .. } => image_size,
-            AtomicToken::Separator => 0,
+            AtomicToken::Separator | AtomicToken::Heading { .. } => 0,
         }
     }
 }
@@ -345,8 +467,84 @@ impl From<AtomicToken> for MessageContent {
             // If this branch is reached, that means it's failed to fetch the image.
             AtomicToken::WebImage { desc, url, hash: _ } => MessageContent::String(format!("![{desc}]({url})")),
 
-            // this branch is not supposed to be reached
-            AtomicToken::Separator => MessageContent::String(String::new()),
+            // these branches are not supposed to be reached
+            AtomicToken::Separator | AtomicToken::Heading { .. } => MessageContent::String(String::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AtomicToken, FileReader, FileReaderImpl};
+    use crate::error::Error;
+    use crate::index::BuildConfig;
+    use std::collections::{HashMap, VecDeque};
+
+    struct TokenReader {
+        tokens: Vec<AtomicToken>,
+    }
+
+    impl FileReaderImpl for TokenReader {
+        fn new(_path: &str, _config: &BuildConfig) -> Result<Self, Error> {
+            Ok(TokenReader { tokens: vec![] })
+        }
+
+        fn pop_all_tokens(&mut self) -> Result<Vec<AtomicToken>, Error> {
+            Ok(std::mem::take(&mut self.tokens))
+        }
+
+        fn load_tokens(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_more_to_read(&self) -> bool {
+            !self.tokens.is_empty()
+        }
+
+        fn key(&self) -> String {
+            String::from("token_reader_v0")
         }
     }
+
+    fn string(s: &str) -> AtomicToken {
+        AtomicToken::String { data: s.to_string(), char_len: s.chars().count() }
+    }
+
+    #[test]
+    fn separator_after_full_chunk_test() {
+        let config = BuildConfig {
+            chunk_size: 10,
+            slide_len: 3,
+            ..BuildConfig::default()
+        };
+        let mut tokens = "abcdefghij".chars().map(|c| string(&c.to_string())).collect::<Vec<_>>();
+        tokens.push(AtomicToken::Separator);
+        tokens.extend("klmnopqrst".chars().map(|c| string(&c.to_string())));
+
+        let mut reader = FileReader {
+            rel_path: String::from("test.txt"),
+            inner: Box::new(TokenReader { tokens }),
+            buffer: VecDeque::new(),
+            curr_buffer_size: 0,
+            images: HashMap::new(),
+            config,
+            curr_heading: None,
+            fetched_images: HashMap::new(),
+        };
+        let mut chunks = vec![];
+
+        while reader.can_generate_chunk() {
+            let chunk = reader.next_chunk().unwrap().into_iter().map(
+                |token| match token {
+                    AtomicToken::String { data, .. } => data,
+                    _ => panic!(),
+                }
+            ).collect::<Vec<_>>().concat();
+            chunks.push(chunk);
+        }
+
+        // The first chunk is full right before the separator, so there's no
+        // sliding window, and the next chunk starts after the separator.
+        assert_eq!(chunks, vec!["abcdefghij", "klmnopqrst"]);
+    }
 }
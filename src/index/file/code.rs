@@ -0,0 +1,739 @@
+use super::{AtomicToken, FileReaderImpl};
+use crate::error::Error;
+use crate::index::BuildConfig;
+use lazy_static::lazy_static;
+use ragit_fs::{FileError, extension};
+use regex::Regex;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// It splits source code at item boundaries: functions, impls, classes... It doesn't
+// parse the code. It tracks braces (or indentation for python) and matches each line
+// against regexes. It's not perfect, but it's good enough for chunking.
+//
+// It emits `AtomicToken::Heading` before each item, so that `FileReader` prefers to
+// start a new chunk at the item. The path of the heading is the signatures of the item
+// and the items that enclose it, like `impl Index > pub fn build(&mut self)`. If a chunk
+// starts in the middle of an item, the chunk still knows the signatures. Comments and
+// attributes right before an item belong to the item.
+//
+// It only looks at top-level items and the items inside containers (impls, traits,
+// classes, modules, namespaces...). Closures and nested functions are not items.
+pub struct CodeReader {
+    lines: BufReader<File>,
+    language: Language,
+    tokens: Vec<AtomicToken>,
+    is_exhausted: bool,
+    strict_mode: bool,
+    lexer: Lexer,
+
+    // The first one is the file itself, and the others are containers.
+    scopes: Vec<Scope>,
+
+    // comments and attributes that might belong to the next item
+    pending: Vec<String>,
+
+    // path of the last `AtomicToken::Heading`
+    curr_path: String,
+}
+
+impl FileReaderImpl for CodeReader {
+    fn new(path: &str, config: &BuildConfig) -> Result<Self, Error> {
+        let language = match Language::from_extension(&extension(path)?.unwrap_or(String::new())) {
+            Some(language) => language,
+            None => {
+                return Err(Error::FileReaderError(format!("`{path}` is not a source code file.")));
+            },
+        };
+
+        match File::open(path) {
+            Ok(f) => Ok(CodeReader {
+                lines: BufReader::new(f),
+                language,
+                tokens: vec![],
+                is_exhausted: false,
+                strict_mode: config.strict_file_reader,
+                lexer: Lexer::default(),
+                scopes: vec![Scope::root()],
+                pending: vec![],
+                curr_path: String::new(),
+            }),
+            Err(e) => Err(FileError::from_std(e, path).into()),
+        }
+    }
+
+    fn load_tokens(&mut self) -> Result<(), Error> {
+        if self.is_exhausted {
+            return Ok(());
+        }
+
+        for _ in 0..64 {
+            // NOTE: `line` includes a newline character
+            let mut bytes = vec![];
+
+            if self.lines.read_until(b'\n', &mut bytes)? == 0 {
+                self.is_exhausted = true;
+                let pending = std::mem::take(&mut self.pending);
+                self.push_lines(pending);
+                break;
+            }
+
+            let line = if self.strict_mode {
+                String::from_utf8(bytes)?
+            } else {
+                String::from_utf8_lossy(&bytes).to_string()
+            };
+
+            self.consume_line(line);
+        }
+
+        Ok(())
+    }
+
+    fn pop_all_tokens(&mut self) -> Result<Vec<AtomicToken>, Error> {
+        let mut result = vec![];
+        std::mem::swap(&mut self.tokens, &mut result);
+        Ok(result)
+    }
+
+    fn has_more_to_read(&self) -> bool {
+        !self.is_exhausted
+    }
+
+    fn key(&self) -> String {
+        String::from("code_reader_v0")
+    }
+}
+
+impl CodeReader {
+    fn consume_line(&mut self, line: String) {
+        let trimmed = line.trim().to_string();
+        let indent = get_indent(&line);
+        let is_in_code = self.lexer.is_in_code();
+        let depth = self.lexer.depth;
+        let paren_depth = self.lexer.paren_depth;
+        let is_significant = is_in_code && paren_depth == 0 && !trimmed.is_empty() && !self.language.is_comment_or_attribute(&trimmed);
+
+        // step 1. leave the containers that are finished
+        if self.language.uses_indentation() {
+            if is_significant {
+                while self.scopes.len() > 1 && indent <= self.scopes.last().unwrap().indent {
+                    self.pop_scope();
+                }
+
+                let scope = self.scopes.last_mut().unwrap();
+
+                if !scope.is_opened && indent > scope.indent {
+                    scope.body_level = indent;
+                    scope.is_opened = true;
+                }
+            }
+        }
+
+        else {
+            while self.scopes.len() > 1 {
+                let scope = self.scopes.last().unwrap();
+
+                // If a container doesn't have a body (e.g. `class Point(val x: Int)` in Kotlin),
+                // it ends when another item starts.
+                if depth < scope.body_level && (scope.is_opened || is_significant && self.language.match_item(&trimmed, true).is_some()) {
+                    self.pop_scope();
+                }
+
+                else {
+                    break;
+                }
+            }
+        }
+
+        let scope = self.scopes.last().unwrap();
+        let is_item_level = is_in_code && paren_depth == 0 && scope.is_opened && if self.language.uses_indentation() {
+            indent == scope.body_level
+        } else {
+            depth == scope.body_level
+        };
+
+        // step 2. find items
+        if is_item_level && !trimmed.is_empty() {
+            if self.language.is_comment_or_attribute(&trimmed) {
+                self.pending.push(line.clone());
+            }
+
+            else if let Some((item, is_container)) = self.language.match_item(&trimmed, self.scopes.len() > 1) {
+                let signature = normalize_signature(&trimmed, self.language.uses_indentation());
+                let mut path = self.scopes.iter().filter_map(|scope| scope.signature.clone()).collect::<Vec<_>>();
+                let mut symbol = self.scopes.iter().filter_map(|scope| scope.symbol.clone()).collect::<Vec<_>>();
+                path.push(signature.clone());
+                symbol.push(item.clone());
+                self.emit_heading(path.join(" > "), Some(symbol.join(self.language.symbol_separator())));
+                let pending = std::mem::take(&mut self.pending);
+                self.push_lines(pending);
+                self.push_lines(vec![line.clone()]);
+
+                let scope = self.scopes.last_mut().unwrap();
+                scope.is_item_open = true;
+                scope.has_item_body = false;
+
+                if is_container {
+                    self.scopes.push(Scope {
+                        signature: Some(signature),
+                        symbol: Some(item),
+                        indent,
+                        body_level: depth + 1,
+                        is_opened: false,
+                        is_item_open: false,
+                        has_item_body: false,
+                    });
+                }
+            }
+
+            else {
+                // A statement between items (e.g. `use std::io;` or `if __name__ == "__main__":`)
+                // doesn't belong to the previous item. But a line that continues the previous
+                // item (e.g. a `where` clause) does.
+                // In python, an item always ends when a statement with the same indentation appears.
+                if (self.language.uses_indentation() || !self.scopes.last().unwrap().is_item_open) && !trimmed.starts_with(['}', ')', ']']) {
+                    let path = self.scopes.iter().filter_map(|scope| scope.signature.clone()).collect::<Vec<_>>();
+                    let symbol = self.scopes.iter().filter_map(|scope| scope.symbol.clone()).collect::<Vec<_>>();
+                    self.emit_heading(
+                        path.join(" > "),
+                        if symbol.is_empty() { None } else { Some(symbol.join(self.language.symbol_separator())) },
+                    );
+                }
+
+                let pending = std::mem::take(&mut self.pending);
+                self.push_lines(pending);
+                self.push_lines(vec![line.clone()]);
+            }
+        }
+
+        else if !self.pending.is_empty() {
+            self.pending.push(line.clone());
+        }
+
+        else {
+            self.push_lines(vec![line.clone()]);
+        }
+
+        // step 3. update the states
+        self.lexer.feed(&line, self.language);
+
+        if !self.language.uses_indentation() {
+            let lexer = &self.lexer;
+            let scope = self.scopes.last_mut().unwrap();
+
+            if !scope.is_opened && lexer.max_depth >= scope.body_level {
+                scope.is_opened = true;
+            }
+
+            if scope.is_item_open {
+                if lexer.max_depth > scope.body_level {
+                    scope.has_item_body = true;
+                }
+
+                if lexer.depth == scope.body_level && lexer.paren_depth == 0 && (scope.has_item_body || trimmed.ends_with(';')) {
+                    scope.is_item_open = false;
+                }
+            }
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+
+        // The container is an item of its parent, and it's finished.
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.is_item_open = false;
+        }
+    }
+
+    fn emit_heading(&mut self, path: String, symbol: Option<String>) {
+        if path != self.curr_path {
+            self.curr_path = path.clone();
+            self.tokens.push(AtomicToken::Heading { path, symbol });
+        }
+    }
+
+    fn push_lines(&mut self, lines: Vec<String>) {
+        for line in lines.into_iter() {
+            for s in split_long_line(line) {
+                self.tokens.push(AtomicToken::String {
+                    char_len: s.chars().count(),
+                    data: s,
+                });
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Language {
+    Rust,
+    Python,
+    Go,
+
+    // javascript and typescript
+    JavaScript,
+
+    // c and c++
+    C,
+
+    // java, kotlin, scala, c# and swift
+    Java,
+}
+
+impl Language {
+    fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "rs" => Some(Language::Rust),
+            "py" | "pyi" => Some(Language::Python),
+            "go" => Some(Language::Go),
+            "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "mts" | "cts" => Some(Language::JavaScript),
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => Some(Language::C),
+            "java" | "kt" | "kts" | "scala" | "cs" | "swift" => Some(Language::Java),
+            _ => None,
+        }
+    }
+
+    fn uses_indentation(&self) -> bool {
+        matches!(self, Language::Python)
+    }
+
+    fn symbol_separator(&self) -> &'static str {
+        match self {
+            Language::Rust | Language::C => "::",
+            _ => ".",
+        }
+    }
+
+    fn is_comment_or_attribute(&self, line: &str) -> bool {
+        match self {
+            Language::Python => line.starts_with('#') || line.starts_with('@'),
+            Language::Rust => line.starts_with("//") || line.starts_with("/*") || line.starts_with('*') || line.starts_with("#["),
+            _ => line.starts_with("//") || line.starts_with("/*") || line.starts_with('*') || line.starts_with('@'),
+        }
+    }
+
+    // It returns (name, is_container).
+    fn match_item(&self, line: &str, is_inner: bool) -> Option<(String, bool)> {
+        let patterns: &[(Regex, bool, bool)] = match self {
+            Language::Rust => &RUST_ITEMS,
+            Language::Python => &PYTHON_ITEMS,
+            Language::Go => &GO_ITEMS,
+            Language::JavaScript => &JAVASCRIPT_ITEMS,
+            Language::C => &C_ITEMS,
+            Language::Java => &JAVA_ITEMS,
+        };
+
+        for (pattern, is_container, is_inner_only) in patterns.iter() {
+            if *is_inner_only && (!is_inner || line.ends_with(';')) {
+                continue;
+            }
+
+            if let Some(cap) = pattern.captures(line) {
+                let name = cap.name("name").unwrap().as_str();
+
+                if ["if", "for", "while", "switch", "catch", "return", "sizeof", "else"].contains(&name) {
+                    continue;
+                }
+
+                return match cap.name("recv") {
+                    Some(recv) => Some((format!("{}.{name}", recv.as_str()), *is_container)),
+                    None => Some((name.to_string(), *is_container)),
+                };
+            }
+        }
+
+        None
+    }
+}
+
+lazy_static! {
+    // (pattern, is_container, is_inner_only)
+    // The patterns are matched against a trimmed line, in order.
+    static ref RUST_ITEMS: Vec<(Regex, bool, bool)> = vec![
+        (Regex::new(r"^(?:pub(?:\([^)]*\))?\s+)?(?:unsafe\s+)?(?:auto\s+)?trait\s+(?P<name>\w+)").unwrap(), true, false),
+        (Regex::new(r"^(?:unsafe\s+)?impl\b(?:\s*<.*?>)?\s*(?:[\w:<>, '&]+?\s+for\s+)?(?P<name>[\w:]+)").unwrap(), true, false),
+        (Regex::new(r"^(?:pub(?:\([^)]*\))?\s+)?mod\s+(?P<name>\w+)\s*\{").unwrap(), true, false),
+        (Regex::new(r#"^(?:pub(?:\([^)]*\))?\s+)?(?:(?:const|async|unsafe|default|extern\s+"[^"]*")\s+)*fn\s+(?P<name>\w+)"#).unwrap(), false, false),
+        (Regex::new(r"^(?:pub(?:\([^)]*\))?\s+)?(?:struct|enum|union|type|static|const)\s+(?:mut\s+)?(?P<name>\w+)").unwrap(), false, false),
+        (Regex::new(r"^macro_rules!\s*(?P<name>\w+)").unwrap(), false, false),
+    ];
+    static ref PYTHON_ITEMS: Vec<(Regex, bool, bool)> = vec![
+        (Regex::new(r"^class\s+(?P<name>\w+)").unwrap(), true, false),
+        (Regex::new(r"^(?:async\s+)?def\s+(?P<name>\w+)").unwrap(), false, false),
+    ];
+    static ref GO_ITEMS: Vec<(Regex, bool, bool)> = vec![
+        (Regex::new(r"^func\s*\(\s*(?:\w+\s+)?\*?\s*(?P<recv>\w+)[^)]*\)\s*(?P<name>\w+)").unwrap(), false, false),
+        (Regex::new(r"^func\s+(?P<name>\w+)").unwrap(), false, false),
+        (Regex::new(r"^type\s+(?P<name>\w+)").unwrap(), false, false),
+        (Regex::new(r"^(?:var|const)\s+(?P<name>\w+)").unwrap(), false, false),
+    ];
+    static ref JAVASCRIPT_ITEMS: Vec<(Regex, bool, bool)> = vec![
+        (Regex::new(r"^(?:export\s+)?(?:default\s+)?(?:declare\s+)?(?:abstract\s+)?class\s+(?P<name>\w+)").unwrap(), true, false),
+        (Regex::new(r"^(?:export\s+)?(?:declare\s+)?(?:namespace|module)\s+(?P<name>[\w.]+)").unwrap(), true, false),
+        (Regex::new(r"^(?:export\s+)?(?:default\s+)?(?:declare\s+)?(?:async\s+)?function\s*\*?\s*(?P<name>\w+)").unwrap(), false, false),
+        (Regex::new(r"^(?:export\s+)?(?:declare\s+)?(?:const\s+)?(?:interface|enum|type)\s+(?P<name>\w+)").unwrap(), false, false),
+        (Regex::new(r"^(?:export\s+)?(?:const|let|var)\s+(?P<name>\w+)\s*(?::[^=]*)?=\s*(?:async\s+)?(?:function\b|\(|\w+\s*=>)").unwrap(), false, false),
+
+        // methods
+        (Regex::new(r"^(?:(?:public|private|protected|static|readonly|abstract|override|declare|async|get|set)\s+)*\*?\s*(?P<name>#?\w+)\s*(?:<[^>]*>)?\s*\(").unwrap(), false, true),
+    ];
+    static ref C_ITEMS: Vec<(Regex, bool, bool)> = vec![
+        (Regex::new(r"^(?:template\s*<.*>\s*)?(?:class|struct|union)\s+(?:\w+\s+)*?(?P<name>\w+)\s*(?:final\s*)?(?::[^;]*)?\{?\s*$").unwrap(), true, false),
+        (Regex::new(r"^namespace\s+(?P<name>[\w:]+)").unwrap(), true, false),
+        (Regex::new(r"^(?:typedef\s+)?enum\s+(?:class\s+|struct\s+)?(?P<name>\w+)[^;]*$").unwrap(), false, false),
+        (Regex::new(r"^(?:[\w:<>,*&~]+\s+)+[*&]*(?P<name>[\w:~]+)\s*\([^;]*$").unwrap(), false, false),
+
+        // constructors and destructors
+        (Regex::new(r"^(?P<name>\w+::~?\w+)\s*\([^;]*$").unwrap(), false, false),
+        (Regex::new(r"^(?:(?:virtual|static|inline|explicit|constexpr)\s+)*(?P<name>~?\w+)\s*\([^;]*$").unwrap(), false, true),
+    ];
+    static ref JAVA_ITEMS: Vec<(Regex, bool, bool)> = vec![
+        (Regex::new(r"^(?:(?:public|private|protected|internal|static|final|abstract|sealed|open|data|inner|partial|fileprivate|override|enum|annotation|value|case)\s+)*(?:class|interface|enum|record|struct|object|trait|extension|protocol|namespace)\s+(?P<name>[\w.]+)").unwrap(), true, false),
+        (Regex::new(r"^(?:(?:public|private|protected|internal|static|final|abstract|synchronized|native|override|open|virtual|async|suspend|inline|operator|infix|tailrec|mutating|fileprivate|unsafe|extern|new|sealed|partial|default)\s+)*(?:fun|func|def)\s+(?:<[^>]*>\s*)?(?:[\w.]+\.)?(?P<name>\w+)").unwrap(), false, false),
+        (Regex::new(r"^(?:(?:public|private|protected|internal|static|final|abstract|synchronized|native|override|virtual|async|sealed|unsafe|extern|new|default)\s+)*(?:<[^>]*>\s+)?[\w<>\[\],.?]+\s+(?P<name>\w+)\s*\([^;]*$").unwrap(), false, false),
+    ];
+}
+
+struct Scope {
+    // `None` for the file itself
+    signature: Option<String>,
+    symbol: Option<String>,
+
+    // indentation of the line that opened the scope
+    indent: usize,
+
+    // An item in this scope has this brace depth (or indentation for python).
+    body_level: usize,
+
+    // Whether the body of the container has started (e.g. `{` is found).
+    is_opened: bool,
+
+    // Whether the last item in this scope is not finished yet.
+    is_item_open: bool,
+
+    // Whether the last item in this scope has a body (`{ ... }`).
+    has_item_body: bool,
+}
+
+impl Scope {
+    fn root() -> Self {
+        Scope {
+            signature: None,
+            symbol: None,
+            indent: 0,
+            body_level: 0,
+            is_opened: true,
+            is_item_open: false,
+            has_item_body: false,
+        }
+    }
+}
+
+// It only cares about braces, parenthesis, comments and string literals.
+#[derive(Default)]
+struct Lexer {
+    // `{` and `}`, except python
+    depth: usize,
+
+    // `(`, `)`, `[` and `]`, and braces in python
+    paren_depth: usize,
+
+    // the biggest `depth` in the last line
+    max_depth: usize,
+
+    state: LexState,
+}
+
+#[derive(Default)]
+enum LexState {
+    #[default]
+    Code,
+    BlockComment,
+    String(char),
+    TripleQuotedString(char),
+}
+
+impl Lexer {
+    fn is_in_code(&self) -> bool {
+        matches!(self.state, LexState::Code)
+    }
+
+    fn feed(&mut self, line: &str, language: Language) {
+        let chars = line.chars().collect::<Vec<_>>();
+        let mut i = 0;
+        self.max_depth = self.depth;
+
+        while i < chars.len() {
+            let c = chars[i];
+
+            match self.state {
+                LexState::BlockComment => {
+                    if c == '*' && chars.get(i + 1) == Some(&'/') {
+                        self.state = LexState::Code;
+                        i += 1;
+                    }
+                },
+                LexState::String(quote) => {
+                    if c == '\\' {
+                        i += 1;
+                    }
+
+                    else if c == quote {
+                        self.state = LexState::Code;
+                    }
+                },
+                LexState::TripleQuotedString(quote) => {
+                    if chars[i..].starts_with(&[quote; 3]) {
+                        self.state = LexState::Code;
+                        i += 2;
+                    }
+                },
+                LexState::Code => match c {
+                    '#' if language == Language::Python => {
+                        break;
+                    },
+                    '/' if language != Language::Python && chars.get(i + 1) == Some(&'/') => {
+                        break;
+                    },
+                    '/' if language != Language::Python && chars.get(i + 1) == Some(&'*') => {
+                        self.state = LexState::BlockComment;
+                        i += 1;
+                    },
+                    '"' | '\'' if language == Language::Python && chars[i..].starts_with(&[c; 3]) => {
+                        self.state = LexState::TripleQuotedString(c);
+                        i += 2;
+                    },
+                    '"' => {
+                        self.state = LexState::String(c);
+                    },
+                    '`' if matches!(language, Language::JavaScript | Language::Go) => {
+                        self.state = LexState::String(c);
+                    },
+                    '\'' if matches!(language, Language::JavaScript | Language::Python) => {
+                        self.state = LexState::String(c);
+                    },
+
+                    // In the other languages, it's either a character literal or a lifetime (rust).
+                    '\'' => {
+                        if chars.get(i + 1) == Some(&'\\') {
+                            if let Some(end) = chars[(i + 2)..].iter().take(10).position(|c| *c == '\'') {
+                                i += end + 2;
+                            }
+                        }
+
+                        else if chars.get(i + 2) == Some(&'\'') {
+                            i += 2;
+                        }
+                    },
+                    '{' if language != Language::Python => {
+                        self.depth += 1;
+                        self.max_depth = self.max_depth.max(self.depth);
+                    },
+                    '}' if language != Language::Python => {
+                        self.depth = self.depth.saturating_sub(1);
+                    },
+                    '(' | '[' | '{' => {
+                        self.paren_depth += 1;
+                    },
+                    ')' | ']' | '}' => {
+                        self.paren_depth = self.paren_depth.saturating_sub(1);
+                    },
+                    _ => {},
+                },
+            }
+
+            i += 1;
+        }
+
+        // Only some languages have multi-line string literals with a single quote.
+        if let LexState::String(quote) = self.state {
+            if quote != '`' && language != Language::Rust {
+                self.state = LexState::Code;
+            }
+        }
+    }
+}
+
+fn get_indent(line: &str) -> usize {
+    let mut result = 0;
+
+    for c in line.chars() {
+        match c {
+            ' ' => { result += 1; },
+            '\t' => { result += 4; },
+            _ => { break; },
+        }
+    }
+
+    result
+}
+
+// `pub fn foo(&self) -> u32 {` -> `pub fn foo(&self) -> u32`
+// `def foo(self):` -> `def foo(self)`
+fn normalize_signature(line: &str, uses_indentation: bool) -> String {
+    let mut result = line.trim().to_string();
+
+    if uses_indentation {
+        result = result.trim_end_matches(':').to_string();
+    }
+
+    // It removes the body, if the body starts in the same line.
+    else {
+        let mut paren_depth = 0;
+
+        for (i, c) in result.char_indices() {
+            match c {
+                '(' | '[' => { paren_depth += 1; },
+                ')' | ']' => { paren_depth -= 1; },
+                '{' if paren_depth == 0 => {
+                    result = result[..i].to_string();
+                    break;
+                },
+                _ => {},
+            }
+        }
+    }
+
+    let result = result.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if result.chars().count() > 120 {
+        format!("{}...", result.chars().take(120).collect::<String>())
+    }
+
+    else {
+        result
+    }
+}
+
+// A minified file may have a very long line.
+fn split_long_line(line: String) -> Vec<String> {
+    if line.len() <= 1024 {
+        return vec![line];
+    }
+
+    let chars = line.chars().collect::<Vec<_>>();
+    chars.chunks(256).map(|chunk| chunk.iter().collect::<String>()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{AtomicToken, FileReaderImpl};
+    use super::CodeReader;
+    use crate::index::BuildConfig;
+    use ragit_fs::{WriteMode, remove_file, write_string};
+
+    fn read_headings(path: &str, code: &str) -> Vec<(String, Option<String>)> {
+        write_string(path, code, WriteMode::AlwaysCreate).unwrap();
+        let mut reader = CodeReader::new(path, &BuildConfig::default()).unwrap();
+
+        while reader.has_more_to_read() {
+            reader.load_tokens().unwrap();
+        }
+
+        let tokens = reader.pop_all_tokens().unwrap();
+        let mut strings = vec![];
+        let mut headings = vec![];
+
+        for token in tokens.into_iter() {
+            match token {
+                AtomicToken::String { data, .. } => { strings.push(data); },
+                AtomicToken::Heading { path, symbol } => { headings.push((path, symbol)); },
+                _ => panic!(),
+            }
+        }
+
+        // it doesn't lose any content
+        assert_eq!(strings.concat(), code);
+        remove_file(path).unwrap();
+        headings
+    }
+
+    #[test]
+    fn rust_code_test() {
+        let code = r#"use std::io;
+
+/// A point.
+#[derive(Clone)]
+pub struct Point {
+    x: i32,
+}
+
+impl<T: Clone> Foo<T> for Point
+where
+    T: Default,
+{
+    fn foo(&self) -> char {
+        let c = '{';
+        "}".len();
+        c
+    }
+
+    // not an item
+    pub async fn bar<'a>(&'a self) {}
+}
+
+fn main() {
+    println!("{}", 1);
+}
+
+const N: usize = 3;
+static S: &str = "a";
+"#;
+        let headings = read_headings("__tmp_test_code.rs", code);
+        let headings = headings.iter().map(|(path, symbol)| (path.as_str(), symbol.as_ref().map(|s| s.as_str()))).collect::<Vec<_>>();
+
+        assert_eq!(
+            headings,
+            vec![
+                ("pub struct Point", Some("Point")),
+                ("impl<T: Clone> Foo<T> for Point", Some("Point")),
+                ("impl<T: Clone> Foo<T> for Point > fn foo(&self) -> char", Some("Point::foo")),
+                ("impl<T: Clone> Foo<T> for Point > pub async fn bar<'a>(&'a self)", Some("Point::bar")),
+                ("fn main()", Some("main")),
+                ("const N: usize = 3;", Some("N")),
+                ("static S: &str = \"a\";", Some("S")),
+            ],
+        );
+    }
+
+    #[test]
+    fn python_code_test() {
+        let code = r#"import os
+
+@decorator
+class Foo(Base):
+    """
+def not_a_function():
+    """
+
+    def bar(self, a: int) -> int:
+        return a
+
+    async def baz(
+        self,
+    ):
+        pass
+
+def main():
+    pass
+
+if __name__ == "__main__":
+    main()
+"#;
+        let headings = read_headings("__tmp_test_code.py", code);
+        let headings = headings.iter().map(|(path, symbol)| (path.as_str(), symbol.as_ref().map(|s| s.as_str()))).collect::<Vec<_>>();
+
+        assert_eq!(
+            headings,
+            vec![
+                ("class Foo(Base)", Some("Foo")),
+                ("class Foo(Base) > def bar(self, a: int) -> int", Some("Foo.bar")),
+                ("class Foo(Base) > async def baz(", Some("Foo.baz")),
+                ("def main()", Some("main")),
+                ("", None),
+            ],
+        );
+    }
+}
@@ -111,7 +111,7 @@ impl FileReaderImpl for ImageReader {
     }
 }
 
-#[derive(Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ImageDescription {
     pub extracted_text: String,
     pub explanation: String,
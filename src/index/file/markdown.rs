@@ -15,6 +15,8 @@ lazy_static! {
     static ref FENCE_RE: Regex = Regex::new(r"(\s*)(\`{3,}|\~{3,})([^`]*)").unwrap();
     static ref DEF_RE: Regex = Regex::new(r"\s{0,3}\[([^\[\]]{1,999})\]\s?\:\s?(.+)").unwrap();
     static ref WEB_URL_RE: Regex = Regex::new(r"[a-zA-Z]+\:\/\/.+\/.+").unwrap();
+    static ref HEADING_RE: Regex = Regex::new(r"^\s{0,3}(\#{1,6})(?:[ \t]+(.*?))?(?:[ \t]+\#+)?[ \t]*$").unwrap();
+    static ref TABLE_DELIMITER_RE: Regex = Regex::new(r"^\s{0,3}\|?\s*\:?\-+\:?\s*(\|\s*\:?\-+\:?\s*)*\|?\s*$").unwrap();
 }
 
 pub struct MarkdownReader {
@@ -25,6 +27,18 @@ pub struct MarkdownReader {
     strict_mode: bool,
     curr_parse_state: ParseState,
     link_reference_definitions: HashMap<String, String>,
+
+    // below fields are only used when `structural_markdown_chunking` is set
+    structural_mode: bool,
+
+    // lines of the current code fence or table
+    block: Vec<String>,
+
+    // a line that might be a header row of a table
+    table_header: Option<String>,
+
+    // (level, title) of the headings that the current line belongs to
+    headings: Vec<(usize, String)>,
 }
 
 impl FileReaderImpl for MarkdownReader {
@@ -38,6 +52,10 @@ impl FileReaderImpl for MarkdownReader {
                 strict_mode: config.strict_file_reader,
                 curr_parse_state: ParseState::Paragraph,
                 link_reference_definitions: HashMap::new(),
+                structural_mode: config.structural_markdown_chunking,
+                block: vec![],
+                table_header: None,
+                headings: vec![],
             }),
             Err(e) => Err(FileError::from_std(e, path).into()),
         }
@@ -48,6 +66,10 @@ impl FileReaderImpl for MarkdownReader {
             return Ok(());
         }
 
+        if self.structural_mode {
+            return self.load_tokens_structural();
+        }
+
         let mut buffer = vec![];
 
         loop {
@@ -92,6 +114,9 @@ impl FileReaderImpl for MarkdownReader {
                     },
                     None => {},
                 },
+
+                // only the structural mode uses this state
+                ParseState::Table => unreachable!(),
             }
 
             buffer.push(StringOrImage::String(line));
@@ -111,11 +136,144 @@ impl FileReaderImpl for MarkdownReader {
     }
 
     fn key(&self) -> String {
-        String::from("markdown_reader_v0")
+        if self.structural_mode {
+            String::from("markdown_reader_structural_v0")
+        }
+
+        else {
+            String::from("markdown_reader_v0")
+        }
     }
 }
 
 impl MarkdownReader {
+    // It's like `load_tokens`, but
+    // 1. A code fence or a table becomes a single token, so that it's never splitted.
+    // 2. It emits `AtomicToken::Heading` before each ATX heading (`# title`). Setext
+    //    headings (a line underlined with `===` or `---`) are not recognized.
+    fn load_tokens_structural(&mut self) -> Result<(), Error> {
+        let mut buffer = vec![];
+
+        loop {
+            // NOTE: `line` includes a newline character
+            let mut line = String::new();
+
+            if self.lines.read_line(&mut line)? == 0 {
+                self.is_exhausted = true;
+
+                // an unclosed code fence or table ends here
+                if let Some(header) = self.table_header.take() {
+                    buffer.extend(parse_markdown_images(&header)?);
+                }
+
+                self.flush_block(&mut buffer)?;
+                self.consume_buffer(buffer)?;
+                break;
+            }
+
+            match &self.curr_parse_state {
+                ParseState::CodeFence(fence) => {
+                    let is_end = match parse_code_fence(&line) {
+                        Some(fence2) => match_fences(fence, &fence2),
+                        None => false,
+                    };
+                    self.block.push(line);
+
+                    if is_end {
+                        self.flush_block(&mut buffer)?;
+                    }
+
+                    continue;
+                },
+                ParseState::Table => {
+                    if !line.trim().is_empty() && line.contains('|') {
+                        self.block.push(line);
+                        continue;
+                    }
+
+                    // The table ends, and this line has to be parsed as a paragraph.
+                    self.flush_block(&mut buffer)?;
+                },
+                ParseState::Paragraph => {},
+            }
+
+            if let Some(header) = self.table_header.take() {
+                if TABLE_DELIMITER_RE.is_match(&line) && line.contains('|') {
+                    self.block = vec![header, line];
+                    self.curr_parse_state = ParseState::Table;
+                    continue;
+                }
+
+                buffer.extend(parse_markdown_images(&header)?);
+            }
+
+            if let Some(fence) = parse_code_fence(&line) {
+                self.block = vec![line];
+                self.curr_parse_state = ParseState::CodeFence(fence);
+                continue;
+            }
+
+            if let Some((label, destination)) = parse_link_reference_definition(&line) {
+                self.link_reference_definitions.insert(label, destination);
+                continue;
+            }
+
+            if let Some((level, title)) = parse_heading(&line) {
+                while let Some((last_level, _)) = self.headings.last() {
+                    if *last_level >= level {
+                        self.headings.pop();
+                    }
+
+                    else {
+                        break;
+                    }
+                }
+
+                self.headings.push((level, title));
+                buffer.push(StringOrImage::Heading(render_heading_path(&self.headings)));
+            }
+
+            // It might be a header row of a table. It can tell after reading the next line.
+            else if line.contains('|') {
+                self.table_header = Some(line);
+                continue;
+            }
+
+            buffer.extend(parse_markdown_images(&line)?);
+
+            if buffer.len() > 16 && !has_unknown_link_reference(&self.link_reference_definitions, &buffer) {
+                self.consume_buffer(buffer)?;
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush_block(&mut self, buffer: &mut Vec<StringOrImage>) -> Result<(), Error> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+
+        let block = self.block.concat();
+        self.block = vec![];
+
+        match &self.curr_parse_state {
+            ParseState::CodeFence(_) => {
+                buffer.push(StringOrImage::String(block));
+            },
+
+            // images in a table are still images
+            ParseState::Table => {
+                buffer.extend(parse_markdown_images(&block)?);
+            },
+            ParseState::Paragraph => unreachable!(),
+        }
+
+        self.curr_parse_state = ParseState::Paragraph;
+        Ok(())
+    }
+
     fn consume_buffer(&mut self, buffer: Vec<StringOrImage>) -> Result<(), Error> {
         for token in buffer.into_iter() {
             match token {
@@ -125,6 +283,9 @@ impl MarkdownReader {
                         data: s,
                     });
                 },
+                StringOrImage::Heading(path) => {
+                    self.tokens.push(AtomicToken::Heading { path, symbol: None });
+                },
                 _ => {
                     let (desc, mut url) = match token {
                         StringOrImage::ImageUrl { desc, url } => (desc, url),
@@ -191,6 +352,7 @@ impl MarkdownReader {
 enum ParseState {
     Paragraph,
     CodeFence(CodeFence),
+    Table,
 }
 
 struct CodeFence {
@@ -205,6 +367,7 @@ enum StringOrImage {
     String(String),
     ImageUrl { desc: String, url: String },    // ![desc](url)
     ImageRef { desc: String, r#ref: String },  // ![ref] or ![desc][ref]
+    Heading(String),  // heading path, like `# A > ## B`
 }
 
 // https://github.github.com/gfm/#fenced-code-blocks
@@ -225,6 +388,23 @@ fn parse_code_fence(line: &str) -> Option<CodeFence> {
     )
 }
 
+// https://github.github.com/gfm/#atx-headings
+// It returns (level, title).
+fn parse_heading(line: &str) -> Option<(usize, String)> {
+    HEADING_RE.captures(line.trim_end()).map(
+        |cap| (
+            cap[1].len(),
+            cap.get(2).map(|title| title.as_str().trim().to_string()).unwrap_or(String::new()),
+        )
+    )
+}
+
+fn render_heading_path(headings: &[(usize, String)]) -> String {
+    headings.iter().map(
+        |(level, title)| format!("{} {title}", "#".repeat(*level)).trim().to_string()
+    ).collect::<Vec<_>>().join(" > ")
+}
+
 fn match_fences(start: &CodeFence, end: &CodeFence) -> bool {
     start.fence_char == end.fence_char &&
     start.fence_len <= end.fence_len &&
@@ -503,4 +683,69 @@ This is a markdown file that has no image.
         );
         remove_file("__tmp_test.md").unwrap();
     }
+
+    #[test]
+    fn structural_markdown_test() {
+        let config = BuildConfig {
+            structural_markdown_chunking: true,
+            ..BuildConfig::default()
+        };
+        let md = "
+# A
+
+| x | y |
+|---|:-:|
+| 1 | 2 |
+
+## B
+
+```rust
+# not a heading
+fn main() {}
+```
+
+### C
+
+#### D ##
+
+## E
+";
+        write_string("__tmp_test_structural.md", md, WriteMode::AlwaysCreate).unwrap();
+        let mut md_reader = MarkdownReader::new("__tmp_test_structural.md", &config).unwrap();
+
+        while md_reader.has_more_to_read() {
+            md_reader.load_tokens().unwrap();
+        }
+
+        let tokens = md_reader.pop_all_tokens().unwrap();
+        let mut headings = vec![];
+        let mut strings = vec![];
+
+        for token in tokens.iter() {
+            match token {
+                AtomicToken::String { data, .. } => { strings.push(data.to_string()); },
+                AtomicToken::Heading { path, .. } => { headings.push(path.to_string()); },
+                _ => panic!(),
+            }
+        }
+
+        // it doesn't lose any content
+        assert_eq!(strings.concat(), md.to_string());
+
+        // code fences and tables are not splitted
+        assert!(strings.contains(&String::from("| x | y |\n|---|:-:|\n| 1 | 2 |\n")));
+        assert!(strings.contains(&String::from("```rust\n# not a heading\nfn main() {}\n```\n")));
+
+        assert_eq!(
+            headings,
+            vec![
+                "# A",
+                "# A > ## B",
+                "# A > ## B > ### C",
+                "# A > ## B > ### C > #### D",
+                "# A > ## E",
+            ],
+        );
+        remove_file("__tmp_test_structural.md").unwrap();
+    }
 }
@@ -1,79 +1,752 @@
-use super::{AtomicToken, FileReaderImpl, Image};
+use super::{AtomicToken, FileReaderImpl, Image, normalize_image};
 use crate::error::Error;
 use crate::index::BuildConfig;
 use crate::uid::Uid;
+use image::{DynamicImage, GrayImage, RgbImage};
+use lopdf::{Dictionary, Document, Encoding, Object, ObjectId, Stream};
+use lopdf::content::Content;
 use ragit_fs::{
+    basename,
     exists,
     extension,
     read_bytes,
     read_dir,
 };
 use ragit_pdl::ImageType;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::rc::Rc;
 
-// This is very very experimental pdf reader.
-// Its strategy is to 1) convert each page into images, 2) use image reader to build a knowledge-base.
-// This strategy works well, but the problem is that there's no easy-to-use rust library that converts
-// pdf page to images. For now, it uses a Python script to do that (see ./pdf.py).
+// It reads the text layer of a pdf file, page by page. Each page starts with an `AtomicToken::Heading`
+// whose path is `page N`, so that a chunk remembers which page it comes from. Images in a page
+// become `AtomicToken::Image`s at the place where they're drawn.
 //
-// Let's say there's a pdf file: `sample.pdf`. It expects the python script to make a directory `sample.pdf-pages/`.
-// The directory must contain the image files, and the file names must be sorted by their original order.
-// It creates 1 chunk per 1 image.
+// If a page has no text at all (e.g. a scanned document), it falls back to page rasters. Ragit cannot
+// render a pdf page by itself, so it looks for rasters that a python script has made (see ./pdf.py).
+// Let's say there's a pdf file: `sample.pdf`. The script makes a directory `sample.pdf-pages/`, and the
+// rasters of the first page are `sample.pdf-pages/page-0000.png` or `sample.pdf-pages/page-0000-chunk-xxxx.png`.
+// It creates 1 chunk per 1 raster. The script is optional. If there's no raster, it uses the images
+// embedded in the page, which is usually the scanned image itself.
 pub struct PdfReader {
-    images: Vec<AtomicToken>,
-    pages: Vec<String>,  // path to images
+    document: Document,
+    pages: Vec<ObjectId>,
+    rasters: Vec<String>,  // path to images
+    tokens: Vec<AtomicToken>,
     cursor: usize,
+    has_content: bool,
+    is_last_page_raster: bool,
+    is_after_image: bool,
+    path: String,
+    strict_mode: bool,
 }
 
 impl FileReaderImpl for PdfReader {
-    fn new(path: &str, _config: &BuildConfig) -> Result<Self, Error> {
+    fn new(path: &str, config: &BuildConfig) -> Result<Self, Error> {
+        let document = Document::load(path)?;
+        let pages = document.get_pages().into_values().collect();
         let pages_at = format!("{path}-pages/");
+        let rasters = if exists(&pages_at) { read_dir(&pages_at, true)? } else { vec![] };
 
-        if !exists(&pages_at) {
-            return Err(Error::FileReaderError(format!("`{pages_at}` not found! Ragit's pdf reader is still experimental and requires extra steps to run. Please run [this](https://github.com/baehyunsol/ragit/blob/main/src/index/file/pdf.py) python script and run ragit again.")));
-        }
-
-        let pages = read_dir(&pages_at, true)?;
         Ok(PdfReader {
-            images: vec![],
+            document,
             pages,
+            rasters,
+            tokens: vec![],
             cursor: 0,
+            has_content: false,
+            is_last_page_raster: false,
+            is_after_image: false,
+            path: path.to_string(),
+            strict_mode: config.strict_file_reader,
         })
     }
 
     fn load_tokens(&mut self) -> Result<(), Error> {
-        if self.cursor < self.pages.len() {
-            let path = &self.pages[self.cursor];
-            let bytes = read_bytes(path)?;
-            let uid = Uid::new_image(&bytes);
-            self.images.push(AtomicToken::Image(Image {
-                bytes,
-                image_type: ImageType::from_extension(&extension(path)?.unwrap_or(String::new()))?,
-                uid,
-            }));
+        if self.cursor >= self.pages.len() {
+            return Ok(());
+        }
 
-            self.cursor += 1;
+        let page_index = self.cursor;
+        self.cursor += 1;
+
+        let mut walker = PageWalker::new(&self.document, self.strict_mode, self.is_after_image);
+        walker.walk_page(self.pages[page_index])?;
+        let (tokens, has_text, is_after_image) = walker.finish();
+        let rasters = if has_text { vec![] } else { self.get_rasters(page_index)? };
+        let is_raster = !rasters.is_empty();
+        let mut page_tokens = vec![];
+
+        if rasters.is_empty() {
+            page_tokens = tokens;
+            self.is_after_image = is_after_image;
+        }
+
+        else {
+            for path in rasters.iter() {
+                if !page_tokens.is_empty() {
+                    page_tokens.push(AtomicToken::Separator);
+                }
+
+                let bytes = read_bytes(path)?;
+                let bytes = normalize_image(bytes, ImageType::from_extension(&extension(path)?.unwrap_or(String::new()))?)?;
+                let uid = Uid::new_image(&bytes);
+                page_tokens.push(AtomicToken::Image(Image {
+                    bytes,
+                    image_type: ImageType::Png,
+                    uid,
+                }));
+            }
+        }
+
+        if !page_tokens.is_empty() {
+            // A raster is not in the same chunk with the other pages.
+            if self.has_content && (is_raster || self.is_last_page_raster) {
+                self.tokens.push(AtomicToken::Separator);
+            }
+
+            self.tokens.push(AtomicToken::Heading {
+                path: format!("page {}", page_index + 1),
+                symbol: None,
+            });
+            self.tokens.extend(page_tokens);
+            self.has_content = true;
+            self.is_last_page_raster = is_raster;
+
+            if is_raster {
+                self.is_after_image = false;
+            }
+        }
+
+        if self.cursor == self.pages.len() && !self.has_content {
+            return Err(Error::FileReaderError(format!("`{}` has no text and no image. If it's a scanned document, please run [this](https://github.com/baehyunsol/ragit/blob/main/src/index/file/pdf.py) python script to render the pages, and run ragit again.", self.path)));
         }
 
         Ok(())
     }
 
     fn pop_all_tokens(&mut self) -> Result<Vec<AtomicToken>, Error> {
-        let mut result = Vec::with_capacity(self.images.len());
+        let mut result = vec![];
+        std::mem::swap(&mut self.tokens, &mut result);
+        Ok(result)
+    }
+
+    fn has_more_to_read(&self) -> bool {
+        self.cursor < self.pages.len()
+    }
+
+    fn key(&self) -> String {
+        String::from("pdf_reader_v1")
+    }
+}
+
+impl PdfReader {
+    // `page_index` starts from 0, and so do the file names that `pdf.py` creates.
+    fn get_rasters(&self, page_index: usize) -> Result<Vec<String>, Error> {
+        let prefix = format!("page-{page_index:04}");
+        let mut result = vec![];
 
-        for image in self.images.iter() {
-            result.push(image.clone());
-            result.push(AtomicToken::Separator);
+        for path in self.rasters.iter() {
+            let name = basename(path)?;
+
+            if let Some(rest) = name.strip_prefix(&prefix) {
+                if rest.starts_with('.') || rest.starts_with('-') {
+                    result.push(path.to_string());
+                }
+            }
         }
 
-        self.images = vec![];
         Ok(result)
     }
+}
 
-    fn has_more_to_read(&self) -> bool {
-        self.cursor < self.pages.len() || !self.images.is_empty()
+// [a, b, c, d, e, f] of pdf
+type Matrix = [f32; 6];
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+// Nested form xobjects deeper than this are ignored.
+const MAX_FORM_DEPTH: usize = 8;
+
+// Smaller images are likely to be bullets, icons or lines.
+const MIN_IMAGE_SIZE: i64 = 32;
+
+struct Font<'a> {
+    name: String,
+    encoding: Option<Encoding<'a>>,
+
+    // It's 2 for composite fonts (Type0). It doesn't support variable-length codes.
+    bytes_per_code: usize,
+
+    // in 1/1000 em
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+}
+
+impl<'a> Font<'a> {
+    fn new(document: &'a Document, name: &[u8], dict: &'a Dictionary) -> Self {
+        let mut widths = HashMap::new();
+        let is_composite = matches!(dict.get(b"Subtype").and_then(Object::as_name), Ok(b"Type0"));
+
+        let default_width = if is_composite {
+            let descendant = dict.get_deref(b"DescendantFonts", document)
+                .and_then(Object::as_array)
+                .ok()
+                .and_then(|fonts| fonts.first())
+                .and_then(|font| document.dereference(font).ok())
+                .and_then(|(_, font)| font.as_dict().ok());
+
+            match descendant {
+                Some(descendant) => {
+                    // [c [w1 w2 ...] c_first c_last w ...]
+                    if let Ok(w) = descendant.get_deref(b"W", document).and_then(Object::as_array) {
+                        let mut i = 0;
+
+                        while i + 1 < w.len() {
+                            let Ok(first) = w[i].as_i64() else { break; };
+
+                            match deref(document, &w[i + 1]) {
+                                Object::Array(ws) => {
+                                    for (j, width) in ws.iter().enumerate() {
+                                        if let Ok(width) = deref(document, width).as_float() {
+                                            widths.insert(first as u32 + j as u32, width);
+                                        }
+                                    }
+
+                                    i += 2;
+                                },
+                                last => {
+                                    let (Ok(last), Some(Ok(width))) = (last.as_i64(), w.get(i + 2).map(|w| deref(document, w).as_float())) else { break; };
+
+                                    for c in first..=last.min(first + 0xffff) {
+                                        widths.insert(c as u32, width);
+                                    }
+
+                                    i += 3;
+                                },
+                            }
+                        }
+                    }
+
+                    descendant.get_deref(b"DW", document).and_then(Object::as_float).unwrap_or(1000.0)
+                },
+                None => 1000.0,
+            }
+        }
+
+        else {
+            let first_char = dict.get_deref(b"FirstChar", document).and_then(Object::as_i64).unwrap_or(0);
+
+            if let Ok(ws) = dict.get_deref(b"Widths", document).and_then(Object::as_array) {
+                for (i, width) in ws.iter().enumerate() {
+                    if let Ok(width) = deref(document, width).as_float() {
+                        widths.insert(first_char as u32 + i as u32, width);
+                    }
+                }
+            }
+
+            500.0
+        };
+
+        Font {
+            name: String::from_utf8_lossy(name).to_string(),
+            encoding: dict.get_font_encoding(document).ok(),
+            bytes_per_code: if is_composite { 2 } else { 1 },
+            widths,
+            default_width,
+        }
     }
 
-    fn key(&self) -> String {
-        String::from("pdf_reader_v0")
+    fn decode(&self, bytes: &[u8]) -> Result<String, Error> {
+        match &self.encoding {
+            Some(encoding) => Ok(Document::decode_text(encoding, bytes)?),
+            None => Err(Error::FileReaderError(format!("cannot decode the text of font `{}`", self.name))),
+        }
+    }
+
+    // in 1/1000 em
+    fn width(&self, bytes: &[u8]) -> f32 {
+        bytes.chunks(self.bytes_per_code).map(
+            |code| {
+                let code = code.iter().fold(0u32, |acc, byte| acc * 256 + *byte as u32);
+                self.widths.get(&code).copied().unwrap_or(self.default_width)
+            }
+        ).sum()
+    }
+}
+
+// It walks the operations of a page, and collects texts and images in the order they're drawn.
+// It doesn't render anything, so the layout is a rough guess: a text that goes down
+// starts a new line, and a gap in a line becomes a space.
+struct PageWalker<'a> {
+    document: &'a Document,
+    strict_mode: bool,
+    tokens: Vec<AtomicToken>,
+    text: String,
+    has_text: bool,
+
+    // If the last thing drawn is an image, the next text or image starts with a newline.
+    // It's carried over to the next page.
+    is_after_image: bool,
+    was_after_image: bool,
+
+    font: Option<Rc<Font<'a>>>,
+    font_size: f32,
+    leading: f32,
+    text_matrix: Matrix,
+    line_matrix: Matrix,
+
+    // (x, y, height) of the end of the last text
+    last_position: Option<(f32, f32, f32)>,
+}
+
+impl<'a> PageWalker<'a> {
+    fn new(document: &'a Document, strict_mode: bool, is_after_image: bool) -> Self {
+        PageWalker {
+            document,
+            strict_mode,
+            tokens: vec![],
+            text: String::new(),
+            has_text: false,
+            is_after_image,
+            was_after_image: is_after_image,
+            font: None,
+            font_size: 0.0,
+            leading: 0.0,
+            text_matrix: IDENTITY,
+            line_matrix: IDENTITY,
+            last_position: None,
+        }
+    }
+
+    fn walk_page(&mut self, page_id: ObjectId) -> Result<(), Error> {
+        let document = self.document;
+        let (resource_dict, resource_ids) = document.get_page_resources(page_id)?;
+        let mut resources = vec![];
+
+        if let Some(resource_dict) = resource_dict {
+            resources.push(resource_dict);
+        }
+
+        for resource_id in resource_ids.iter() {
+            if let Ok(resource_dict) = document.get_dictionary(*resource_id) {
+                resources.push(resource_dict);
+            }
+        }
+
+        let content = document.get_page_content(page_id)?;
+        self.walk(&content, &resources, 0)
+    }
+
+    fn walk(&mut self, content: &[u8], resources: &[&'a Dictionary], depth: usize) -> Result<(), Error> {
+        let Some(content) = self.recover(Content::decode(content).map_err(|e| e.into()))? else { return Ok(()); };
+        let fonts = self.load_fonts(resources);
+
+        for operation in content.operations.iter() {
+            let operands = &operation.operands;
+            let number = |i: usize| operands.get(i).and_then(|n| n.as_float().ok()).unwrap_or(0.0);
+
+            match operation.operator.as_str() {
+                "BT" => {
+                    self.text_matrix = IDENTITY;
+                    self.line_matrix = IDENTITY;
+                },
+                "Tf" => {
+                    self.font = operands.first().and_then(|name| name.as_name().ok()).and_then(|name| fonts.get(name)).cloned();
+                    self.font_size = number(1);
+                },
+                "TL" => {
+                    self.leading = number(0);
+                },
+                "Td" => {
+                    self.move_line(number(0), number(1));
+                },
+                "TD" => {
+                    self.leading = -number(1);
+                    self.move_line(number(0), number(1));
+                },
+                "Tm" => {
+                    self.text_matrix = [number(0), number(1), number(2), number(3), number(4), number(5)];
+                    self.line_matrix = self.text_matrix;
+                },
+                "T*" => {
+                    self.move_line(0.0, -self.leading);
+                },
+                "Tj" => {
+                    if let Some(Ok(s)) = operands.first().map(|s| s.as_str()) {
+                        self.show_text(s)?;
+                    }
+                },
+                "'" => {
+                    self.move_line(0.0, -self.leading);
+
+                    if let Some(Ok(s)) = operands.first().map(|s| s.as_str()) {
+                        self.show_text(s)?;
+                    }
+                },
+                "\"" => {
+                    self.move_line(0.0, -self.leading);
+
+                    if let Some(Ok(s)) = operands.get(2).map(|s| s.as_str()) {
+                        self.show_text(s)?;
+                    }
+                },
+                "TJ" => {
+                    if let Some(Ok(elements)) = operands.first().map(|e| e.as_array()) {
+                        for element in elements.iter() {
+                            match element {
+                                Object::String(s, _) => {
+                                    self.show_text(s)?;
+                                },
+                                n => if let Ok(n) = n.as_float() {
+                                    self.move_text(-n / 1000.0 * self.font_size);
+                                },
+                            }
+                        }
+                    }
+                },
+                "Do" => {
+                    if let Some(Ok(name)) = operands.first().map(|name| name.as_name()) {
+                        self.draw_xobject(name, resources, depth)?;
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_fonts(&self, resources: &[&'a Dictionary]) -> HashMap<Vec<u8>, Rc<Font<'a>>> {
+        let document = self.document;
+        let mut result = HashMap::new();
+
+        for resource_dict in resources.iter() {
+            let Ok(fonts) = resource_dict.get_deref(b"Font", document).and_then(Object::as_dict) else { continue; };
+
+            for (name, font) in fonts.iter() {
+                if result.contains_key(name) {
+                    continue;
+                }
+
+                if let Ok(font) = deref(document, font).as_dict() {
+                    result.insert(name.to_vec(), Rc::new(Font::new(document, name, font)));
+                }
+            }
+        }
+
+        result
+    }
+
+    fn move_line(&mut self, tx: f32, ty: f32) {
+        let [a, b, c, d, e, f] = self.line_matrix;
+        self.line_matrix = [a, b, c, d, tx * a + ty * c + e, tx * b + ty * d + f];
+        self.text_matrix = self.line_matrix;
+    }
+
+    fn move_text(&mut self, tx: f32) {
+        let [a, b, c, d, e, f] = self.text_matrix;
+        self.text_matrix = [a, b, c, d, tx * a + e, tx * b + f];
+    }
+
+    fn show_text(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let Some(font) = self.font.clone() else { return Ok(()); };
+        let Some(s) = self.recover(font.decode(bytes))? else { return Ok(()); };
+        let [a, b, c, d, x, y] = self.text_matrix;
+        let em_width = self.font_size * (a * a + b * b).sqrt();
+        let height = self.font_size * (c * c + d * d).sqrt();
+
+        if let Some((last_x, last_y, last_height)) = self.last_position {
+            if (y - last_y).abs() > last_height.max(height) * 0.5 {
+                if !self.text.ends_with('\n') {
+                    self.text.push('\n');
+                }
+            }
+
+            else if x - last_x > em_width * 0.12
+                && !self.text.ends_with(char::is_whitespace)
+                && !s.starts_with(char::is_whitespace)
+            {
+                self.text.push(' ');
+            }
+        }
+
+        if self.is_after_image {
+            self.text.push('\n');
+            self.is_after_image = false;
+        }
+
+        for ch in s.chars() {
+            if !ch.is_control() {
+                self.text.push(ch);
+            }
+
+            if !ch.is_whitespace() {
+                self.has_text = true;
+            }
+        }
+
+        self.move_text(font.width(bytes) / 1000.0 * self.font_size);
+        self.last_position = Some((self.text_matrix[4], self.text_matrix[5], height));
+        Ok(())
+    }
+
+    fn draw_xobject(&mut self, name: &[u8], resources: &[&'a Dictionary], depth: usize) -> Result<(), Error> {
+        let document = self.document;
+        let mut xobject = None;
+
+        for resource_dict in resources.iter() {
+            if let Ok(xobjects) = resource_dict.get_deref(b"XObject", document).and_then(Object::as_dict) {
+                if let Ok(stream) = xobjects.get_deref(name, document).and_then(Object::as_stream) {
+                    xobject = Some(stream);
+                    break;
+                }
+            }
+        }
+
+        let Some(xobject) = xobject else { return Ok(()); };
+
+        match xobject.dict.get(b"Subtype").and_then(Object::as_name) {
+            Ok(b"Image") => {
+                // An image is on its own line.
+                if let Some(Some(bytes)) = self.recover(self.convert_image(xobject))? {
+                    if self.is_after_image || !self.text.is_empty() && !self.text.ends_with('\n') {
+                        self.text.push('\n');
+                    }
+
+                    self.flush_text();
+                    let uid = Uid::new_image(&bytes);
+                    self.tokens.push(AtomicToken::Image(Image {
+                        bytes,
+                        image_type: ImageType::Png,
+                        uid,
+                    }));
+                    self.is_after_image = true;
+                    self.last_position = None;
+                }
+            },
+            Ok(b"Form") if depth < MAX_FORM_DEPTH => {
+                let Some(content) = self.recover(xobject.get_plain_content().map_err(|e| e.into()))? else { return Ok(()); };
+                let mut form_resources = vec![];
+
+                if let Ok(resource_dict) = xobject.dict.get_deref(b"Resources", document).and_then(Object::as_dict) {
+                    form_resources.push(resource_dict);
+                }
+
+                form_resources.extend(resources.iter().copied());
+                self.walk(&content, &form_resources, depth + 1)?;
+            },
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    // It returns a normalized png file. If ragit cannot read the image format, it returns `None`.
+    fn convert_image(&self, xobject: &Stream) -> Result<Option<Vec<u8>>, Error> {
+        let document = self.document;
+        let dict = &xobject.dict;
+
+        if let Ok(true) = dict.get(b"ImageMask").and_then(Object::as_bool) {
+            return Ok(None);
+        }
+
+        let width = dict.get_deref(b"Width", document).and_then(Object::as_i64).unwrap_or(0);
+        let height = dict.get_deref(b"Height", document).and_then(Object::as_i64).unwrap_or(0);
+
+        if width < MIN_IMAGE_SIZE || height < MIN_IMAGE_SIZE {
+            return Ok(None);
+        }
+
+        let filters = xobject.filters().unwrap_or_default();
+
+        match filters.as_slice() {
+            [b"DCTDecode"] => {
+                return Ok(Some(normalize_image(xobject.content.clone(), ImageType::Jpeg)?));
+            },
+            [.., b"DCTDecode" | b"JPXDecode" | b"CCITTFaxDecode" | b"JBIG2Decode"] => {
+                return Ok(None);
+            },
+            _ => {},
+        }
+
+        if dict.get_deref(b"BitsPerComponent", document).and_then(Object::as_i64).unwrap_or(8) != 8 {
+            return Ok(None);
+        }
+
+        let Some(color_space) = ColorSpace::from_object(document, dict.get_deref(b"ColorSpace", document).ok()) else { return Ok(None); };
+        let data = xobject.get_plain_content()?;
+        let broken_image = || Error::FileReaderError(String::from("an image in the pdf file is broken"));
+
+        // The dimensions can be anything, and an overflow means that the image is broken.
+        let width = u32::try_from(width).map_err(|_| broken_image())?;
+        let height = u32::try_from(height).map_err(|_| broken_image())?;
+        let pixels = (width as u64).checked_mul(height as u64).and_then(|pixels| usize::try_from(pixels).ok()).ok_or_else(broken_image)?;
+        let bytes = |components: usize| pixels.checked_mul(components).ok_or_else(broken_image);
+
+        let image = match color_space {
+            ColorSpace::Gray => DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, data.get(..pixels).ok_or_else(broken_image)?.to_vec()).ok_or_else(broken_image)?),
+            ColorSpace::Rgb => DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, data.get(..bytes(3)?).ok_or_else(broken_image)?.to_vec()).ok_or_else(broken_image)?),
+            ColorSpace::Cmyk => {
+                let data = data.get(..bytes(4)?).ok_or_else(broken_image)?;
+                let rgb = data.chunks(4).flat_map(
+                    |cmyk| {
+                        let k = 255 - cmyk[3] as u32;
+                        [cmyk[0], cmyk[1], cmyk[2]].map(|c| ((255 - c as u32) * k / 255) as u8)
+                    }
+                ).collect::<Vec<_>>();
+                DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, rgb).ok_or_else(broken_image)?)
+            },
+            ColorSpace::Indexed { base_components, palette } => {
+                let data = data.get(..pixels).ok_or_else(broken_image)?;
+                let mut rgb = Vec::with_capacity(bytes(3)?);
+
+                for index in data.iter() {
+                    let color = palette.get((*index as usize * base_components)..((*index as usize + 1) * base_components)).ok_or_else(broken_image)?;
+
+                    if base_components == 1 {
+                        rgb.extend([color[0]; 3]);
+                    }
+
+                    else {
+                        rgb.extend(color);
+                    }
+                }
+
+                DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, rgb).ok_or_else(broken_image)?)
+            },
+        };
+
+        let mut writer = Cursor::new(vec![]);
+        image.write_to(&mut writer, image::ImageFormat::Png)?;
+        Ok(Some(normalize_image(writer.into_inner(), ImageType::Png)?))
+    }
+
+    fn flush_text(&mut self) {
+        // it is NOT a tokenizer
+        // I just want to make sure that it does not split a word
+        // into two different chunks
+        let mut curr = String::new();
+
+        for ch in self.text.chars() {
+            if !curr.is_empty() && (ch.is_whitespace() || curr.len() >= 256) {
+                self.tokens.push(AtomicToken::String {
+                    char_len: curr.chars().count(),
+                    data: curr,
+                });
+                curr = String::new();
+            }
+
+            curr.push(ch);
+        }
+
+        if !curr.is_empty() {
+            self.tokens.push(AtomicToken::String {
+                char_len: curr.chars().count(),
+                data: curr,
+            });
+        }
+
+        self.text = String::new();
+    }
+
+    // It returns (tokens, has_text, is_after_image). If the page ends with a text, the text
+    // ends with a newline, so that the next page starts with a new line.
+    fn finish(mut self) -> (Vec<AtomicToken>, bool, bool) {
+        if self.has_text && !self.is_after_image && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+
+        self.flush_text();
+
+        if self.has_text {
+            (self.tokens, true, self.is_after_image)
+        }
+
+        // There might be whitespaces, but they're meaningless.
+        else {
+            let mut result = vec![];
+
+            for token in self.tokens.into_iter() {
+                if let AtomicToken::Image(_) = &token {
+                    if self.was_after_image || !result.is_empty() {
+                        result.push(AtomicToken::String { data: String::from("\n"), char_len: 1 });
+                    }
+
+                    result.push(token);
+                }
+            }
+
+            (result, false, self.is_after_image)
+        }
+    }
+
+    // In strict mode, it fails if a part of the file is broken or cannot be read.
+    // Otherwise, it skips the part.
+    fn recover<T>(&self, result: Result<T, Error>) -> Result<Option<T>, Error> {
+        match result {
+            Ok(v) => Ok(Some(v)),
+            Err(e) if self.strict_mode => Err(e),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    Indexed {
+        base_components: usize,
+        palette: Vec<u8>,
+    },
+}
+
+impl ColorSpace {
+    fn from_object(document: &Document, object: Option<&Object>) -> Option<Self> {
+        let object = deref(document, object?);
+
+        match object {
+            Object::Name(name) => match name.as_slice() {
+                b"DeviceGray" | b"CalGray" | b"G" => Some(ColorSpace::Gray),
+                b"DeviceRGB" | b"CalRGB" | b"RGB" => Some(ColorSpace::Rgb),
+                b"DeviceCMYK" | b"CMYK" => Some(ColorSpace::Cmyk),
+                _ => None,
+            },
+            Object::Array(array) => match array.first().and_then(|name| name.as_name().ok()) {
+                Some(b"ICCBased") => {
+                    let stream = deref(document, array.get(1)?).as_stream().ok()?;
+
+                    match stream.dict.get_deref(b"N", document).and_then(Object::as_i64) {
+                        Ok(1) => Some(ColorSpace::Gray),
+                        Ok(3) => Some(ColorSpace::Rgb),
+                        Ok(4) => Some(ColorSpace::Cmyk),
+                        _ => None,
+                    }
+                },
+                Some(b"Indexed" | b"I") => {
+                    let base_components = match ColorSpace::from_object(document, array.get(1))? {
+                        ColorSpace::Gray => 1,
+                        ColorSpace::Rgb => 3,
+                        _ => { return None; },
+                    };
+                    let palette = match deref(document, array.get(3)?) {
+                        Object::String(s, _) => s.to_vec(),
+                        Object::Stream(s) => s.get_plain_content().ok()?,
+                        _ => { return None; },
+                    };
+
+                    Some(ColorSpace::Indexed { base_components, palette })
+                },
+                Some(_) => ColorSpace::from_object(document, array.first()),
+                None => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn deref<'a>(document: &'a Document, object: &'a Object) -> &'a Object {
+    match document.dereference(object) {
+        Ok((_, object)) => object,
+        Err(_) => object,
     }
 }
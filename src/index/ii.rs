@@ -1,29 +1,61 @@
 // Inverted Index Implementation
 // Inverted Index is still very naive and lacking many features.
 //
-// 1. You can only build ii from scratch. There's no incremental build.
-// 2. You can only remove the entire ii. There's no removing a single file or a chunk.
-// 3. If something goes wrong while an ii is building, you have to build it from scratch.
+// 1. If the ii is `Complete`, `rag build`, `rag rm` and `rag merge` update it in place.
+//    If it's `Ongoing`, they make it `Outdated`, and you have to build it from scratch.
+// 2. If something goes wrong while an ii is building, you have to build it from scratch.
+//
+// If `query_config.ii_positions` is set, each term also has a positions file
+// (`.ragit/ii/xx/yyyy.pos`). It's a list of positions lists, in the same order
+// as the postings in the term's ii file. A position is an index of `tokenize(haystack)`.
+//
+// There're 2 formats of ii files (`IIFormat`). The old one (`UidList`) is a list of
+// uids, and a positions file is a gzipped json. The new one (`Compact`) is much
+// smaller: see `posting.rs`. `rag migrate` converts the old one to the new one.
+//
+// A query looks up the chunk table many times (once for each term), so `Index`
+// caches the table. It's loaded at most once until the table is modified.
 
-use super::Index;
-use crate::constant::{II_DIR_NAME, INDEX_DIR_NAME};
+use super::{CorpusStats, Index, ProcessedDoc};
+use super::tfidf::{Tokenizer, get_term_positions};
+use crate::constant::{II_CHUNK_TABLE_FILE_NAME, II_DIR_NAME, INDEX_DIR_NAME};
 use crate::error::Error;
+use crate::query::{Phrase, PhraseKind};
 use crate::uid::{self, Uid, UidWriteMode};
+use flate2::Compression;
+use flate2::read::{GzDecoder, GzEncoder};
 use ragit_fs::{
+    WriteMode,
     exists,
     file_name,
     is_dir,
     join,
     join3,
     parent,
+    read_bytes,
     read_dir,
     remove_dir_all,
+    remove_file,
+    set_extension,
     try_create_dir,
+    write_bytes,
 };
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Sha3_256};
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+mod posting;
+
+use posting::{
+    ChunkTable,
+    decode_positions,
+    decode_posting,
+    encode_positions,
+    encode_posting,
+};
 
 pub type Term = String;
 pub type Weight = f32;
@@ -50,6 +82,75 @@ pub enum IIStatus {
     Ongoing(Uid),
 }
 
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum IIFormat {
+    /// Each ii file is a list of uids. `index.json` of an old knowledge-base
+    /// doesn't have this field, so it's the default value.
+    #[default]
+    UidList,
+
+    /// Each ii file is a list of varint-encoded deltas of chunk ids. A chunk id
+    /// is an index of `.ragit/ii/chunk_table`.
+    Compact,
+}
+
+/// In-memory copy of `.ragit/ii/chunk_table`. Like `CorpusStatsCache`, it's not
+/// serialized and it's ignored by `==`.
+#[derive(Debug, Default)]
+pub(crate) struct ChunkTableCache(Mutex<Option<Arc<ChunkTable>>>);
+
+impl Clone for ChunkTableCache {
+    fn clone(&self) -> Self {
+        ChunkTableCache(Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
+
+impl PartialEq for ChunkTableCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for ChunkTableCache {}
+
+pub(crate) struct IIBuffer {
+    uids: HashMap<Term, Vec<Uid>>,
+
+    // It's `Some` only if the ii has positional postings.
+    positions: Option<HashMap<Term, Vec<Vec<usize>>>>,
+
+    // The positions are calculated with this.
+    tokenizer: Tokenizer,
+}
+
+impl IIBuffer {
+    pub fn len(&self) -> usize {
+        self.uids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.uids.is_empty()
+    }
+}
+
+/// Result of matching phrases against the positional postings.
+pub(crate) struct PhraseMatches {
+    /// If it's `Some`, only these chunks match all the `Exact` and `Near` phrases.
+    required: Option<HashSet<Uid>>,
+
+    /// The highest proximity of each chunk, from 0.0 to 1.0
+    pub proximity: HashMap<Uid, f32>,
+}
+
+impl PhraseMatches {
+    pub fn is_allowed(&self, uid: &Uid) -> bool {
+        match &self.required {
+            Some(required) => required.contains(uid),
+            None => true,
+        }
+    }
+}
+
 #[derive(Default)]
 struct IIBuildState {
     total_uid: usize,
@@ -59,15 +160,32 @@ struct IIBuildState {
 }
 
 impl Index {
+    /// If the ii has positional postings, chunks that don't match `Exact` or `Near`
+    /// phrases are filtered out, and chunks whose terms are close to each other get
+    /// higher scores. Otherwise, `phrases` are ignored.
     pub fn get_search_candidates(
         &self,
         terms: &HashMap<Term, Weight>,
+        phrases: &[Phrase],
         limit: usize,
     ) -> Result<Vec<Uid>, Error> {
+        let phrase_matches = self.match_phrases(phrases)?;
+        self.get_search_candidates_worker(terms, phrase_matches.as_ref(), None, limit)
+    }
+
+    /// If `allowed` is set, the other chunks are filtered out before `limit` is applied.
+    pub(crate) fn get_search_candidates_worker(
+        &self,
+        terms: &HashMap<Term, Weight>,
+        phrase_matches: Option<&PhraseMatches>,
+        allowed: Option<&HashSet<Uid>>,
+        limit: usize,
+    ) -> Result<Vec<Uid>, Error> {
+        let chunk_table = self.load_chunk_table()?;
         let mut result = HashMap::new();
 
         for (term, weight) in terms.iter() {
-            let chunk_uids = self.search_ii_by_term(term)?;
+            let chunk_uids = self.search_ii_by_term_worker(term, &chunk_table)?;
             let score = weight * ((self.chunk_count + 1) as f32 / (chunk_uids.len() + 1) as f32).log2();
 
             for chunk_uid in chunk_uids.iter() {
@@ -80,6 +198,18 @@ impl Index {
 
         let mut result = result.into_iter().collect::<Vec<_>>();
 
+        if let Some(allowed) = allowed {
+            result.retain(|(uid, _)| allowed.contains(uid));
+        }
+
+        if let Some(phrase_matches) = phrase_matches {
+            result = result.into_iter().filter(
+                |(uid, _)| phrase_matches.is_allowed(uid)
+            ).map(
+                |(uid, score)| (uid, score * (1.0 + self.query_config.proximity_boost.0 * phrase_matches.proximity.get(&uid).unwrap_or(&0.0)))
+            ).collect();
+        }
+
         // It has to be sorted in reverse order
         result.sort_by(|(_, score_a), (_, score_b)| score_b.partial_cmp(score_a).unwrap_or(Ordering::Equal));
 
@@ -90,11 +220,81 @@ impl Index {
         Ok(result.into_iter().map(|(uid, _)| uid).collect())
     }
 
+    /// It returns `None` if there's no phrase or the ii doesn't have positional postings.
+    pub(crate) fn match_phrases(&self, phrases: &[Phrase]) -> Result<Option<PhraseMatches>, Error> {
+        if phrases.is_empty() || !self.is_ii_built() || !self.ii_positions {
+            return Ok(None);
+        }
+
+        let chunk_table = self.load_chunk_table()?;
+        let mut positions_by_term: HashMap<Term, HashMap<Uid, Vec<usize>>> = HashMap::new();
+
+        for phrase in phrases.iter() {
+            for term in phrase.terms.iter() {
+                if !positions_by_term.contains_key(term) {
+                    positions_by_term.insert(term.to_string(), self.get_positions_by_term_worker(term, &chunk_table)?);
+                }
+            }
+        }
+
+        let mut required: Option<HashSet<Uid>> = None;
+        let mut proximity = HashMap::new();
+
+        for phrase in phrases.iter() {
+            let mut matched = HashSet::new();
+
+            // a chunk has to have the first term to match the phrase
+            for uid in positions_by_term.get(&phrase.terms[0]).unwrap().keys() {
+                let mut positions = HashMap::with_capacity(phrase.terms.len());
+
+                for term in phrase.terms.iter() {
+                    if let Some(positions_) = positions_by_term.get(term).unwrap().get(uid) {
+                        positions.insert(term.to_string(), positions_.clone());
+                    }
+                }
+
+                if let Some(proximity_) = phrase.get_proximity(&positions) {
+                    matched.insert(*uid);
+
+                    match proximity.get_mut(uid) {
+                        Some(p) => { *p = proximity_.max(*p); },
+                        None => { proximity.insert(*uid, proximity_); },
+                    }
+                }
+            }
+
+            if phrase.kind != PhraseKind::Loose {
+                required = Some(match required {
+                    Some(required) => required.intersection(&matched).copied().collect(),
+                    None => matched,
+                });
+            }
+        }
+
+        Ok(Some(PhraseMatches { required, proximity }))
+    }
+
     pub fn search_ii_by_term(&self, term: &Term) -> Result<Vec<Uid>, Error> {
+        self.search_ii_by_term_worker(term, &*self.load_chunk_table()?)
+    }
+
+    /// It's like calling `search_ii_by_term` for each term, but it loads the chunk table only once.
+    pub fn search_ii_by_terms(&self, terms: &[Term]) -> Result<HashMap<Term, Vec<Uid>>, Error> {
+        let chunk_table = self.load_chunk_table()?;
+        let mut result = HashMap::with_capacity(terms.len());
+
+        for term in terms.iter() {
+            result.insert(term.to_string(), self.search_ii_by_term_worker(term, &chunk_table)?);
+        }
+
+        Ok(result)
+    }
+
+    fn search_ii_by_term_worker(&self, term: &Term, chunk_table: &ChunkTable) -> Result<Vec<Uid>, Error> {
         let ii_path = Index::get_ii_path(&self.root_dir, hash(term));
 
         if exists(&ii_path) {
-            Ok(uid::load_from_file(&ii_path)?)
+            Ok(load_postings(&ii_path, self.ii_format, false, chunk_table)?.into_iter().map(|(uid, _)| uid).collect())
         }
 
         else {
@@ -102,13 +302,41 @@ impl Index {
         }
     }
 
+    /// It returns an empty map if the ii doesn't have positional postings.
+    pub fn get_positions_by_term(&self, term: &Term) -> Result<HashMap<Uid, Vec<usize>>, Error> {
+        self.get_positions_by_term_worker(term, &*self.load_chunk_table()?)
+    }
+
+    /// It's like calling `get_positions_by_term` for each term, but it loads the chunk table only once.
+    pub fn get_positions_by_terms(&self, terms: &[Term]) -> Result<HashMap<Term, HashMap<Uid, Vec<usize>>>, Error> {
+        let chunk_table = self.load_chunk_table()?;
+        let mut result = HashMap::with_capacity(terms.len());
+
+        for term in terms.iter() {
+            result.insert(term.to_string(), self.get_positions_by_term_worker(term, &chunk_table)?);
+        }
+
+        Ok(result)
+    }
+
+    fn get_positions_by_term_worker(&self, term: &Term, chunk_table: &ChunkTable) -> Result<HashMap<Uid, Vec<usize>>, Error> {
+        let ii_path = Index::get_ii_path(&self.root_dir, hash(term));
+
+        if !self.ii_positions || !exists(&ii_path) {
+            return Ok(HashMap::new());
+        }
+
+        Ok(load_postings(&ii_path, self.ii_format, true, chunk_table)?.into_iter().collect())
+    }
+
     /// Very naive way of incremental ii-build.
     /// It works only when `self.ii_status` is `IIStatus::Complete`.
     pub fn add_chunk_to_ii(&mut self, uid: Uid) -> Result<(), Error> {
         if self.ii_status == IIStatus::Complete {
-            let mut buffer = HashMap::new();
+            let mut buffer = self.new_ii_buffer();
+            let mut chunk_table = self.load_chunk_table()?.as_ref().clone();
             self.update_ii_buffer(&mut buffer, uid)?;
-            self.flush_ii_buffer(buffer)?;
+            self.flush_ii_buffer(buffer, &mut chunk_table)?;
             Ok(())
         }
 
@@ -117,11 +345,90 @@ impl Index {
         }
     }
 
+    /// It adds `added` chunks to the ii and removes `removed` chunks from the ii.
+    /// `removed` are the tfidfs of the removed chunks, which tell which terms
+    /// have to be updated. The chunk files of `added` must exist.
+    ///
+    /// If the ii is `Complete`, it stays `Complete`. If it's `Ongoing`, it becomes
+    /// `Outdated`. The caller has to save `self` to `index.json`.
+    pub(crate) fn update_ii(&mut self, added: &[Uid], removed: &[ProcessedDoc]) -> Result<(), Error> {
+        if added.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        match self.ii_status {
+            IIStatus::None | IIStatus::Outdated => Ok(()),
+            IIStatus::Ongoing(_) => {
+                self.ii_status = IIStatus::Outdated;
+                Ok(())
+            },
+            IIStatus::Complete => {
+                let mut chunk_table = self.load_chunk_table()?.as_ref().clone();
+
+                if !removed.is_empty() {
+                    self.remove_chunks_from_ii(removed, &chunk_table)?;
+                }
+
+                if !added.is_empty() {
+                    let mut buffer = self.new_ii_buffer();
+
+                    for uid in added.iter() {
+                        self.update_ii_buffer(&mut buffer, *uid)?;
+                    }
+
+                    self.flush_ii_buffer(buffer, &mut chunk_table)?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+
+    fn remove_chunks_from_ii(&self, removed: &[ProcessedDoc], chunk_table: &ChunkTable) -> Result<(), Error> {
+        let mut removed_by_term: HashMap<Term, HashSet<Uid>> = HashMap::new();
+
+        for doc in removed.iter() {
+            if let Some(uid) = doc.uid {
+                for term in doc.term_frequency.keys() {
+                    match removed_by_term.get_mut(term) {
+                        Some(uids) => { uids.insert(uid); },
+                        None => { removed_by_term.insert(term.to_string(), [uid].into_iter().collect()); },
+                    }
+                }
+            }
+        }
+
+        for (term, removed_uids) in removed_by_term.into_iter() {
+            let ii_path = Index::get_ii_path(&self.root_dir, hash(&term));
+
+            if !exists(&ii_path) {
+                continue;
+            }
+
+            // removed chunks stay in the chunk table, so that the ids of the other chunks don't change
+            let postings = load_postings(&ii_path, self.ii_format, self.ii_positions, chunk_table)?.into_iter().filter(
+                |(uid, _)| !removed_uids.contains(uid)
+            ).collect::<Vec<_>>();
+
+            save_postings(&ii_path, self.ii_format, self.ii_positions, postings, chunk_table)?;
+        }
+
+        Ok(())
+    }
+
+    /// If the ii is `Complete`, it does nothing unless the config has changed or the
+    /// chunk table has removed chunks. The chunk table is append-only, so building
+    /// the ii from scratch is the only way to shrink it.
     pub fn build_ii(&mut self, quiet: bool) -> Result<(), Error> {
         match self.ii_status {
             IIStatus::None => {},
-            IIStatus::Complete => {
+            IIStatus::Complete => if self.ii_positions == self.query_config.ii_positions
+                && self.ii_format == IIFormat::Compact
+                && self.load_chunk_table()?.len() <= self.chunk_count
+            {
                 return Ok(());
+            } else {
+                self.reset_ii()?;
             },
             // TODO: resuming `Ongoing` ii-build is not implemented yet
             IIStatus::Outdated | IIStatus::Ongoing(_) => {
@@ -129,16 +436,24 @@ impl Index {
             },
         }
 
-        let mut buffer = HashMap::with_capacity(AUTO_FLUSH);
+        self.ii_positions = self.query_config.ii_positions;
+        self.ii_format = IIFormat::Compact;
+        let mut buffer = self.new_ii_buffer();
+
+        // It doesn't load the table even if there's one. The ii is built from
+        // scratch, so the table only has the chunks that exist.
+        let mut chunk_table = ChunkTable::default();
         let mut state = IIBuildState::default();
         let mut uid_check_point = None;
+        let mut corpus_stats = CorpusStats::default();
 
         for uid in self.get_all_chunk_uids()? {
             if uid_check_point.is_none() {
                 uid_check_point = Some(uid);
             }
 
-            self.update_ii_buffer(&mut buffer, uid)?;
+            let processed_doc = self.update_ii_buffer(&mut buffer, uid)?;
+            corpus_stats.add_doc(&processed_doc);
             state.total_uid += 1;
             state.buffer_uid += 1;
             state.buffer_term = buffer.len();
@@ -152,17 +467,19 @@ impl Index {
                 uid_check_point = None;
                 self.save_to_file()?;
 
-                self.flush_ii_buffer(buffer)?;
-                buffer = HashMap::with_capacity(AUTO_FLUSH);
+                self.flush_ii_buffer(buffer, &mut chunk_table)?;
+                buffer = self.new_ii_buffer();
                 state.buffer_uid = 0;
                 state.buffer_flush += 1;
             }
         }
 
         if !buffer.is_empty() {
-            self.flush_ii_buffer(buffer)?;
+            self.flush_ii_buffer(buffer, &mut chunk_table)?;
         }
 
+        self.save_corpus_stats(&corpus_stats)?;
+
         if !quiet {
             self.render_ii_build_dashboard(&state);
         }
@@ -185,27 +502,102 @@ impl Index {
             }
         }
 
+        let chunk_table_path = self.get_chunk_table_path()?;
+
+        if exists(&chunk_table_path) {
+            remove_file(&chunk_table_path)?;
+        }
+
+        *self.chunk_table.0.lock().unwrap() = None;
+
         self.ii_status = IIStatus::None;
+        self.ii_positions = false;
+
+        // an empty ii can always be in the new format
+        self.ii_format = IIFormat::Compact;
         self.save_to_file()?;
         Ok(())
     }
 
+    /// It converts an ii in `IIFormat::UidList` to `IIFormat::Compact`. If the ii
+    /// is `Outdated` or `Ongoing`, it's reset instead, because it has to be built
+    /// from scratch anyway. It returns `false` if there's nothing to convert.
+    pub fn migrate_ii(&mut self) -> Result<bool, Error> {
+        if self.ii_format == IIFormat::Compact {
+            return Ok(false);
+        }
+
+        if self.ii_status != IIStatus::Complete {
+            self.reset_ii()?;
+            return Ok(true);
+        }
+
+        let chunk_table = self.new_chunk_table()?;
+        chunk_table.save(&self.get_chunk_table_path()?)?;
+        *self.chunk_table.0.lock().unwrap() = None;
+
+        for internal in read_dir(&join3(
+            &self.root_dir,
+            INDEX_DIR_NAME,
+            II_DIR_NAME,
+        )?, false)? {
+            if !is_dir(&internal) {
+                continue;
+            }
+
+            for ii_path in read_dir(&internal, false)? {
+                if ii_path.ends_with(".pos") {
+                    continue;
+                }
+
+                let postings = load_postings(&ii_path, IIFormat::UidList, self.ii_positions, &chunk_table)?;
+                save_postings(&ii_path, IIFormat::Compact, self.ii_positions, postings, &chunk_table)?;
+            }
+        }
+
+        self.ii_format = IIFormat::Compact;
+        self.save_to_file()?;
+        Ok(true)
+    }
+
     pub fn check_ii(&self) -> Result<(), Error> {
         let mut term_hash_map: HashMap<String, String> = HashMap::with_capacity(1024);
         let mut from_ii: HashMap<String, Vec<Uid>> = HashMap::with_capacity(1024);
         let mut from_tfidf: HashMap<String, Vec<Uid>> = HashMap::with_capacity(1024);
+        let chunk_table = self.load_chunk_table()?;
 
         'outer: for internal in read_dir(&join3(
             &self.root_dir,
             INDEX_DIR_NAME,
             II_DIR_NAME,
         )?, false)? {
+            // the chunk table
+            if !is_dir(&internal) {
+                continue;
+            }
+
             let prefix = file_name(&internal)?;
 
             for ii_path in read_dir(&internal, false)? {
+                // positions files are checked with their ii files
+                if ii_path.ends_with(".pos") {
+                    continue;
+                }
+
                 let suffix = file_name(&ii_path)?;
                 let term_hash = format!("{prefix}{suffix}");
-                let uids = uid::load_from_file(&ii_path)?;
+
+                // In `IIFormat::Compact`, it also checks that the ids are sorted and are in the chunk table.
+                let uids = match load_postings(&ii_path, self.ii_format, self.ii_positions, &chunk_table) {
+                    Ok(postings) => postings.into_iter().map(|(uid, _)| uid).collect::<Vec<_>>(),
+                    Err(Error::BrokenII(e)) => {
+                        return Err(Error::BrokenII(format!("`{term_hash}`: {e}")));
+                    },
+                    Err(e) => {
+                        return Err(e);
+                    },
+                };
+
                 from_ii.insert(term_hash, uids);
 
                 // It takes too long to iterate all the terms...
@@ -247,7 +639,9 @@ impl Index {
                     )?;
 
                     if exists(&ii_at) {
-                        let ii_uids = uid::load_from_file(&ii_at)?;
+                        let ii_uids = from_ii.get(&term_hash).cloned().map(Ok).unwrap_or_else(
+                            || self.search_ii_by_term_worker(term, &chunk_table)
+                        )?;
 
                         if !ii_uids.contains(&uid) {
                             return Err(Error::BrokenII(format!("`{term}` is in `{uid}`, but not in ii.")));
@@ -304,24 +698,77 @@ impl Index {
         self.ii_status == IIStatus::Complete
     }
 
-    pub(crate) fn update_ii_buffer(&self, buffer: &mut HashMap<Term, Vec<Uid>>, uid: Uid) -> Result<(), Error> {
+    pub(crate) fn new_ii_buffer(&self) -> IIBuffer {
+        IIBuffer {
+            uids: HashMap::new(),
+            positions: if self.ii_positions { Some(HashMap::new()) } else { None },
+            tokenizer: self.tokenizer(),
+        }
+    }
+
+    /// It returns the tfidf of the chunk, so that callers don't have to load it again.
+    pub(crate) fn update_ii_buffer(&self, buffer: &mut IIBuffer, uid: Uid) -> Result<ProcessedDoc, Error> {
         let tfidf = self.get_tfidf_by_chunk_uid(uid)?;
 
         for term in tfidf.term_frequency.keys() {
-            match buffer.get_mut(term) {
+            match buffer.uids.get_mut(term) {
                 Some(uids) => {
                     uids.push(uid);
                 },
                 None => {
-                    buffer.insert(term.to_string(), vec![uid]);
+                    buffer.uids.insert(term.to_string(), vec![uid]);
                 },
             }
         }
 
-        Ok(())
+        if let Some(positions_buffer) = &mut buffer.positions {
+            let chunk = self.get_chunk_by_uid(uid)?;
+
+            // it's the same haystack as the tfidf file, so the terms are the same
+            let mut term_positions = if chunk.searchable {
+                get_term_positions(&chunk.into_tfidf_haystack(&self.root_dir)?, &buffer.tokenizer)
+            } else {
+                HashMap::new()
+            };
+
+            for term in tfidf.term_frequency.keys() {
+                let positions = term_positions.remove(term).unwrap_or_default();
+
+                match positions_buffer.get_mut(term) {
+                    Some(positions_) => {
+                        positions_.push(positions);
+                    },
+                    None => {
+                        positions_buffer.insert(term.to_string(), vec![positions]);
+                    },
+                }
+            }
+        }
+
+        Ok(tfidf)
     }
 
-    pub(crate) fn flush_ii_buffer(&self, buffer: HashMap<Term, Vec<Uid>>) -> Result<(), Error> {
+    /// `chunk_table` is the table of the ii files. Callers load it once and reuse it
+    /// for all the flushes of an operation. New chunks are added to it.
+    pub(crate) fn flush_ii_buffer(&self, buffer: IIBuffer, chunk_table: &mut ChunkTable) -> Result<(), Error> {
+        let IIBuffer { uids: buffer, positions: mut positions_buffer, .. } = buffer;
+
+        if self.ii_format == IIFormat::Compact {
+            let prev_len = chunk_table.len();
+
+            for uids in buffer.values() {
+                for uid in uids.iter() {
+                    chunk_table.get_or_insert(*uid);
+                }
+            }
+
+            // the table has to be saved before the ii files that refer to the new ids
+            if chunk_table.len() != prev_len {
+                chunk_table.save(&self.get_chunk_table_path()?)?;
+                *self.chunk_table.0.lock().unwrap() = None;
+            }
+        }
+
         for (term, uids) in buffer.into_iter() {
             let term_hash = hash(&term);
             let ii_path = Index::get_ii_path(&self.root_dir, term_hash);
@@ -331,22 +778,59 @@ impl Index {
                 try_create_dir(&parent_path)?;
             }
 
-            let uids = if exists(&ii_path) {
-                let mut prev_uids = uid::load_from_file(&ii_path)?;
-                prev_uids.extend(uids);
-                prev_uids
-            }
-
-            else {
-                uids
+            let mut postings = match &mut positions_buffer {
+                Some(positions_buffer) => uids.into_iter().zip(
+                    positions_buffer.remove(&term).unwrap_or_default()
+                ).collect::<Vec<_>>(),
+                None => uids.into_iter().map(|uid| (uid, vec![])).collect(),
             };
 
-            uid::save_to_file(&ii_path, &uids, UidWriteMode::Compact)?;
+            if exists(&ii_path) {
+                postings.extend(load_postings(&ii_path, self.ii_format, self.ii_positions, chunk_table)?);
+            }
+
+            save_postings(&ii_path, self.ii_format, self.ii_positions, postings, chunk_table)?;
         }
 
         Ok(())
     }
 
+    /// It's cached. Callers that modify the table have to clone it.
+    fn load_chunk_table(&self) -> Result<Arc<ChunkTable>, Error> {
+        if self.ii_format == IIFormat::UidList {
+            return Ok(Arc::new(ChunkTable::default()));
+        }
+
+        let mut cache = self.chunk_table.0.lock().unwrap();
+
+        if let Some(chunk_table) = cache.as_ref() {
+            return Ok(chunk_table.clone());
+        }
+
+        let chunk_table = Arc::new(ChunkTable::load(&self.get_chunk_table_path()?)?);
+        *cache = Some(chunk_table.clone());
+        Ok(chunk_table)
+    }
+
+    // chunks are sorted by uid, like `ii-build`
+    fn new_chunk_table(&self) -> Result<ChunkTable, Error> {
+        let mut result = ChunkTable::default();
+
+        for uid in self.get_all_chunk_uids()? {
+            result.get_or_insert(uid);
+        }
+
+        Ok(result)
+    }
+
+    fn get_chunk_table_path(&self) -> Result<String, Error> {
+        Ok(join3(
+            &self.root_dir,
+            INDEX_DIR_NAME,
+            &join(II_DIR_NAME, II_CHUNK_TABLE_FILE_NAME)?,
+        )?)
+    }
+
     fn render_ii_build_dashboard(&self, state: &IIBuildState) {
         clearscreen::clear().expect("failed to clear screen");
         println!("building an inverted index...");
@@ -362,3 +846,132 @@ fn hash(term: &Term) -> String {
     hasher.update(term.as_bytes());
     format!("{:064x}", hasher.finalize())
 }
+
+/// If `positions` is not set, the positions lists of the postings are empty.
+fn load_postings(
+    ii_path: &str,
+    format: IIFormat,
+    positions: bool,
+    chunk_table: &ChunkTable,
+) -> Result<Vec<(Uid, Vec<usize>)>, Error> {
+    let uids = match format {
+        IIFormat::UidList => uid::load_from_file(ii_path)?,
+        IIFormat::Compact => {
+            let mut uids = vec![];
+
+            for id in decode_posting(&read_bytes(ii_path)?)? {
+                match chunk_table.get_uid(id) {
+                    Some(uid) => { uids.push(uid); },
+                    None => {
+                        return Err(Error::BrokenII(format!("chunk id {id} is not in the chunk table ({} chunks)", chunk_table.len())));
+                    },
+                }
+            }
+
+            uids
+        },
+    };
+
+    if !positions {
+        return Ok(uids.into_iter().map(|uid| (uid, vec![])).collect());
+    }
+
+    let positions_path = set_extension(ii_path, "pos")?;
+
+    if !exists(&positions_path) {
+        return Err(Error::BrokenII(format!("`{ii_path}` doesn't have a positions file.")));
+    }
+
+    let positions = match format {
+        IIFormat::UidList => load_positions(&positions_path)?,
+        IIFormat::Compact => decode_positions(&read_bytes(&positions_path)?)?,
+    };
+
+    if uids.len() != positions.len() {
+        return Err(Error::BrokenII(format!("`{ii_path}` has {} uids, but {} positions lists.", uids.len(), positions.len())));
+    }
+
+    Ok(uids.into_iter().zip(positions).collect())
+}
+
+/// It sorts and deduplicates `postings`. If `postings` is empty, it removes the files.
+/// In `IIFormat::Compact`, all the uids must be in `chunk_table`.
+fn save_postings(
+    ii_path: &str,
+    format: IIFormat,
+    positions: bool,
+    mut postings: Vec<(Uid, Vec<usize>)>,
+    chunk_table: &ChunkTable,
+) -> Result<(), Error> {
+    let positions_path = set_extension(ii_path, "pos")?;
+
+    if postings.is_empty() {
+        for path in [ii_path, positions_path.as_str()] {
+            if exists(path) {
+                remove_file(path)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    match format {
+        IIFormat::UidList => {
+            // `UidWriteMode::Compact` sorts the uids, and the positions have to be in the same order
+            postings.sort_by_key(|(uid, _)| *uid);
+            postings.dedup_by_key(|(uid, _)| *uid);
+            let (uids, positions_): (Vec<Uid>, Vec<Vec<usize>>) = postings.into_iter().unzip();
+            uid::save_to_file(ii_path, &uids, UidWriteMode::Compact)?;
+
+            if positions {
+                save_positions(&positions_path, &positions_)?;
+            }
+        },
+        IIFormat::Compact => {
+            let mut postings_ = Vec::with_capacity(postings.len());
+
+            for (uid, positions_) in postings.into_iter() {
+                match chunk_table.get_id(&uid) {
+                    Some(id) => { postings_.push((id, positions_)); },
+                    None => {
+                        return Err(Error::BrokenII(format!("`{uid}` is not in the chunk table.")));
+                    },
+                }
+            }
+
+            postings_.sort_by_key(|(id, _)| *id);
+            postings_.dedup_by_key(|(id, _)| *id);
+            let (ids, positions_): (Vec<usize>, Vec<Vec<usize>>) = postings_.into_iter().unzip();
+            write_bytes(ii_path, &encode_posting(&ids), WriteMode::CreateOrTruncate)?;
+
+            if positions {
+                write_bytes(&positions_path, &encode_positions(&positions_), WriteMode::CreateOrTruncate)?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+// positions files in `IIFormat::UidList` are always compressed
+fn load_positions(path: &str) -> Result<Vec<Vec<usize>>, Error> {
+    let content = read_bytes(path)?;
+    let mut decompressed = vec![];
+    let mut gz = GzDecoder::new(&content[..]);
+    gz.read_to_end(&mut decompressed)?;
+
+    Ok(serde_json::from_slice(&decompressed)?)
+}
+
+fn save_positions(path: &str, positions: &[Vec<usize>]) -> Result<(), Error> {
+    let result = serde_json::to_vec(positions)?;
+    let mut compressed = vec![];
+    let mut gz = GzEncoder::new(&result[..], Compression::best());
+    gz.read_to_end(&mut compressed)?;
+
+    Ok(write_bytes(
+        path,
+        &compressed,
+        WriteMode::CreateOrTruncate,
+    )?)
+}
@@ -0,0 +1,270 @@
+// Compact posting format (`IIFormat::Compact`)
+//
+// `.ragit/ii/chunk_table` lists the uids of the chunks in the ii, and a chunk's
+// index in the table is its id. The table is append-only: a removed chunk stays
+// in the table until the ii is built from scratch, so that the ids of the other
+// chunks don't change.
+//
+// A posting file is `COMPACT_MARKER, varint(n), varint(id_0), varint(id_1 - id_0), ...`.
+// The ids are sorted and deduplicated, so most deltas fit in 1 or 2 bytes.
+//
+// A positions file is `COMPACT_MARKER`, then for each id in the posting file (in the
+// same order), `varint(m), varint(p_0), varint(p_1 - p_0), ...`.
+
+use crate::error::Error;
+use crate::uid::Uid;
+use ragit_fs::{
+    WriteMode,
+    exists,
+    read_bytes,
+    write_bytes,
+};
+use std::collections::HashMap;
+
+/// First byte of every file in the compact format. Files in the old format start
+/// with an ascii hex digit or a byte in `192..=224` (see `uid::load_from_file`),
+/// so it can tell the formats apart just by looking at the first byte.
+pub const COMPACT_MARKER: u8 = 2;
+
+// `Uid::encode` with this length never loses information
+const UID_BYTE_LEN: usize = 32;
+
+#[derive(Clone, Debug, Default)]
+pub struct ChunkTable {
+    uids: Vec<Uid>,
+    ids: HashMap<Uid, usize>,
+}
+
+impl ChunkTable {
+    /// It returns an empty table if the file doesn't exist.
+    pub fn load(path: &str) -> Result<Self, Error> {
+        if !exists(path) {
+            return Ok(ChunkTable::default());
+        }
+
+        let bytes = read_bytes(path)?;
+
+        match bytes.first() {
+            Some(&COMPACT_MARKER) => {},
+            _ => {
+                return Err(Error::BrokenII(format!("`{path}` is not a chunk table.")));
+            },
+        }
+
+        if (bytes.len() - 1) % UID_BYTE_LEN != 0 {
+            return Err(Error::BrokenII(format!("`{path}` is {} bytes, which is not 1 + {UID_BYTE_LEN} * n.", bytes.len())));
+        }
+
+        let mut result = ChunkTable::default();
+
+        for uid_bytes in bytes[1..].chunks(UID_BYTE_LEN) {
+            result.insert(Uid::decode(uid_bytes)?);
+        }
+
+        Ok(result)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let mut bytes = Vec::with_capacity(1 + self.uids.len() * UID_BYTE_LEN);
+        bytes.push(COMPACT_MARKER);
+
+        for uid in self.uids.iter() {
+            uid.encode(UID_BYTE_LEN, &mut bytes);
+        }
+
+        Ok(write_bytes(
+            path,
+            &bytes,
+            WriteMode::Atomic,
+        )?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.uids.len()
+    }
+
+    pub fn get_id(&self, uid: &Uid) -> Option<usize> {
+        self.ids.get(uid).copied()
+    }
+
+    pub fn get_uid(&self, id: usize) -> Option<Uid> {
+        self.uids.get(id).copied()
+    }
+
+    pub fn get_or_insert(&mut self, uid: Uid) -> usize {
+        match self.ids.get(&uid) {
+            Some(id) => *id,
+            None => self.insert(uid),
+        }
+    }
+
+    fn insert(&mut self, uid: Uid) -> usize {
+        let id = self.uids.len();
+        self.uids.push(uid);
+        self.ids.insert(uid, id);
+        id
+    }
+}
+
+/// `ids` must be sorted and deduplicated.
+pub fn encode_posting(ids: &[usize]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(ids.len() * 2 + 2);
+    result.push(COMPACT_MARKER);
+    push_varint(ids.len(), &mut result);
+    let mut prev = 0;
+
+    for id in ids.iter() {
+        push_varint(id - prev, &mut result);
+        prev = *id;
+    }
+
+    result
+}
+
+/// It also checks that the ids are strictly increasing.
+pub fn decode_posting(bytes: &[u8]) -> Result<Vec<usize>, Error> {
+    let mut cursor = check_marker(bytes)?;
+    let len = read_varint(bytes, &mut cursor)?;
+    let mut result = Vec::with_capacity(len.min(bytes.len()));
+    let mut prev: usize = 0;
+
+    for i in 0..len {
+        let delta = read_varint(bytes, &mut cursor)?;
+
+        if i > 0 && delta == 0 {
+            return Err(Error::BrokenII(String::from("duplicate ids in a posting list")));
+        }
+
+        prev = prev.checked_add(delta).ok_or_else(|| Error::BrokenII(String::from("integer overflow in a posting list")))?;
+        result.push(prev);
+    }
+
+    if cursor != bytes.len() {
+        return Err(Error::BrokenII(format!("{} trailing bytes in a posting list", bytes.len() - cursor)));
+    }
+
+    Ok(result)
+}
+
+/// Each positions list must be sorted.
+pub fn encode_positions(positions: &[Vec<usize>]) -> Vec<u8> {
+    let mut result = vec![COMPACT_MARKER];
+
+    for positions_ in positions.iter() {
+        push_varint(positions_.len(), &mut result);
+        let mut prev = 0;
+
+        for position in positions_.iter() {
+            push_varint(position - prev, &mut result);
+            prev = *position;
+        }
+    }
+
+    result
+}
+
+pub fn decode_positions(bytes: &[u8]) -> Result<Vec<Vec<usize>>, Error> {
+    let mut cursor = check_marker(bytes)?;
+    let mut result = vec![];
+
+    while cursor < bytes.len() {
+        let len = read_varint(bytes, &mut cursor)?;
+        let mut positions = Vec::with_capacity(len.min(bytes.len()));
+        let mut prev: usize = 0;
+
+        for _ in 0..len {
+            prev = prev.checked_add(read_varint(bytes, &mut cursor)?).ok_or_else(|| Error::BrokenII(String::from("integer overflow in a positions list")))?;
+            positions.push(prev);
+        }
+
+        result.push(positions);
+    }
+
+    Ok(result)
+}
+
+fn check_marker(bytes: &[u8]) -> Result<usize, Error> {
+    match bytes.first() {
+        Some(&COMPACT_MARKER) => Ok(1),
+        Some(b) => Err(Error::BrokenII(format!("unexpected ii format marker: `{b}`"))),
+        None => Err(Error::BrokenII(String::from("an empty ii file"))),
+    }
+}
+
+// LEB128: 7 bits per byte, and the highest bit tells whether there're more bytes
+fn push_varint(mut n: usize, buffer: &mut Vec<u8>) {
+    loop {
+        if n < 128 {
+            buffer.push(n as u8);
+            break;
+        }
+
+        buffer.push((n & 127) as u8 | 128);
+        n >>= 7;
+    }
+}
+
+fn read_varint(bytes: &[u8], cursor: &mut usize) -> Result<usize, Error> {
+    let mut result: usize = 0;
+    let mut shift = 0;
+
+    loop {
+        let b = match bytes.get(*cursor) {
+            Some(b) => *b,
+            None => {
+                return Err(Error::BrokenII(String::from("unexpected end of a varint")));
+            },
+        };
+        *cursor += 1;
+
+        if shift >= usize::BITS {
+            return Err(Error::BrokenII(String::from("a varint is too long")));
+        }
+
+        result |= ((b & 127) as usize) << shift;
+
+        if b < 128 {
+            return Ok(result);
+        }
+
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn posting_test() {
+        for ids in [
+            vec![],
+            vec![0],
+            vec![0, 1, 2, 3],
+            vec![5, 127, 128, 129, 16383, 16384, 1 << 40],
+        ] {
+            let encoded = encode_posting(&ids);
+            assert_eq!(decode_posting(&encoded).unwrap(), ids);
+        }
+
+        // deltas are small, so it's much smaller than uid lists (16 ~ 32 bytes per uid)
+        assert_eq!(encode_posting(&(0..1000).map(|i| i * 3).collect::<Vec<_>>()).len(), 1 + 2 + 1000);
+
+        assert!(decode_posting(&[]).is_err());
+        assert!(decode_posting(&[192, 0]).is_err());
+        assert!(decode_posting(&[COMPACT_MARKER, 2, 3]).is_err());
+        assert!(decode_posting(&[COMPACT_MARKER, 2, 3, 0]).is_err());
+        assert!(decode_posting(&[COMPACT_MARKER, 1, 3, 0]).is_err());
+
+        for positions in [
+            vec![],
+            vec![vec![]],
+            vec![vec![0, 1, 2], vec![300], vec![], vec![7, 100000]],
+        ] {
+            let encoded = encode_positions(&positions);
+            assert_eq!(decode_positions(&encoded).unwrap(), positions);
+        }
+
+        assert!(decode_positions(&[COMPACT_MARKER, 2, 3]).is_err());
+    }
+}
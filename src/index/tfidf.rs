@@ -1,6 +1,6 @@
-use crate::chunk::Chunk;
+use crate::chunk::{self, Chunk};
 use crate::error::Error;
-use crate::index::{IMAGE_DIR_NAME, Index};
+use crate::index::{CorpusStats, IIStatus, IMAGE_DIR_NAME, Index};
 use crate::query::Keywords;
 use crate::uid::Uid;
 use flate2::Compression;
@@ -10,37 +10,119 @@ use ragit_fs::{
     WriteMode,
     read_bytes,
     read_string,
+    set_extension,
     write_bytes,
 };
+use ragit_cjk::Script;
 use rust_stemmers::{Algorithm, Stemmer};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 use std::io::Read;
 
 type Term = String;
 type Weight = f32;
 
+struct CorpusState {
+    doc_count: usize,
+    avg_len: f32,
+
+    // only has `terms`
+    document_frequency: HashMap<Term, usize>,
+}
+
 pub struct TfidfState<DocId> {
     pub terms: HashMap<Term, Weight>,
-    term_frequency: HashMap<(DocId, Term), usize>,
+    term_frequency: HashMap<(DocId, Term), f32>,
     document_frequency: HashMap<Term, usize>,
     document_len: HashMap<DocId, usize>,
     docs: Vec<DocId>,
+
+    // BM25 parameters
+    k1: f32,
+    b: f32,
+
+    // If it's set, idf and average length are calculated with this,
+    // instead of the consumed documents.
+    corpus: Option<CorpusState>,
+
+    // A document's score is multiplied by `1 + proximity_boost * proximity`.
+    proximity: HashMap<DocId, f32>,
+    proximity_boost: f32,
+
+    // If it's set, term frequencies are weighted by fields. See `ProcessedDoc::get_weighted`.
+    field_weights: Option<HashMap<TfidfField, f32>>,
 }
 
 #[derive(Clone)]
 pub struct TfidfResult<DocId: Clone> {
     pub id: DocId,
     pub score: f32,
+
+    /// How much each term contributed to the score (weighted bm25), in descending
+    /// order. `score` is the sum of the contributions, multiplied by the proximity boost.
+    pub term_scores: Vec<(Term, f32)>,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, Serialize, PartialEq)]
 pub struct ProcessedDoc {
     pub uid: Option<Uid>,
+
+    /// If `fields` is not empty, it's derived from `fields` and is not stored in the tfidf file.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub term_frequency: HashMap<Term, usize>,
     length: usize,
+
+    /// Term frequency of each field. It's empty if the tfidf file is built by
+    /// an older version of ragit. See `Chunk::into_tfidf_fields`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub fields: HashMap<TfidfField, FieldFrequency>,
+}
+
+/// A chunk is searched by these fields, and each field has its own weight (e.g. `QueryConfig::title_field_weight`).
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TfidfField {
+    Path,
+    Title,
+    Summary,
+    Data,
+    Image,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, Serialize, PartialEq)]
+pub struct FieldFrequency {
+    pub term_frequency: HashMap<Term, usize>,
+    pub length: usize,
+}
+
+impl FieldFrequency {
+    pub fn new(content: &str, tokenizer: &Tokenizer) -> Self {
+        let mut result = FieldFrequency::default();
+
+        for term in tokenize(content, tokenizer) {
+            result.length += 1;
+
+            match result.term_frequency.get_mut(&term) {
+                Some(n) => { *n += 1; },
+                None => { result.term_frequency.insert(term, 1); },
+            }
+        }
+
+        result
+    }
+
+    fn extend(&mut self, other: &FieldFrequency) {
+        self.length += other.length;
+
+        for (term, count) in other.term_frequency.iter() {
+            match self.term_frequency.get_mut(term) {
+                Some(n) => { *n += *count; },
+                None => { self.term_frequency.insert(term.clone(), *count); },
+            }
+        }
+    }
 }
 
 // tfidf files are always compressed
@@ -49,21 +131,35 @@ pub fn load_from_file(path: &str) -> Result<ProcessedDoc, Error> {
     let mut decompressed = vec![];
     let mut gz = GzDecoder::new(&content[..]);
     gz.read_to_end(&mut decompressed)?;
+    let mut result = serde_json::from_slice::<ProcessedDoc>(&decompressed)?;
 
-    Ok(serde_json::from_slice(&decompressed)?)
+    if !result.fields.is_empty() {
+        result.term_frequency = result.sum_fields().0;
+    }
+
+    Ok(result)
 }
 
-pub fn save_to_file(path: &str, chunk: &Chunk, root_dir: &str) -> Result<(), Error> {
+pub fn save_to_file(path: &str, chunk: &Chunk, root_dir: &str, tokenizer: &Tokenizer) -> Result<(), Error> {
     let tfidf = if chunk.searchable {
-        ProcessedDoc::new(chunk.uid.clone(), &chunk.into_tfidf_haystack(root_dir)?)
+        ProcessedDoc::from_fields(
+            chunk.uid,
+            chunk.into_tfidf_fields(root_dir)?.into_iter().map(
+                |(field, content)| (field, FieldFrequency::new(&content, tokenizer))
+            ).collect(),
+        )
     } else {
         ProcessedDoc {
             uid: Some(chunk.uid),
-            term_frequency: HashMap::new(),
-            length: 0,
+            ..ProcessedDoc::empty()
         }
     };
-    let result = serde_json::to_vec(&tfidf)?;
+
+    // `term_frequency` is derived from `fields` when it's loaded.
+    let result = serde_json::to_vec(&ProcessedDoc {
+        term_frequency: HashMap::new(),
+        ..tfidf
+    })?;
     let mut compressed = vec![];
     let mut gz = GzEncoder::new(&result[..], Compression::best());
     gz.read_to_end(&mut compressed)?;
@@ -90,11 +186,12 @@ impl ProcessedDoc {
     pub fn new(
         uid: Uid,
         doc_content: &str,
+        tokenizer: &Tokenizer,
     ) -> Self {
         let mut term_frequency = HashMap::new();
         let mut length = 0;
 
-        for term in tokenize(doc_content) {
+        for term in tokenize(doc_content, tokenizer) {
             length += 1;
 
             match term_frequency.get_mut(&term) {
@@ -107,7 +204,33 @@ impl ProcessedDoc {
             uid: Some(uid),
             length,
             term_frequency,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// The terms are the same as `Chunk::into_tfidf_haystack`, where the title is counted twice.
+    pub fn from_fields(uid: Uid, fields: HashMap<TfidfField, FieldFrequency>) -> Self {
+        let mut result = ProcessedDoc {
+            uid: Some(uid),
+            fields,
+            ..ProcessedDoc::empty()
+        };
+        (result.term_frequency, result.length) = result.sum_fields();
+        result
+    }
+
+    fn sum_fields(&self) -> (HashMap<Term, usize>, usize) {
+        let mut total = FieldFrequency::default();
+
+        for (field, frequency) in self.fields.iter() {
+            total.extend(frequency);
+
+            if *field == TfidfField::Title {
+                total.extend(frequency);
+            }
         }
+
+        (total.term_frequency, total.length)
     }
 
     pub fn empty() -> Self {
@@ -115,6 +238,7 @@ impl ProcessedDoc {
             uid: None,
             length: 0,
             term_frequency: HashMap::new(),
+            fields: HashMap::new(),
         }
     }
 
@@ -131,12 +255,31 @@ impl ProcessedDoc {
                 None => { self.term_frequency.insert(term.clone(), *count); },
             }
         }
+
+        for (field, frequency) in other.fields.iter() {
+            match self.fields.get_mut(field) {
+                Some(f) => { f.extend(frequency); },
+                None => { self.fields.insert(*field, frequency.clone()); },
+            }
+        }
     }
 
     pub fn get(&self, term: &str) -> Option<usize> {
         self.term_frequency.get(term).copied()
     }
 
+    /// Term frequency where an occurrence in a field counts as `weights[field]` occurrences.
+    /// If the doc doesn't have the fields, it's the same as `get`.
+    pub fn get_weighted(&self, term: &str, weights: &HashMap<TfidfField, f32>) -> f32 {
+        if self.fields.is_empty() {
+            return self.get(term).unwrap_or(0) as f32;
+        }
+
+        self.fields.iter().map(
+            |(field, frequency)| *frequency.term_frequency.get(term).unwrap_or(&0) as f32 * weights.get(field).copied().unwrap_or(1.0)
+        ).sum()
+    }
+
     pub fn contains_term(&self, term: &str) -> bool {
         self.term_frequency.contains_key(term)
     }
@@ -194,16 +337,50 @@ impl ProcessedDoc {
 }
 
 impl<DocId: Clone + Eq + Hash> TfidfState<DocId> {
-    pub fn new(keywords: &Keywords) -> Self {
+    pub fn new(keywords: &Keywords, tokenizer: &Tokenizer) -> Self {
         TfidfState {
-            terms: keywords.tokenize(),
+            terms: keywords.tokenize(tokenizer),
             term_frequency: HashMap::new(),
             document_frequency: HashMap::new(),
             document_len: HashMap::new(),
             docs: vec![],
+            k1: 1.2,
+            b: 0.75,
+            corpus: None,
+            proximity: HashMap::new(),
+            proximity_boost: 0.0,
+            field_weights: None,
         }
     }
 
+    /// `proximity` is from 0.0 to 1.0. The higher, the closer the terms are in the document.
+    pub fn set_proximity(&mut self, proximity: HashMap<DocId, f32>, proximity_boost: f32) {
+        self.proximity = proximity;
+        self.proximity_boost = proximity_boost;
+    }
+
+    /// Document lengths are not weighted, so the weights don't change how much long
+    /// documents are penalized.
+    pub fn set_field_weights(&mut self, weights: HashMap<TfidfField, f32>) {
+        self.field_weights = Some(weights);
+    }
+
+    pub fn set_bm25_params(&mut self, k1: f32, b: f32) {
+        self.k1 = k1;
+        self.b = b;
+    }
+
+    /// Use corpus-wide statistics instead of the statistics of the consumed documents.
+    pub fn set_corpus_stats(&mut self, stats: &CorpusStats) {
+        self.corpus = Some(CorpusState {
+            doc_count: stats.doc_count,
+            avg_len: stats.avg_len(),
+            document_frequency: self.terms.keys().map(
+                |term| (term.to_string(), *stats.document_frequency.get(term).unwrap_or(&0))
+            ).collect(),
+        });
+    }
+
     pub fn consume(
         &mut self,
         doc_id: DocId,
@@ -220,7 +397,10 @@ impl<DocId: Clone + Eq + Hash> TfidfState<DocId> {
 
             self.term_frequency.insert(
                 (doc_id.clone(), term.to_string()),
-                processed_doc.get(term).unwrap_or(0),
+                match &self.field_weights {
+                    Some(weights) => processed_doc.get_weighted(term, weights),
+                    None => processed_doc.get(term).unwrap_or(0) as f32,
+                },
             );
         }
 
@@ -229,26 +409,36 @@ impl<DocId: Clone + Eq + Hash> TfidfState<DocId> {
     }
 
     pub fn get_top(&self, limit: usize) -> Vec<TfidfResult<DocId>> {
-        let mut tfidfs: HashMap<DocId, f32> = HashMap::new();
+        let mut tfidfs: HashMap<DocId, (f32, Vec<(Term, f32)>)> = HashMap::new();
 
         // https://en.wikipedia.org/wiki/Okapi_BM25
-        let k = 1.2;
-        let b = 0.75;
+        let k = self.k1;
+        let b = self.b;
 
         if self.document_len.is_empty() {
             return vec![];
         }
 
-        let avg_len = self.document_len.values().sum::<usize>() as f32 / self.document_len.len() as f32;
+        let (doc_count, avg_len, document_frequency) = match &self.corpus {
+            Some(corpus) => (corpus.doc_count, corpus.avg_len, &corpus.document_frequency),
+            None => (
+                self.docs.len(),
+                self.document_len.values().sum::<usize>() as f32 / self.document_len.len() as f32,
+                &self.document_frequency,
+            ),
+        };
+
+        // It prevents division by zero. If all the documents are empty, no term is found anyway.
+        let avg_len = if avg_len > 0.0 { avg_len } else { 1.0 };
 
         for (term, weight) in self.terms.iter() {
-            let idf = ((self.docs.len() + 1) as f32 / (*self.document_frequency.get(term).unwrap_or(&0) + 1) as f32).log2();
+            let idf = ((doc_count + 1) as f32 / (*document_frequency.get(term).unwrap_or(&0) + 1) as f32).log2();
             let idf = idf.max(0.1);
 
             for doc in self.docs.iter() {
-                let t = *self.term_frequency.get(&(doc.clone(), term.to_string())).unwrap_or(&0) as f32;
+                let t = *self.term_frequency.get(&(doc.clone(), term.to_string())).unwrap_or(&0.0);
 
-                if t == 0.0 {
+                if t <= 0.0 {
                     continue;
                 }
 
@@ -257,18 +447,25 @@ impl<DocId: Clone + Eq + Hash> TfidfState<DocId> {
                 let tfidf = tf * idf;
 
                 match tfidfs.get_mut(doc) {
-                    Some(val) => {
+                    Some((val, term_scores)) => {
                         *val += tfidf * weight;
+                        term_scores.push((term.to_string(), tfidf * weight));
                     },
                     None => {
-                        tfidfs.insert(doc.clone(), tfidf * weight);
+                        tfidfs.insert(doc.clone(), (tfidf * weight, vec![(term.to_string(), tfidf * weight)]));
                     },
                 }
             }
         }
 
-        let mut tfidfs: Vec<_> = tfidfs.into_iter().map(|(id, score)| TfidfResult { id, score }).collect();
-        tfidfs.sort_by(|TfidfResult { score: a, .. }, TfidfResult { score: b, .. }| b.partial_cmp(a).unwrap());  // rev sort
+        let mut tfidfs: Vec<_> = tfidfs.into_iter().map(
+            |(id, (score, mut term_scores))| {
+                let score = score * (1.0 + self.proximity_boost * self.proximity.get(&id).unwrap_or(&0.0));
+                term_scores.sort_by(|(_, a), (_, b)| b.total_cmp(a));  // rev sort
+                TfidfResult { id, score, term_scores }
+            }
+        ).collect();
+        tfidfs.sort_by(|TfidfResult { score: a, .. }, TfidfResult { score: b, .. }| b.total_cmp(a));  // rev sort
 
         if tfidfs.len() > limit {
             tfidfs[..limit].to_vec()
@@ -278,8 +475,114 @@ impl<DocId: Clone + Eq + Hash> TfidfState<DocId> {
     }
 }
 
-pub fn tokenize(s: &str) -> Vec<String> {
-    let stemmer = Stemmer::create(Algorithm::English);
+/// Positions of each term in `tokenize(doc_content, tokenizer)`. The positions are sorted.
+pub fn get_term_positions(doc_content: &str, tokenizer: &Tokenizer) -> HashMap<Term, Vec<usize>> {
+    let mut result: HashMap<Term, Vec<usize>> = HashMap::new();
+
+    for (position, term) in tokenize(doc_content, tokenizer).into_iter().enumerate() {
+        match result.get_mut(&term) {
+            Some(positions) => { positions.push(position); },
+            None => { result.insert(term, vec![position]); },
+        }
+    }
+
+    result
+}
+
+/// Names of the stemmers that `TokenizerConfig::stemmer` can be.
+pub const STEMMERS: [&str; 19] = [
+    "arabic", "danish", "dutch", "english", "finnish", "french", "german",
+    "greek", "hungarian", "italian", "norwegian", "portuguese", "romanian",
+    "russian", "spanish", "swedish", "tamil", "turkish", "none",
+];
+
+/// How documents and queries are tokenized. It's recorded in `index.json`
+/// when the tfidf files are built, so that queries are tokenized the same way.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct TokenizerConfig {
+    /// One of `STEMMERS`. If it's `"none"`, the terms are not stemmed.
+    pub stemmer: String,
+
+    /// Tokens in this list are dropped. They're compared before stemming, case-insensitively.
+    pub stopwords: Vec<String>,
+
+    /// If it's set, Chinese and Japanese texts are split into unigrams and bigrams (see `ragit_cjk`).
+    /// Otherwise, they're huge tokens. It's `BuildConfig::split_cjk`, which is not set
+    /// in knowledge-bases that are created by older versions of ragit.
+    #[serde(default)]
+    pub split_cjk: bool,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        TokenizerConfig {
+            stemmer: String::from("english"),
+            stopwords: vec![],
+            split_cjk: true,
+        }
+    }
+}
+
+impl TokenizerConfig {
+    /// tfidf files built by older versions are tokenized this way
+    pub(crate) fn legacy() -> Self {
+        TokenizerConfig {
+            split_cjk: false,
+            ..TokenizerConfig::default()
+        }
+    }
+
+    pub fn get_stemmer(&self) -> Result<Option<Stemmer>, Error> {
+        let algorithm = match self.stemmer.to_ascii_lowercase().as_str() {
+            "arabic" => Algorithm::Arabic,
+            "danish" => Algorithm::Danish,
+            "dutch" => Algorithm::Dutch,
+            "english" => Algorithm::English,
+            "finnish" => Algorithm::Finnish,
+            "french" => Algorithm::French,
+            "german" => Algorithm::German,
+            "greek" => Algorithm::Greek,
+            "hungarian" => Algorithm::Hungarian,
+            "italian" => Algorithm::Italian,
+            "norwegian" => Algorithm::Norwegian,
+            "portuguese" => Algorithm::Portuguese,
+            "romanian" => Algorithm::Romanian,
+            "russian" => Algorithm::Russian,
+            "spanish" => Algorithm::Spanish,
+            "swedish" => Algorithm::Swedish,
+            "tamil" => Algorithm::Tamil,
+            "turkish" => Algorithm::Turkish,
+            "none" => { return Ok(None); },
+            _ => { return Err(Error::InvalidStemmer(self.stemmer.to_string())); },
+        };
+
+        Ok(Some(Stemmer::create(algorithm)))
+    }
+}
+
+/// A `TokenizerConfig` that is ready to use. Creating a stemmer and the set of
+/// stopwords is not free, so create one per operation, not per document.
+pub struct Tokenizer {
+    stemmer: Option<Stemmer>,
+    stopwords: HashSet<String>,
+    split_cjk: bool,
+}
+
+impl TokenizerConfig {
+    /// If `self.stemmer` is invalid, the tokenizer doesn't stem the terms.
+    /// `rag config --set` doesn't allow invalid stemmers, so it's unlikely to happen.
+    pub fn tokenizer(&self) -> Tokenizer {
+        Tokenizer {
+            stemmer: self.get_stemmer().unwrap_or(None),
+            stopwords: self.stopwords.iter().map(|word| word.to_lowercase()).collect(),
+            split_cjk: self.split_cjk,
+        }
+    }
+}
+
+/// `tokenizer` is from `Index::tokenizer_config`.
+pub fn tokenize(s: &str, tokenizer: &Tokenizer) -> Vec<String> {
+    let Tokenizer { stemmer, stopwords, split_cjk } = tokenizer;
     let mut result = vec![];
 
     for token in s.to_ascii_lowercase().split(
@@ -293,12 +596,32 @@ pub fn tokenize(s: &str) -> Vec<String> {
         } else {
             false
         }
-    ).map(
-        move |s| ragit_korean::tokenize(&stemmer.stem(s))
+    ).filter(
+        |s| !stopwords.contains(&s.to_lowercase())
     ) {
-        for t in token {
-            if t.len() > 0 {
-                result.push(t);
+        let runs = if *split_cjk {
+            ragit_cjk::split_by_script(token)
+        } else {
+            vec![(Script::Other, token)]
+        };
+
+        for (script, run) in runs {
+            let terms = match script {
+                // Chinese and Japanese don't put spaces between words, so
+                // stopwords are compared with the unigrams and bigrams.
+                Script::Han | Script::Hiragana | Script::Katakana => ragit_cjk::unigrams_and_bigrams(run).into_iter().filter(
+                    |term| !stopwords.contains(term)
+                ).collect(),
+                Script::Other => match stemmer {
+                    Some(stemmer) => ragit_korean::tokenize(&stemmer.stem(run)),
+                    None => ragit_korean::tokenize(run),
+                },
+            };
+
+            for t in terms {
+                if t.len() > 0 {
+                    result.push(t);
+                }
             }
         }
     }
@@ -314,9 +637,66 @@ impl Chunk {
     //     - It has explanations on images
     //     - It's always English
     // 4. Images have to be replaced with its description.
+    // 5. `self.symbol` (e.g. `Index::build`) is a part of the title.
+    //
+    // The terms of the haystack are the terms of `into_tfidf_fields`, but the title is counted twice.
+    // The inverted index and the positions of the terms are built from the haystack.
     pub fn into_tfidf_haystack(&self, root_dir: &str) -> Result<String, Error> {
         let mut data = self.data.clone();
 
+        for (image, description) in self.get_image_descriptions(root_dir)? {
+            data = data.replace(
+                &format!("img_{image}"),
+                &description,
+            );
+        }
+
+        let title = self.render_tfidf_title();
+
+        Ok(format!(
+            "{}\n{}\n{}\n{}\n{}",
+            self.render_source(),
+            title,
+            title,
+            self.summary,
+            data,
+        ))
+    }
+
+    /// Each field is tokenized separately, so that the fields can be weighted at query time.
+    pub fn into_tfidf_fields(&self, root_dir: &str) -> Result<Vec<(TfidfField, String)>, Error> {
+        let mut data = self.data.clone();
+        let mut descriptions = vec![];
+
+        for (image, description) in self.get_image_descriptions(root_dir)? {
+            let image = format!("img_{image}");
+
+            for _ in 0..data.matches(&image).count() {
+                descriptions.push(description.clone());
+            }
+
+            data = data.replace(&image, " ");
+        }
+
+        Ok(vec![
+            (TfidfField::Path, self.render_source()),
+            (TfidfField::Title, self.render_tfidf_title()),
+            (TfidfField::Summary, self.summary.clone()),
+            (TfidfField::Data, data),
+            (TfidfField::Image, descriptions.join("\n")),
+        ])
+    }
+
+    fn render_tfidf_title(&self) -> String {
+        match &self.symbol {
+            Some(symbol) => format!("{}\n{symbol}", self.title),
+            None => self.title.clone(),
+        }
+    }
+
+    fn get_image_descriptions(&self, root_dir: &str) -> Result<Vec<(Uid, String)>, Error> {
+        let mut result = Vec::with_capacity(self.images.len());
+
         for image in self.images.iter() {
             let description_at = Index::get_uid_path(
                 root_dir,
@@ -341,19 +721,159 @@ impl Chunk {
                 },
             };
 
-            data = data.replace(
-                &format!("img_{image}"),
-                &rep_text,
-            );
+            result.push((*image, rep_text));
         }
 
-        Ok(format!(
-            "{}\n{}\n{}\n{}\n{}",
-            self.render_source(),
-            self.title,
-            self.title,
-            self.summary,
-            data,
-        ))
+        Ok(result)
+    }
+}
+
+impl Index {
+    /// The tokenizer of the tfidf files, which queries have to use.
+    pub fn tokenizer(&self) -> Tokenizer {
+        self.tokenizer_config.tokenizer()
+    }
+
+    /// `BuildConfig::stemmer`, `BuildConfig::stopwords` and `BuildConfig::split_cjk` are applied when the
+    /// tfidf files are rebuilt. Until then, the tfidf files and queries use `self.tokenizer_config`.
+    pub fn is_tfidf_outdated(&self) -> bool {
+        self.build_config.tokenizer_config() != self.tokenizer_config
+    }
+
+    /// The tfidf files built by an older version of ragit don't have the fields, so
+    /// `QueryConfig::field_weights` doesn't work with them. `rag migrate` rebuilds them
+    /// with this function. It returns how many files it has rebuilt, or `None` if the
+    /// tfidf files already have the fields.
+    pub fn migrate_tfidf_fields(&mut self) -> Result<Option<usize>, Error> {
+        if self.tfidf_fields {
+            return Ok(None);
+        }
+
+        Ok(Some(self.rebuild_tfidf_files()?))
+    }
+
+    /// It creates the tfidf files of all the chunks with the tokenizer of `BuildConfig`,
+    /// and returns how many files it has created. The ii is marked outdated because its
+    /// terms are tokenized with the previous tokenizer.
+    pub fn rebuild_tfidf_files(&mut self) -> Result<usize, Error> {
+        let tokenizer_config = self.build_config.tokenizer_config();
+        tokenizer_config.get_stemmer()?;
+        let tokenizer = tokenizer_config.tokenizer();
+        let mut count = 0;
+
+        for chunk_file in self.get_all_chunk_files()? {
+            save_to_file(
+                &set_extension(&chunk_file, "tfidf")?,
+                &chunk::load_from_file(&chunk_file)?,
+                &self.root_dir,
+                &tokenizer,
+            )?;
+            count += 1;
+        }
+
+        // If it's interrupted before this line, it'll start over next time.
+        self.tokenizer_config = tokenizer_config;
+        self.tfidf_fields = true;
+
+        if self.ii_status != IIStatus::None {
+            self.ii_status = IIStatus::Outdated;
+        }
+
+        self.rebuild_corpus_stats()?;
+        self.save_to_file()?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::ChunkSource;
+
+    #[test]
+    fn tokenizer_test() {
+        let english = TokenizerConfig::default();
+        let german = TokenizerConfig {
+            stemmer: String::from("german"),
+            stopwords: vec![String::from("Die"), String::from("und")],
+            ..TokenizerConfig::default()
+        };
+        let none = TokenizerConfig {
+            stemmer: String::from("none"),
+            ..TokenizerConfig::default()
+        };
+        let tokenize = |s: &str, config: &TokenizerConfig| tokenize(s, &config.tokenizer());
+
+        assert_eq!(tokenize("connections pooling", &english), vec!["connect", "pool"]);
+        assert_eq!(tokenize("die Verbindungen und Häuser", &german), vec!["verbind", "haus"]);
+        assert_eq!(tokenize("connections pooling", &none), vec!["connections", "pooling"]);
+
+        assert!(english.get_stemmer().unwrap().is_some());
+        assert!(none.get_stemmer().unwrap().is_none());
+        assert!(TokenizerConfig { stemmer: String::from("klingon"), ..TokenizerConfig::default() }.get_stemmer().is_err());
+
+        assert_eq!(tokenize("東京の天気は晴れ。", &english), vec!["東", "東京", "京", "の", "天", "天気", "気", "は", "晴", "れ"]);
+        assert_eq!(tokenize("ragitで検索する", &english), vec!["ragit", "で", "検", "検索", "索", "す", "する", "る"]);
+        assert_eq!(tokenize("東京の天気", &TokenizerConfig::legacy()), vec!["東京の天気"]);
+
+        let japanese = TokenizerConfig {
+            stopwords: vec![String::from("の"), String::from("は")],
+            ..TokenizerConfig::default()
+        };
+        assert_eq!(tokenize("東京の天気は晴れ", &japanese), vec!["東", "東京", "京", "天", "天気", "気", "晴", "れ"]);
+
+        // a single-character query matches a longer word
+        let mut state = TfidfState::new(&Keywords::from_raw(vec![String::from("京")]), &english.tokenizer());
+        let doc = ProcessedDoc::new(Uid::dummy(), "東京都庁", &english.tokenizer());
+        state.consume(Uid::dummy(), &doc);
+        assert_eq!(state.get_top(10).len(), 1);
+    }
+
+    #[test]
+    fn field_weight_test() {
+        let config = TokenizerConfig::default().tokenizer();
+        let doc = |uid: u8, fields: Vec<(TfidfField, &str)>| ProcessedDoc::from_fields(
+            Uid::new_image(&[uid]),
+            fields.into_iter().map(|(field, content)| (field, FieldFrequency::new(content, &config))).collect(),
+        );
+        let title = doc(0, vec![(TfidfField::Title, "connection pool"), (TfidfField::Data, "a b c d")]);
+        let data = doc(1, vec![(TfidfField::Title, "a b"), (TfidfField::Data, "connection pool pool")]);
+        let weights = |title: f32| vec![(TfidfField::Title, title), (TfidfField::Data, 1.0)].into_iter().collect::<HashMap<_, _>>();
+
+        assert_eq!(title.get_weighted("pool", &weights(2.0)), 2.0);
+        assert_eq!(data.get_weighted("pool", &weights(2.0)), 2.0);
+        assert_eq!(title.get_weighted("pool", &weights(0.0)), 0.0);
+
+        // a doc without fields is not weighted
+        assert_eq!(ProcessedDoc::new(Uid::dummy(), "pool pool", &config).get_weighted("pool", &weights(5.0)), 2.0);
+
+        // the total term frequency is derived from the fields, and the title is counted twice, like the haystack
+        let haystack = ProcessedDoc::new(title.uid.unwrap(), "connection pool\nconnection pool\na b c d", &config);
+        assert_eq!(title.term_frequency, haystack.term_frequency);
+        assert_eq!(title.length(), haystack.length());
+
+        // the symbol is a part of the title
+        let mut chunk = Chunk::dummy(String::from("let conn = self.pool.pop();"), ChunkSource::File { path: String::from("pool.rs"), index: 0 });
+        chunk.title = String::from("Connection pool");
+        chunk.symbol = Some(String::from("Pool::acquire"));
+        let fields = ProcessedDoc::from_fields(
+            chunk.uid,
+            chunk.into_tfidf_fields("").unwrap().into_iter().map(|(field, content)| (field, FieldFrequency::new(&content, &config))).collect(),
+        );
+        assert_eq!(fields.fields[&TfidfField::Title].term_frequency.get("acquir"), Some(&1));
+        assert_eq!(fields.get("acquir"), Some(2));
+        assert_eq!(fields.term_frequency, ProcessedDoc::new(chunk.uid, &chunk.into_tfidf_haystack("").unwrap(), &config).term_frequency);
+
+        let rank = |title_weight: f32| {
+            let mut state = TfidfState::new(&Keywords::from_raw(vec![String::from("pool")]), &config);
+            state.set_field_weights(weights(title_weight));
+            state.consume(title.uid.unwrap(), &title);
+            state.consume(data.uid.unwrap(), &data);
+            state.get_top(10).into_iter().map(|result| result.id).collect::<Vec<_>>()
+        };
+
+        assert_eq!(rank(3.0), vec![title.uid.unwrap(), data.uid.unwrap()]);
+        assert_eq!(rank(1.0), vec![data.uid.unwrap(), title.uid.unwrap()]);
+        assert_eq!(rank(0.0), vec![data.uid.unwrap()]);
     }
 }
@@ -22,7 +22,11 @@ pub use index::{
     AddMode,
     AddResult,
     BuildConfig,
+    CorpusStats,
+    FieldFrequency,
+    IIFormat,
     IIStatus,
+    ImageDescription,
     Index,
     LoadMode,
     MergeMode,
@@ -30,16 +34,38 @@ pub use index::{
     ProcessedDoc,
     RecoverResult,
     RemoveResult,
+    STEMMERS,
+    Session,
+    Status,
+    TfidfField,
     TfidfResult,
+    Tokenizer,
+    TokenizerConfig,
     VersionInfo,
     get_compatibility_warning,
+    normalize_image,
 };
 pub use query::{
+    BooleanQuery,
+    BooleanSearchResult,
+    Citation,
+    EmbeddingMode,
+    ExpandedTerm,
+    ExpansionKind,
     Keywords,
     MultiTurnSchema,
+    OrderedF32,
+    Phrase,
+    PhraseKind,
     QueryConfig,
     QueryResponse,
     QueryTurn,
+    RetrievalCandidate,
+    RetrievalExplanation,
+    RetrievalFilter,
+    RetrievalStage,
+    TermDictionary,
+    expand_term,
 };
 pub use schema::{
     ChunkSchema,
@@ -1,8 +1,11 @@
 use async_recursion::async_recursion;
 use ragit::{
     AddMode,
+    BooleanQuery,
     ChunkSchema,
     Error,
+    ExpandedTerm,
+    ExpansionKind,
     IIStatus,
     Index,
     INDEX_DIR_NAME,
@@ -14,25 +17,35 @@ use ragit::{
     ProcessedDoc,
     QueryTurn,
     RemoveResult,
+    RetrievalFilter,
+    STEMMERS,
+    TermDictionary,
     UidQueryConfig,
+    expand_term,
     get_compatibility_warning,
     merge_and_convert_chunks,
+    normalize_image,
 };
 use ragit_cli::{
     ArgCount,
     ArgParser,
     ArgType,
+    ParsedArgs,
 };
 use ragit_fs::{
     basename,
+    extension,
     join,
     join3,
+    read_bytes,
     read_dir,
 };
-use ragit_pdl::encode_base64;
+use ragit_pdl::{ImageType, encode_base64};
 use serde_json::{Map, Value};
+use std::collections::HashSet;
 use std::env;
 use std::io::Write;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() {
@@ -49,6 +62,12 @@ async fn main() {
                 Error::InvalidConfigKey(k) => {
                     eprintln!("{k:?} is not a valid key for config.");
                 },
+                Error::InvalidConfigValue { key, message } => {
+                    eprintln!("{key:?}: {message}");
+                },
+                Error::InvalidStemmer(s) => {
+                    eprintln!("{s:?} is not a valid stemmer. Valid stemmers are: {}", STEMMERS.join(", "));
+                },
                 Error::CannotBuild(errors) => {
                     eprintln!("Cannot build knowledge-base due to {} errors", errors.len());
 
@@ -94,6 +113,21 @@ async fn main() {
                         span.2,
                     ));
                 },
+                Error::BooleanQueryError(message) => {
+                    eprintln!("invalid search expression: {message}");
+                },
+                Error::PdlError(ragit_pdl::Error::SchemaParseError(e)) => {
+                    eprintln!("invalid schema: {e:?}");
+                },
+                Error::CannotValidateResponse(schema) => {
+                    eprintln!("The model's response doesn't match the schema `{schema}`. Please try again, or try a simpler schema.");
+                },
+                Error::ModelCannotReadImages(model) => {
+                    eprintln!("`{model}` cannot read images. Run `rag ls-models` to find a model that can read images.");
+                },
+                Error::NoSuchSession(name) => {
+                    eprintln!("session `{name}` not found. Run `rag session --list` to see the sessions.");
+                },
                 e => {
                     eprintln!("{e:?}");
                 },
@@ -112,9 +146,9 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
         Some("add") => {
             let parsed_args = ArgParser::new()
                 .optional_flag(&["--reject", "--force"])
-                .optional_flag(&["--all"])
+                .optional_flag(&["--all", "--update"])
                 .optional_flag(&["--dry-run"])
-                .short_flag(&["--force"])
+                .short_flag(&["--force", "--update"])
                 .args(ArgType::Path, ArgCount::Any).parse(&args[2..])?;
 
             if parsed_args.show_help() {
@@ -125,12 +159,26 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
             let root_dir = root_dir?;
             let mut index = Index::load(root_dir.clone(), LoadMode::QuickCheck)?;
             let add_mode = parsed_args.get_flag(0).map(|flag| AddMode::parse_flag(&flag)).unwrap_or(None);
-            let all = parsed_args.get_flag(1).is_some();
+            let all = parsed_args.get_flag(1) == Some(String::from("--all"));
+            let update = parsed_args.get_flag(1) == Some(String::from("--update"));
             let dry_run = parsed_args.get_flag(2).is_some();
             let ignore_file = index.read_ignore_file()?;
 
             let mut files = parsed_args.get_args();
 
+            if update {
+                if !files.is_empty() || add_mode.is_some() {
+                    return Err(Error::CliError {
+                        message: String::from("You cannot use `--update` option with paths, `--force` or `--reject`."),
+                        span: (String::new(), 0, 0),  // TODO
+                    });
+                }
+
+                let result = index.add_update(dry_run)?;
+                println!("{result}");
+                return Ok(());
+            }
+
             if all {
                 if !files.is_empty() {
                     return Err(Error::CliError {
@@ -230,7 +278,9 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
         Some("build") => {
             let parsed_args = ArgParser::new()
                 .arg_flag_with_default("--jobs", "4", ArgType::UnsignedInteger)
+                .arg_flag_with_default("--debounce", "500", ArgType::UnsignedInteger)
                 .optional_flag(&["--quiet"])
+                .optional_flag(&["--watch"])
                 .short_flag(&["--quiet"])
                 .parse(&args[2..])?;
 
@@ -240,9 +290,18 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
             }
 
             let jobs = parsed_args.arg_flags.get("--jobs").as_ref().unwrap().parse::<usize>().unwrap();
+            let debounce = parsed_args.arg_flags.get("--debounce").as_ref().unwrap().parse::<u64>().unwrap();
             let quiet = parsed_args.get_flag(0).is_some();
+            let watch = parsed_args.get_flag(1).is_some();
             let mut index = Index::load(root_dir?, LoadMode::QuickCheck)?;
-            index.build(jobs, quiet).await?;
+
+            if watch {
+                index.watch(jobs, Duration::from_millis(debounce), quiet).await?;
+            }
+
+            else {
+                index.build(jobs, quiet).await?;
+            }
         },
         Some("cat-file") => {
             let parsed_args = ArgParser::new()
@@ -446,6 +505,21 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
                 _ => unreachable!(),
             }
         },
+        Some("embedding-build") => {
+            let parsed_args = ArgParser::new()
+                .optional_flag(&["--quiet"])
+                .short_flag(&["--quiet"])
+                .parse(&args[2..])?;
+
+            if parsed_args.show_help() {
+                println!("{}", include_str!("../docs/commands/embedding-build.txt"));
+                return Ok(());
+            }
+
+            let index = Index::load(root_dir?, LoadMode::QuickCheck)?;
+            let quiet = parsed_args.get_flag(0).is_some();
+            index.build_embeddings(quiet).await?;
+        },
         Some("extract-keywords") => {
             let parsed_args = ArgParser::new()
                 .optional_flag(&["--full-schema"])
@@ -1044,6 +1118,7 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
             let parsed_args = ArgParser::new()
                 .optional_flag(&["--term-only", "--stat-only"])
                 .optional_flag(&["--json"])
+                .optional_arg_flag("--expand", ArgType::String)
                 .args(ArgType::Query, ArgCount::Any).parse(&args[2..])?;
 
             if parsed_args.show_help() {
@@ -1088,6 +1163,41 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
                 }
             };
 
+            // It shows how `fuzzy_search` would expand the keyword, with the terms of `processed_doc`.
+            if let Some(keyword) = parsed_args.arg_flags.get("--expand") {
+                let mut terms = Keywords::from_raw(vec![keyword.to_string()]).tokenize(&index.tokenizer()).into_keys().collect::<Vec<_>>();
+                terms.sort();
+                let dictionary = TermDictionary::new(&processed_doc.term_frequency);
+                let expanded_terms = terms.into_iter().map(
+                    |term| {
+                        let expanded = expand_term(&term, &dictionary, &index.query_config);
+                        (term, expanded)
+                    }
+                ).collect::<Vec<_>>();
+
+                if json_mode {
+                    let mut result = Map::new();
+
+                    for (term, expanded) in expanded_terms.into_iter() {
+                        result.insert(term, serde_json::to_value(expanded)?);
+                    }
+
+                    println!("{}", serde_json::to_string_pretty(&result)?);
+                }
+
+                else {
+                    for (term, expanded) in expanded_terms.iter() {
+                        println!("{term:?}");
+
+                        for e in expanded.iter() {
+                            println!("    {}", render_expanded_term(e));
+                        }
+                    }
+                }
+
+                return Ok(());
+            }
+
             println!("{}", processed_doc.render(term_only, stat_only, json_mode));
             return Ok(());
         },
@@ -1230,6 +1340,14 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
             if !recover_result.is_empty() {
                 println!("recovered from a corrupted knowledge-base: {recover_result}");
             }
+
+            if index.migrate_ii()? {
+                println!("converted the inverted index to the compact format");
+            }
+
+            if let Some(count) = index.migrate_tfidf_fields()? {
+                println!("rebuilt {count} tfidf files, so that they have the term frequency of each field");
+            }
         },
         Some("push") => {
             let parsed_args = ArgParser::new()
@@ -1262,6 +1380,13 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
             let parsed_args = ArgParser::new()
                 .optional_flag(&["--interactive", "--multi-turn"])
                 .optional_flag(&["--json"])
+                .optional_arg_flag("--path", ArgType::String)
+                .optional_arg_flag("--file-ext", ArgType::String)
+                .optional_arg_flag("--after", ArgType::String)
+                .optional_arg_flag("--uid", ArgType::Query)
+                .optional_arg_flag("--session", ArgType::String)
+                .optional_arg_flag("--image", ArgType::Path)
+                .optional_arg_flag("--schema", ArgType::String)
                 .short_flag(&["--interactive"])
                 .args(ArgType::String, ArgCount::Any).parse(&args[2..])?;
 
@@ -1273,6 +1398,52 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
             let index = Index::load(root_dir?, LoadMode::OnlyJson)?;
             let interactive_mode = parsed_args.get_flag(0).is_some();
             let json_mode = parsed_args.get_flag(1).is_some();
+            let filter = parse_retrieval_filter(&parsed_args)?;
+
+            let mut session = match parsed_args.arg_flags.get("--session") {
+                Some(name) => {
+                    if !Index::is_valid_session_name(name) {
+                        return Err(Error::CliError {
+                            message: format!("`{name}` is not a valid session name. A name can only have alphanumeric characters, `-` and `_`."),
+                            span: (String::new(), 0, 0),  // TODO
+                        });
+                    }
+
+                    Some(index.load_or_create_session(name)?)
+                },
+                None => None,
+            };
+            let history = session.as_ref().map(|session| session.turns.clone()).unwrap_or_default();
+            let image = match parsed_args.arg_flags.get("--image") {
+                Some(path) => {
+                    if interactive_mode {
+                        return Err(Error::CliError {
+                            message: String::from("You cannot use `--image` in an interactive mode."),
+                            span: (String::new(), 0, 0),  // TODO
+                        });
+                    }
+
+                    let image_type = ImageType::from_extension(&extension(path)?.unwrap_or(String::new()))?;
+                    Some(normalize_image(read_bytes(path)?, image_type)?)
+                },
+                None => None,
+            };
+            let schema = parsed_args.arg_flags.get("--schema").map(|schema| schema.to_string());
+
+            if schema.is_some() && (interactive_mode || image.is_some()) {
+                return Err(Error::CliError {
+                    message: String::from("You cannot use `--schema` with `--interactive` or `--image`."),
+                    span: (String::new(), 0, 0),  // TODO
+                });
+            }
+
+            // If there's an image, the query text is optional.
+            let get_query = || -> Result<String, Error> {
+                match (&image, parsed_args.get_args().len()) {
+                    (Some(_), 0) => Ok(String::from("What is this image about?")),
+                    _ => Ok(parsed_args.get_args_exact(1)?[0].to_string()),
+                }
+            };
 
             match (interactive_mode, json_mode) {
                 (true, true) => {
@@ -1282,34 +1453,108 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
                     });
                 },
                 (true, _) => {
-                    let mut history = vec![];
+                    let mut history = history;
+
+                    if let Some(session) = &session {
+                        if !session.turns.is_empty() {
+                            println!("resuming session `{}` ({} turns)", session.name, session.turns.len());
+                        }
+                    }
 
                     loop {
                         let mut curr_input = String::new();
                         print!(">>> ");
                         std::io::stdout().flush()?;
-                        std::io::stdin().read_line(&mut curr_input)?;
-                        let response = index.query(
+
+                        // EOF (ctrl+D)
+                        if std::io::stdin().read_line(&mut curr_input)? == 0 {
+                            println!();
+                            break;
+                        }
+
+                        let curr_input = curr_input.trim().to_string();
+
+                        if curr_input.is_empty() {
+                            continue;
+                        }
+
+                        let response = index.query_stream(
                             &curr_input,
                             history.clone(),
+                            &filter,
+                            print_delta,
                         ).await?;
-                        println!("{}", response.response);
+                        println!();
                         history.push(QueryTurn::new(curr_input, response));
+
+                        // it saves the session after every turn, so that nothing is lost when the process is killed
+                        if let Some(session) = &mut session {
+                            session.turns = history.clone();
+                            index.save_session(session)?;
+                        }
                     }
                 },
-                _ => {
-                    let response = index.query(
-                        &parsed_args.get_args_exact(1)?[0],
-                        vec![],  // no history
-                    ).await?;
-
-                    if json_mode {
-                        println!("{}", serde_json::to_string_pretty(&response.prettify()?)?);
+                (_, true) => {
+                    let query = get_query()?;
+                    let response = match (&image, &schema) {
+                        (Some(image), _) => index.query_with_image(
+                            &query,
+                            image,
+                            history,
+                            &filter,
+                        ).await?,
+                        (_, Some(schema)) => index.query_with_schema(
+                            &query,
+                            schema,
+                            history,
+                            &filter,
+                        ).await?,
+                        (None, None) => index.query_with_filter(
+                            &query,
+                            history,
+                            &filter,
+                        ).await?,
+                    };
+                    println!("{}", serde_json::to_string_pretty(&response.prettify()?)?);
+
+                    if let Some(session) = &mut session {
+                        session.turns.push(QueryTurn::new(query, response));
+                        index.save_session(session)?;
                     }
-
-                    else {
-                        println!("{}", response.response);
-
+                },
+                (_, false) => {
+                    let query = get_query()?;
+                    let response = match (&image, &schema) {
+                        (Some(image), _) => index.query_with_image_stream(
+                            &query,
+                            image,
+                            history,
+                            &filter,
+                            print_delta,
+                        ).await?,
+                        // It cannot stream the response because the response has to be validated first.
+                        (_, Some(schema)) => {
+                            let response = index.query_with_schema(
+                                &query,
+                                schema,
+                                history,
+                                &filter,
+                            ).await?;
+                            print!("{}", response.response);
+                            response
+                        },
+                        (None, None) => index.query_stream(
+                            &query,
+                            history,
+                            &filter,
+                            print_delta,
+                        ).await?,
+                    };
+
+                    println!();
+
+                    // With `--schema`, it prints only the json value, so that you can pipe it to other tools.
+                    if schema.is_none() {
                         if !response.retrieved_chunks.is_empty() {
                             println!("\n---- sources ----");
 
@@ -1317,6 +1562,27 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
                                 println!("{} ({})", chunk.render_source(), chunk.uid.get_short_name());
                             }
                         }
+
+                        if !response.citations.is_empty() {
+                            println!("\n---- citations ----");
+                            let mut printed = HashSet::new();
+
+                            // the same chunk can be cited multiple times
+                            for citation in response.citations.iter() {
+                                if !printed.insert((citation.marker, citation.uid)) {
+                                    continue;
+                                }
+
+                                if let Some(source) = response.get_citation_source(citation) {
+                                    println!("[{}] {} ({})", citation.marker, source.render(), citation.uid.get_short_name());
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(session) = &mut session {
+                        session.turns.push(QueryTurn::new(query, response));
+                        index.save_session(session)?;
                     }
                 },
             }
@@ -1398,8 +1664,13 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
                 .optional_flag(&["--uid-only"])
                 .optional_flag(&["--json"])
                 .flag_with_default(&["--rerank", "--no-rerank"])
+                .optional_flag(&["--explain"])
                 .optional_arg_flag("--max-retrieval", ArgType::UnsignedInteger)
                 .optional_arg_flag("--max-summaries", ArgType::UnsignedInteger)
+                .optional_arg_flag("--path", ArgType::String)
+                .optional_arg_flag("--file-ext", ArgType::String)
+                .optional_arg_flag("--after", ArgType::String)
+                .optional_arg_flag("--uid", ArgType::Query)
                 .args(ArgType::Query, ArgCount::Exact(1)).parse(&args[2..])?;
 
             if parsed_args.show_help() {
@@ -1410,9 +1681,10 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
             let uid_only = parsed_args.get_flag(0).is_some();
             let json_mode = parsed_args.get_flag(1).is_some();
             let rerank = parsed_args.get_flag(2).unwrap() == "--rerank";
-            let index = Index::load(root_dir?, LoadMode::OnlyJson)?;
+            let explain = parsed_args.get_flag(3).is_some();
+            let mut index = Index::load(root_dir?, LoadMode::OnlyJson)?;
 
-            let max_retrieval = match parsed_args.arg_flags.get("--max-chunks") {
+            let max_retrieval = match parsed_args.arg_flags.get("--max-retrieval") {
                 Some(n) => n.parse::<usize>().unwrap(),
                 None => index.query_config.max_retrieval,
             };
@@ -1421,6 +1693,24 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
                 None => index.query_config.max_summaries,
             };
             let query = parsed_args.get_args_exact(1)?[0].clone();
+            let filter = parse_retrieval_filter(&parsed_args)?;
+
+            // It runs the same pipeline as `rag query`, and shows the candidates of each stage.
+            if explain {
+                index.query_config.max_retrieval = max_retrieval;
+                index.query_config.max_summaries = max_summaries;
+                let (_, explanation) = index.retrieve_chunks_with_explanation(&query, &filter).await?;
+
+                if json_mode {
+                    println!("{}", serde_json::to_string_pretty(&explanation.to_json()?)?);
+                }
+
+                else {
+                    println!("{}", explanation.render_table());
+                }
+
+                return Ok(());
+            }
 
             let keywords = {
                 let keywords = index.extract_keywords(&parsed_args.get_args_exact(1)?[0]).await?;
@@ -1434,14 +1724,10 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
                     keywords
                 }
             };
-            let tfidf_results = index.run_tfidf(
-                keywords,
-                max_summaries,
-            )?;
-            let mut chunks = Vec::with_capacity(tfidf_results.len());
+            let mut chunks = vec![];
 
-            for tfidf_result in tfidf_results.iter() {
-                chunks.push(index.get_chunk_by_uid(tfidf_result.id)?);
+            for (uid, _) in index.run_hybrid_search_with_filter(&query, Some(keywords), max_summaries, &filter).await? {
+                chunks.push(index.get_chunk_by_uid(uid)?);
             }
 
             if rerank {
@@ -1496,6 +1782,207 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
                 }
             }
         },
+        Some("search") => {
+            let parsed_args = ArgParser::new()
+                .optional_flag(&["--uid-only"])
+                .optional_flag(&["--json"])
+                .args(ArgType::Query, ArgCount::Exact(1)).parse(&args[2..])?;
+
+            if parsed_args.show_help() {
+                println!("{}", include_str!("../docs/commands/search.txt"));
+                return Ok(());
+            }
+
+            let uid_only = parsed_args.get_flag(0).is_some();
+            let json_mode = parsed_args.get_flag(1).is_some();
+            let index = Index::load(root_dir?, LoadMode::OnlyJson)?;
+            let query = BooleanQuery::parse(&parsed_args.get_args_exact(1)?[0], &index.tokenizer())?;
+            let results = index.search_boolean(&query)?;
+
+            if json_mode {
+                if uid_only {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(
+                            &results.iter().map(
+                                |result| result.uid.to_string()
+                            ).collect::<Vec<_>>(),
+                        )?,
+                    );
+                }
+
+                else {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(
+                            &results.iter().map(
+                                |result| [
+                                    (String::from("uid"), result.uid.to_string().into()),
+                                    (String::from("source"), result.source.render().into()),
+                                    (String::from("path"), result.path().map(|path| path.to_string()).into()),
+                                ].into_iter().collect::<Map<String, Value>>(),
+                            ).collect::<Vec<_>>(),
+                        )?,
+                    );
+                }
+            }
+
+            else {
+                for result in results.iter() {
+                    if uid_only {
+                        println!("{}", result.uid);
+                    }
+
+                    else {
+                        println!("{} {}", result.uid, result.source.render());
+                    }
+                }
+            }
+        },
+        Some("session") => {
+            let parsed_args = ArgParser::new()
+                .flag(&["--list", "--show", "--remove", "--rm", "--remove-all"])
+                .optional_flag(&["--json"])
+                .args(ArgType::String, ArgCount::Any).parse(&args[2..])?;
+
+            if parsed_args.show_help() {
+                println!("{}", include_str!("../docs/commands/session.txt"));
+                return Ok(());
+            }
+
+            let index = Index::load(root_dir?, LoadMode::OnlyJson)?;
+            let flag = parsed_args.get_flag(0).unwrap();
+            let json_mode = parsed_args.get_flag(1).is_some();
+
+            match flag.as_str() {
+                "--list" => {
+                    parsed_args.get_args_exact(0)?;
+                    let sessions = index.get_all_sessions()?;
+
+                    if json_mode {
+                        println!("{}", serde_json::to_string_pretty(&sessions.iter().map(
+                            |session| {
+                                let mut result = Map::new();
+                                result.insert(String::from("name"), session.name.clone().into());
+                                result.insert(String::from("turns"), session.turns.len().into());
+                                result.insert(String::from("created_at"), session.created_at.into());
+                                result.insert(String::from("updated_at"), session.updated_at.into());
+                                result.into()
+                            }
+                        ).collect::<Vec<Value>>())?);
+                    }
+
+                    else {
+                        println!("{} sessions", sessions.len());
+
+                        for session in sessions.iter() {
+                            println!("----------");
+                            println!("name: {}", session.name);
+                            println!("turns: {}", session.turns.len());
+                            println!("updated at: {}", chrono::DateTime::from_timestamp(session.updated_at, 0).map(|d| d.with_timezone(&chrono::Local).to_rfc3339()).unwrap_or_default());
+                        }
+                    }
+                },
+                "--show" => {
+                    let name = &parsed_args.get_args_exact(1)?[0];
+                    let session = index.load_session(name)?;
+
+                    if json_mode {
+                        let mut turns = Vec::with_capacity(session.turns.len());
+
+                        for turn in session.turns.iter() {
+                            let mut turn_json = Map::new();
+                            turn_json.insert(String::from("query"), turn.query.clone().into());
+                            turn_json.insert(String::from("response"), turn.response.prettify()?);
+                            turns.push(Value::Object(turn_json));
+                        }
+
+                        println!("{}", serde_json::to_string_pretty(&turns)?);
+                    }
+
+                    else {
+                        for turn in session.turns.iter() {
+                            println!(">>> {}", turn.query);
+                            println!("{}\n", turn.response.response);
+                        }
+                    }
+                },
+                "--remove" | "--rm" => {
+                    let name = &parsed_args.get_args_exact(1)?[0];
+                    index.remove_session(name)?;
+                    println!("removed session `{name}`");
+                },
+                "--remove-all" => {
+                    parsed_args.get_args_exact(0)?;
+                    let sessions = index.get_all_sessions()?;
+
+                    for session in sessions.iter() {
+                        index.remove_session(&session.name)?;
+                    }
+
+                    println!("removed {} sessions", sessions.len());
+                },
+                _ => unreachable!(),
+            }
+        },
+        Some("status") => {
+            let parsed_args = ArgParser::new()
+                .optional_flag(&["--json"])
+                .args(ArgType::String, ArgCount::None).parse(&args[2..])?;
+
+            if parsed_args.show_help() {
+                println!("{}", include_str!("../docs/commands/status.txt"));
+                return Ok(());
+            }
+
+            let index = Index::load(root_dir?, LoadMode::QuickCheck)?;
+            let json_mode = parsed_args.get_flag(0).is_some();
+            let status = index.get_status(&index.read_ignore_file()?)?;
+
+            if json_mode {
+                println!("{}", serde_json::to_string_pretty(&status)?);
+            }
+
+            else if status.is_clean() {
+                println!("nothing to build, the knowledge-base is up to date");
+            }
+
+            else {
+                if !status.staged.is_empty() {
+                    println!("staged files (run `rag build` to process them):");
+
+                    for file in status.staged.iter() {
+                        println!("    {file}");
+                    }
+
+                    println!();
+                }
+
+                if !status.modified.is_empty() || !status.deleted.is_empty() {
+                    println!("changed files (run `rag add --update` to stage them):");
+
+                    for file in status.modified.iter() {
+                        println!("    modified: {file}");
+                    }
+
+                    for file in status.deleted.iter() {
+                        println!("    deleted:  {file}");
+                    }
+
+                    println!();
+                }
+
+                if !status.new.is_empty() {
+                    println!("new files (run `rag add <file>` or `rag add --all` to stage them):");
+
+                    for file in status.new.iter() {
+                        println!("    {file}");
+                    }
+
+                    println!();
+                }
+            }
+        },
         // tmp command for testing `Index::summary_file`
         // this interface is likely to change
         Some("summary-file") => {
@@ -1538,13 +2025,35 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
             } else {
                 Keywords::from_raw(parsed_args.get_args())
             };
-            let tokenized_keywords = keywords.tokenize();
+            let tokenized_keywords = keywords.tokenize(&index.tokenizer());
             let limit = parsed_args.arg_flags.get("--limit").map(|n| n.parse::<usize>().unwrap()).unwrap();
 
             if !uid_only && !json_mode {
                 println!("search keywords: {:?}", parsed_args.get_args());
                 println!("tokenized keywords: {:?}", tokenized_keywords.iter().map(|(token, _)| token).collect::<Vec<_>>());
 
+                if index.query_config.fuzzy_search {
+                    let expanded_terms = index.expand_terms(&tokenized_keywords)?;
+                    let mut terms = expanded_terms.keys().collect::<Vec<_>>();
+                    terms.sort();
+
+                    for term in terms.into_iter() {
+                        let expanded_terms = expanded_terms.get(term).unwrap();
+
+                        if !expanded_terms.is_empty() {
+                            println!("{term:?} is expanded to {}", expanded_terms.iter().map(render_expanded_term).collect::<Vec<_>>().join(", "));
+                        }
+                    }
+                }
+
+                if index.is_tfidf_outdated() {
+                    println!("tokenizer config has changed. Run `rag build` to rebuild the tfidf files.");
+                }
+
+                if !index.tfidf_fields {
+                    println!("tfidf files are built by an older version of ragit, so the field weights are ignored. Run `rag migrate` to rebuild them.");
+                }
+
                 match index.ii_status {
                     IIStatus::None => if index.query_config.enable_ii {
                         println!("inverted-index not found");
@@ -1666,6 +2175,45 @@ async fn run(args: Vec<String>) -> Result<(), Error> {
     Ok(())
 }
 
+// `rag query` prints the response as the model generates it
+fn print_delta(delta: &str) {
+    print!("{delta}");
+    let _ = std::io::stdout().flush();
+}
+
+// `rag tfidf` and `rag ls-terms --expand`
+fn render_expanded_term(expanded: &ExpandedTerm) -> String {
+    match expanded.kind {
+        ExpansionKind::Prefix => format!("{:?} (prefix, weight {:.2})", expanded.term, expanded.weight),
+        ExpansionKind::Typo => format!("{:?} (typo, distance {}, weight {:.2})", expanded.term, expanded.distance, expanded.weight),
+    }
+}
+
+// `--path`, `--file-ext`, `--after` and `--uid` of `rag query` and `rag retrieve-chunks`
+fn parse_retrieval_filter(parsed_args: &ParsedArgs) -> Result<RetrievalFilter, Error> {
+    let after = match parsed_args.arg_flags.get("--after") {
+        Some(date) => match RetrievalFilter::parse_date(date) {
+            Some(timestamp) => Some(timestamp),
+            None => {
+                return Err(Error::CliError {
+                    message: format!("`{date}` is not a valid date. Please use `YYYY-MM-DD` or rfc 3339."),
+                    span: (String::new(), 0, 0),  // TODO
+                });
+            },
+        },
+        None => None,
+    };
+
+    Ok(RetrievalFilter {
+        path: parsed_args.arg_flags.get("--path").map(|path| path.to_string()),
+        file_ext: parsed_args.arg_flags.get("--file-ext").map(
+            |exts| exts.split(",").map(|ext| ext.trim().to_string()).filter(|ext| !ext.is_empty()).collect()
+        ).unwrap_or_default(),
+        after,
+        uid: parsed_args.arg_flags.get("--uid").map(|uid| uid.to_string()),
+    })
+}
+
 // it starts from "." and goes up until it finds ".ragit"
 // you can run git commands anywhere inside a repo, and I want ragit to be like that
 fn find_root() -> Result<String, Error> {
@@ -1,29 +1,63 @@
-use crate::chunk::{Chunk, RenderableChunk, merge_and_convert_chunks};
+use crate::chunk::{Chunk, ChunkSource, RenderableChunk, merge_and_convert_chunks_with_uids};
 use crate::error::Error;
-use crate::index::Index;
+use crate::index::{ImageDescription, Index};
 use ragit_api::{
     RecordAt,
     Request,
 };
 use ragit_pdl::{
     Pdl,
+    encode_base64,
     escape_pdl_tokens,
     parse_pdl,
+    parse_schema,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 
+mod boolean;
+mod citation;
 pub mod config;
+mod diversity;
+mod explain;
+mod filter;
+mod fusion;
+mod fuzzy;
 mod keyword;
 
-pub use config::QueryConfig;
-pub use keyword::Keywords;
+pub use boolean::{BooleanQuery, BooleanSearchResult};
+pub use citation::Citation;
+pub use config::{EmbeddingMode, OrderedF32, QueryConfig};
+pub use explain::{RetrievalCandidate, RetrievalExplanation, RetrievalStage};
+pub use filter::RetrievalFilter;
+pub use fuzzy::{ExpandedTerm, ExpansionKind, TermDictionary, expand_term};
+pub use keyword::{Keywords, Phrase, PhraseKind};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct QueryResponse {
     pub multi_turn_schema: Option<MultiTurnSchema>,
     pub retrieved_chunks: Vec<Chunk>,
+
+    /// If the query has a schema (`query_with_schema`), it's a json value that matches the schema.
     pub response: String,
+
+    /// Parts of `response` and the chunks that support them. It's empty if
+    /// no chunks are retrieved, the query has a schema or the model didn't cite any chunk.
+    #[serde(default)]
+    pub citations: Vec<Citation>,
+
+    /// If the query has an image, the model describes the image and
+    /// the description is used to retrieve chunks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_description: Option<ImageDescription>,
+}
+
+impl QueryResponse {
+    /// It finds the source of the cited chunk, so that you can render a footnote with `ChunkSource::render`.
+    pub fn get_citation_source(&self, citation: &Citation) -> Option<&ChunkSource> {
+        self.retrieved_chunks.iter().find(|chunk| chunk.uid == citation.uid).map(|chunk| &chunk.source)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -40,13 +74,42 @@ impl QueryTurn {
 
 impl Index {
     pub async fn retrieve_chunks(&self, query: &str) -> Result<Vec<Chunk>, Error> {
-        let mut chunks = self.load_chunks_or_tfidf(query).await?;
+        self.retrieve_chunks_with_filter(query, &RetrievalFilter::default()).await
+    }
+
+    pub async fn retrieve_chunks_with_filter(&self, query: &str, filter: &RetrievalFilter) -> Result<Vec<Chunk>, Error> {
+        self.retrieve_chunks_worker(query, filter, None).await
+    }
+
+    /// It's like `retrieve_chunks_with_filter`, but it also tells you the candidates of
+    /// each stage. It's useful when you want to know why a chunk is (not) retrieved.
+    pub async fn retrieve_chunks_with_explanation(&self, query: &str, filter: &RetrievalFilter) -> Result<(Vec<Chunk>, RetrievalExplanation), Error> {
+        let mut explanation = RetrievalExplanation {
+            query: query.to_string(),
+            ..RetrievalExplanation::default()
+        };
+        let chunks = self.retrieve_chunks_worker(query, filter, Some(&mut explanation)).await?;
+        Ok((chunks, explanation))
+    }
+
+    async fn retrieve_chunks_worker(
+        &self,
+        query: &str,
+        filter: &RetrievalFilter,
+        mut explanation: Option<&mut RetrievalExplanation>,
+    ) -> Result<Vec<Chunk>, Error> {
+        let mut chunks = self.load_chunks_or_tfidf(query, filter, explanation.as_deref_mut()).await?;
 
         if chunks.len() > self.query_config.max_summaries {
             chunks = self.titles_to_summaries(
                 query,
                 chunks.into_iter().map(|c| c.into()).collect(),
             ).await?;
+
+            if let Some(explanation) = &mut explanation {
+                let stage = RetrievalStage::reranked("titles_to_summaries", explanation.stages.last().unwrap(), &chunks);
+                explanation.stages.push(stage);
+            }
         }
 
         if chunks.len() > self.query_config.max_retrieval {
@@ -55,6 +118,11 @@ impl Index {
                 chunks.into_iter().map(|c| c.into()).collect(),
                 self.query_config.max_retrieval,
             ).await?;
+
+            if let Some(explanation) = &mut explanation {
+                let stage = RetrievalStage::reranked("summaries_to_chunks", explanation.stages.last().unwrap(), &chunks);
+                explanation.stages.push(stage);
+            }
         }
 
         Ok(chunks)
@@ -74,6 +142,90 @@ impl Index {
         q: &str,
         history: Vec<QueryTurn>,
     ) -> Result<QueryResponse, Error> {
+        self.query_with_filter(q, history, &RetrievalFilter::default()).await
+    }
+
+    /// `filter` restricts which chunks can be retrieved. See `RetrievalFilter`.
+    pub async fn query_with_filter(
+        &self,
+        q: &str,
+        history: Vec<QueryTurn>,
+        filter: &RetrievalFilter,
+    ) -> Result<QueryResponse, Error> {
+        self.query_worker(q, None, None, history, filter, None).await
+    }
+
+    /// It's like `query_with_filter`, but the query comes with an image. The model
+    /// has to be able to read images. `image` must be a png file, use `normalize_image`
+    /// if it's not.
+    ///
+    /// The model describes the image with `prompts/describe_image.pdl`, and the
+    /// description is used to retrieve chunks. The image is given to the model
+    /// when it answers the query.
+    pub async fn query_with_image(
+        &self,
+        q: &str,
+        image: &[u8],
+        history: Vec<QueryTurn>,
+        filter: &RetrievalFilter,
+    ) -> Result<QueryResponse, Error> {
+        self.query_worker(q, Some(image), None, history, filter, None).await
+    }
+
+    /// It's like `query_with_filter`, but the response is a json value that matches
+    /// `schema`. `schema` is written in pdl's schema syntax, like `{ name: str, age: int }`.
+    /// If the model fails to follow the schema after a few tries, it returns
+    /// `Error::CannotValidateResponse`.
+    pub async fn query_with_schema(
+        &self,
+        q: &str,
+        schema: &str,
+        history: Vec<QueryTurn>,
+        filter: &RetrievalFilter,
+    ) -> Result<QueryResponse, Error> {
+        self.query_worker(q, None, Some(schema), history, filter, None).await
+    }
+
+    /// It's like `query_with_filter`, but `on_delta` is called with each piece of
+    /// the response as soon as the model generates it. The returned `QueryResponse`
+    /// has the entire response.
+    pub async fn query_stream<F: FnMut(&str) + Send>(
+        &self,
+        q: &str,
+        history: Vec<QueryTurn>,
+        filter: &RetrievalFilter,
+        mut on_delta: F,
+    ) -> Result<QueryResponse, Error> {
+        self.query_worker(q, None, None, history, filter, Some(&mut on_delta)).await
+    }
+
+    /// It's like `query_with_image`, but `on_delta` is called with each piece of
+    /// the response, like `query_stream`.
+    pub async fn query_with_image_stream<F: FnMut(&str) + Send>(
+        &self,
+        q: &str,
+        image: &[u8],
+        history: Vec<QueryTurn>,
+        filter: &RetrievalFilter,
+        mut on_delta: F,
+    ) -> Result<QueryResponse, Error> {
+        self.query_worker(q, Some(image), None, history, filter, Some(&mut on_delta)).await
+    }
+
+    async fn query_worker(
+        &self,
+        q: &str,
+        image: Option<&[u8]>,
+        schema: Option<&str>,
+        history: Vec<QueryTurn>,
+        filter: &RetrievalFilter,
+        on_delta: Option<&mut (dyn FnMut(&str) + Send)>,
+    ) -> Result<QueryResponse, Error> {
+        // It checks the schema before it calls any api.
+        if let Some(schema) = schema {
+            parse_schema(schema.as_bytes()).map_err(ragit_pdl::Error::from)?;
+        }
+
         let (multi_turn_schema, query) = if history.is_empty() {
             (None, q.to_string())
         } else {
@@ -88,9 +240,28 @@ impl Index {
 
             (Some(multi_turn_schema), query)
         };
-        let chunks = self.retrieve_chunks(&query).await?;
+        let image_description = match image {
+            Some(image) => {
+                let model = self.get_model_by_name(&self.api_config.model)?;
 
-        let response = if chunks.is_empty() {
+                if !model.can_read_images {
+                    return Err(Error::ModelCannotReadImages(model.name));
+                }
+
+                Some(self.describe_image(image).await?)
+            },
+            None => None,
+        };
+        let retrieval_query = match &image_description {
+            Some(ImageDescription { extracted_text, explanation }) => [query.as_str(), extracted_text, explanation].into_iter().filter(
+                |s| !s.trim().is_empty()
+            ).collect::<Vec<_>>().join("\n\n"),
+            None => query.clone(),
+        };
+        let image = image.map(encode_base64);
+        let chunks = self.retrieve_chunks_with_filter(&retrieval_query, filter).await?;
+
+        let (response, citations) = if chunks.is_empty() {
             let mut history_turns = Vec::with_capacity(history.len() * 2);
 
             for h in history.iter() {
@@ -98,21 +269,47 @@ impl Index {
                 history_turns.push(h.response.response.clone());
             }
 
-            self.raw_request(
+            let response = self.raw_request_worker(
                 q,
+                image.as_deref(),
+                schema,
                 history_turns,
-            ).await?
+                on_delta,
+            ).await?;
+
+            (response, vec![])
         } else {
-            self.answer_query_with_chunks(
+            // Neighbours are not in `retrieved_uids`, so a citation of a widened
+            // chunk points to the retrieved chunk in it.
+            let retrieved_uids = chunks.iter().map(|chunk| chunk.uid).collect::<HashSet<_>>();
+            let (renderable_chunks, chunk_uids): (Vec<_>, Vec<_>) = merge_and_convert_chunks_with_uids(
+                self,
+                self.expand_chunks_with_neighbours(&chunks)?,
+            )?.into_iter().unzip();
+            let response = self.answer_query_with_chunks_worker(
                 &query,
-                merge_and_convert_chunks(self, chunks.clone())?,
-            ).await?
+                image.as_deref(),
+                schema,
+                renderable_chunks,
+                on_delta,
+            ).await?;
+
+            // `prompts/answer_query.pdl` doesn't ask for citations if there's a schema,
+            // and a json value like `[1, 2]` is not a list of citations.
+            let citations = match schema {
+                Some(_) => vec![],
+                None => citation::parse_citations(&response, &chunk_uids, &retrieved_uids),
+            };
+
+            (response, citations)
         };
 
         Ok(QueryResponse {
             multi_turn_schema,
             retrieved_chunks: chunks,
             response,
+            citations,
+            image_description,
         })
     }
 
@@ -242,25 +439,82 @@ impl Index {
         ).collect())
     }
 
+    /// It widens each chunk with its neighbours in the same file: up to
+    /// `query_config.context_chunks` chunks before and after it. Closer neighbours
+    /// come first, and it stops widening a chunk when its neighbours exceed
+    /// `query_config.context_budget` characters. The result has the original chunks
+    /// and the neighbours, and `merge_and_convert_chunks` stitches them together.
+    pub fn expand_chunks_with_neighbours(&self, chunks: &[Chunk]) -> Result<Vec<Chunk>, Error> {
+        let mut result = chunks.to_vec();
+
+        if self.query_config.context_chunks == 0 {
+            return Ok(result);
+        }
+
+        let mut uids = chunks.iter().map(|chunk| chunk.uid).collect::<HashSet<_>>();
+        let mut chunks_of_file = HashMap::new();
+
+        for chunk in chunks.iter() {
+            let ChunkSource::File { path, index } = &chunk.source else { continue; };
+            let Some(file_uid) = self.processed_files.get(path) else { continue; };
+
+            if !chunks_of_file.contains_key(path) {
+                chunks_of_file.insert(path.to_string(), self.get_chunks_of_file(*file_uid)?);
+            }
+
+            let siblings = chunks_of_file.get(path).unwrap();
+            let mut budget = self.query_config.context_budget;
+
+            'expand: for distance in 1..=self.query_config.context_chunks {
+                for sibling_index in [index.checked_sub(distance), Some(index + distance)].into_iter().flatten() {
+                    let Some(sibling_uid) = siblings.get(sibling_index) else { continue; };
+
+                    if uids.contains(sibling_uid) {
+                        continue;
+                    }
+
+                    let sibling = self.get_chunk_by_uid(*sibling_uid)?;
+
+                    // It stops instead of skipping a big sibling, so that the
+                    // neighbours are always contiguous with the chunk.
+                    if sibling.char_len > budget {
+                        break 'expand;
+                    }
+
+                    budget -= sibling.char_len;
+                    uids.insert(*sibling_uid);
+                    result.push(sibling);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// `prompts/answer_query.pdl` asks the model to cite the chunks with their
+    /// (1-based) indices in `chunks`, like `[1]`. `query` parses the markers
+    /// into `QueryResponse::citations`.
     pub async fn answer_query_with_chunks(
         &self,
         query: &str,
         chunks: Vec<RenderableChunk>,
     ) -> Result<String, Error> {
-        let mut tera_context = tera::Context::new();
-        tera_context.insert(
-            "chunks",
-            &chunks,  // it's already escaped
-        );
-        tera_context.insert(
-            "query",
-            &escape_pdl_tokens(&query),
-        );
+        self.answer_query_with_chunks_worker(query, None, None, chunks, None).await
+    }
 
+    // `image` is a base64-encoded png file.
+    async fn answer_query_with_chunks_worker(
+        &self,
+        query: &str,
+        image: Option<&str>,
+        schema: Option<&str>,
+        chunks: Vec<RenderableChunk>,
+        on_delta: Option<&mut (dyn FnMut(&str) + Send)>,
+    ) -> Result<String, Error> {
         let Pdl { messages, .. } = parse_pdl(
             &self.get_prompt("answer_query")?,
-            &tera_context,
-            "/",  // TODO: `<|media|>` is not supported for this prompt
+            &answer_query_context(query, image, schema, &chunks),
+            "/",  // the image is given with `<|raw_media|>`, so it doesn't need `curr_dir`
             true,
             true,
         )?;
@@ -280,9 +534,10 @@ impl Index {
             ..Request::default()
         };
 
-        let response = request.send().await?;
-
-        Ok(response.get_message(0).unwrap().to_string())
+        match schema {
+            Some(schema) => send_with_schema(request, schema).await,
+            None => send_with_callback(request, on_delta).await,
+        }
     }
 
     pub async fn rephrase_multi_turn(
@@ -328,14 +583,22 @@ impl Index {
         query: &str,
         history: Vec<String>,
     ) -> Result<String, Error> {
-        let mut tera_context = tera::Context::new();
-        tera_context.insert("query", &escape_pdl_tokens(&query));
-        tera_context.insert("history", &history.iter().map(|h| escape_pdl_tokens(h)).collect::<Vec<_>>());
+        self.raw_request_worker(query, None, None, history, None).await
+    }
 
+    // `image` is a base64-encoded png file.
+    async fn raw_request_worker(
+        &self,
+        query: &str,
+        image: Option<&str>,
+        schema: Option<&str>,
+        history: Vec<String>,
+        on_delta: Option<&mut (dyn FnMut(&str) + Send)>,
+    ) -> Result<String, Error> {
         let Pdl { messages, .. } = parse_pdl(
             &self.get_prompt("raw")?,
-            &tera_context,
-            "/",  // TODO: `<|media|>` is not supported for this prompt
+            &raw_request_context(query, image, schema, &history),
+            "/",  // the image is given with `<|raw_media|>`, so it doesn't need `curr_dir`
             true,
             true,
         )?;
@@ -354,11 +617,49 @@ impl Index {
             ..Request::default()
         };
 
-        let response = request.send().await?;
-        Ok(response.get_message(0).unwrap().to_string())
+        match schema {
+            Some(schema) => send_with_schema(request, schema).await,
+            None => send_with_callback(request, on_delta).await,
+        }
     }
 }
 
+// context of `prompts/answer_query.pdl`. `image` is a base64-encoded png file.
+fn answer_query_context(
+    query: &str,
+    image: Option<&str>,
+    schema: Option<&str>,
+    chunks: &[RenderableChunk],
+) -> tera::Context {
+    let mut tera_context = tera::Context::new();
+    tera_context.insert(
+        "chunks",
+        chunks,  // it's already escaped
+    );
+    tera_context.insert(
+        "query",
+        &escape_pdl_tokens(query),
+    );
+    tera_context.insert("image", &image);
+    tera_context.insert("schema", &schema.map(escape_pdl_tokens));
+    tera_context
+}
+
+// context of `prompts/raw.pdl`. `image` is a base64-encoded png file.
+fn raw_request_context(
+    query: &str,
+    image: Option<&str>,
+    schema: Option<&str>,
+    history: &[String],
+) -> tera::Context {
+    let mut tera_context = tera::Context::new();
+    tera_context.insert("query", &escape_pdl_tokens(query));
+    tera_context.insert("history", &history.iter().map(|h| escape_pdl_tokens(h)).collect::<Vec<_>>());
+    tera_context.insert("image", &image);
+    tera_context.insert("schema", &schema.map(escape_pdl_tokens));
+    tera_context
+}
+
 /// Ragit supports multi-turn conversations. Since the pipeline
 /// can handle only 1 query at a time, a multi-turn conversation
 /// has to be rephrased into a single query. Ragit uses a
@@ -383,6 +684,43 @@ impl Default for MultiTurnSchema {
     }
 }
 
+// If `on_delta` is given, it streams the response.
+async fn send_with_callback(
+    request: Request,
+    on_delta: Option<&mut (dyn FnMut(&str) + Send)>,
+) -> Result<String, Error> {
+    match on_delta {
+        Some(on_delta) => {
+            let mut stream = request.send_stream().await?;
+
+            while let Some(delta) = stream.next().await? {
+                on_delta(&delta);
+            }
+
+            Ok(stream.into_response().get_message(0).unwrap().to_string())
+        },
+        None => {
+            let response = request.send().await?;
+            Ok(response.get_message(0).unwrap().to_string())
+        },
+    }
+}
+
+// If the response doesn't match the schema, it tells the model what's wrong and asks again.
+// It returns an error if the response still doesn't match the schema after `schema_max_try` tries.
+async fn send_with_schema(
+    mut request: Request,
+    schema: &str,
+) -> Result<String, Error> {
+    request.schema = Some(parse_schema(schema.as_bytes()).map_err(ragit_pdl::Error::from)?);
+    request.schema_max_try = 3;
+
+    match request.send_and_validate::<Option<Value>>(None).await? {
+        Some(value) => Ok(serde_json::to_string_pretty(&value)?),
+        None => Err(Error::CannotValidateResponse(schema.to_string())),
+    }
+}
+
 fn select_turns_for_context(history: &[QueryTurn], query: &str) -> Vec<String> {
     match history.len() {
         0 => unreachable!(),
@@ -439,3 +777,71 @@ fn select_turns_for_context(history: &[QueryTurn], query: &str) -> Vec<String> {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{answer_query_context, raw_request_context};
+    use crate::chunk::RenderableChunk;
+    use crate::prompts::PROMPTS;
+    use ragit_pdl::{MessageContent, Pdl, Role, encode_base64, parse_pdl};
+
+    #[test]
+    fn image_query_test() {
+        let image = encode_base64(b"png bytes");
+
+        for (prompt, context) in [
+            ("answer_query", answer_query_context("what is this?", Some(&image), None, &[])),
+            ("raw", raw_request_context("what is this?", Some(&image), None, &[])),
+        ] {
+            let Pdl { messages, .. } = parse_pdl(PROMPTS.get(prompt).unwrap(), &context, "/", true, true).unwrap();
+
+            // the image comes with the query
+            let image_message = messages.iter().find(
+                |message| message.content.iter().any(
+                    |content| matches!(content, MessageContent::Image { bytes, .. } if bytes == b"png bytes")
+                )
+            ).unwrap();
+            assert_eq!(image_message.role, Role::User, "{prompt}");
+            assert!(image_message.content.iter().any(
+                |content| matches!(content, MessageContent::String(s) if s.contains("what is this?"))
+            ), "{prompt}");
+        }
+
+        // without an image
+        let Pdl { messages, .. } = parse_pdl(PROMPTS.get("raw").unwrap(), &raw_request_context("hi", None, None, &[]), "/", true, true).unwrap();
+        assert!(messages.iter().all(|message| message.content.iter().all(|content| matches!(content, MessageContent::String(_)))));
+    }
+
+    #[test]
+    fn schema_prompt_test() {
+        let chunks = vec![RenderableChunk::fake(String::from("Ragit is written in Rust."), String::from("1st chunk of README.md"))];
+        let render = |prompt: &str, context: &tera::Context| -> Vec<String> {
+            let Pdl { messages, .. } = parse_pdl(PROMPTS.get(prompt).unwrap(), context, "/", true, true).unwrap();
+
+            messages.iter().map(
+                |message| message.content.iter().map(
+                    |content| match content {
+                        MessageContent::String(s) => s.to_string(),
+                        _ => panic!(),
+                    }
+                ).collect::<Vec<_>>().concat()
+            ).collect()
+        };
+
+        // without a schema, it asks for citations
+        let messages = render("answer_query", &answer_query_context("what is ragit?", None, None, &chunks));
+        assert!(messages.iter().any(|message| message.contains("cite")));
+        assert!(messages.iter().all(|message| !message.contains("json")));
+
+        // with a schema, it only asks for a json value, which `query` doesn't parse citations from
+        for (prompt, context) in [
+            ("answer_query", answer_query_context("what is ragit?", None, Some("[int]"), &chunks)),
+            ("raw", raw_request_context("what is ragit?", None, Some("[int]"), &[])),
+        ] {
+            let messages = render(prompt, &context);
+            assert!(messages.iter().all(|message| !message.contains("cite")), "{prompt}");
+            assert!(messages[0].contains("json value"), "{prompt}");
+            assert!(messages.last().unwrap().ends_with("Please don't write anything other than the json value."), "{prompt}");
+        }
+    }
+}
@@ -0,0 +1,456 @@
+// Boolean search (`rag search`)
+//
+// Unlike tfidf, it doesn't rank chunks. A chunk either matches the expression or
+// not, and the result is always the same for the same knowledge-base. It uses the
+// inverted index if it's built and enabled. Otherwise, it reads the tfidf files
+// of all the chunks.
+
+use super::{Phrase, PhraseKind};
+use crate::chunk::ChunkSource;
+use crate::error::Error;
+use crate::index::Index;
+use crate::index::tfidf::{Tokenizer, get_term_positions, tokenize};
+use crate::uid::Uid;
+use std::collections::{HashMap, HashSet};
+
+/// `connection AND (pool OR "thread pool") NOT timeout`
+///
+/// 1. `AND`, `OR` and `NOT` must be uppercase. Lowercase `and` is just a term.
+/// 2. `NOT` binds tighter than `AND`, and `AND` binds tighter than `OR`.
+/// 3. If there's no operator between 2 expressions, it's `AND`.
+/// 4. Terms are tokenized (and stemmed) the same way as tfidf. If a word or a quoted
+///    phrase has multiple terms, the terms must appear next to each other, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BooleanQuery {
+    Term(String),
+    Phrase(Vec<String>),
+    And(Vec<BooleanQuery>),
+    Or(Vec<BooleanQuery>),
+    Not(Box<BooleanQuery>),
+}
+
+#[derive(Clone, Debug)]
+pub struct BooleanSearchResult {
+    pub uid: Uid,
+    pub source: ChunkSource,
+}
+
+impl BooleanSearchResult {
+    /// It's `None` if the chunk is not from a file.
+    pub fn path(&self) -> Option<&str> {
+        match &self.source {
+            ChunkSource::File { path, .. } => Some(path),
+            ChunkSource::Chunks { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    OpenParenthesis,
+    CloseParenthesis,
+    Word(String),
+    Quoted(String),
+}
+
+impl BooleanQuery {
+    /// `tokenizer` is `Index::tokenizer` of the knowledge-base that you're searching.
+    pub fn parse(s: &str, tokenizer: &Tokenizer) -> Result<Self, Error> {
+        let tokens = lex(s)?;
+        let mut cursor = 0;
+        let result = parse_or(&tokens, &mut cursor, tokenizer)?;
+
+        match tokens.get(cursor) {
+            Some(token) => Err(Error::BooleanQueryError(format!("unexpected token `{}`", token.render()))),
+            None => Ok(result),
+        }
+    }
+
+    fn terms(&self, buffer: &mut HashSet<String>) {
+        match self {
+            BooleanQuery::Term(term) => { buffer.insert(term.to_string()); },
+            BooleanQuery::Phrase(terms) => {
+                for term in terms.iter() {
+                    buffer.insert(term.to_string());
+                }
+            },
+            BooleanQuery::And(queries) | BooleanQuery::Or(queries) => {
+                for query in queries.iter() {
+                    query.terms(buffer);
+                }
+            },
+            BooleanQuery::Not(query) => { query.terms(buffer); },
+        }
+    }
+
+    fn has_phrase(&self) -> bool {
+        match self {
+            BooleanQuery::Term(_) => false,
+            BooleanQuery::Phrase(_) => true,
+            BooleanQuery::And(queries) | BooleanQuery::Or(queries) => queries.iter().any(|query| query.has_phrase()),
+            BooleanQuery::Not(query) => query.has_phrase(),
+        }
+    }
+
+    fn has_not(&self) -> bool {
+        match self {
+            BooleanQuery::Term(_) | BooleanQuery::Phrase(_) => false,
+            BooleanQuery::And(queries) | BooleanQuery::Or(queries) => queries.iter().any(|query| query.has_not()),
+            BooleanQuery::Not(_) => true,
+        }
+    }
+}
+
+impl Token {
+    fn render(&self) -> String {
+        match self {
+            Token::And => String::from("AND"),
+            Token::Or => String::from("OR"),
+            Token::Not => String::from("NOT"),
+            Token::OpenParenthesis => String::from("("),
+            Token::CloseParenthesis => String::from(")"),
+            Token::Word(w) => w.to_string(),
+            Token::Quoted(q) => format!("{q:?}"),
+        }
+    }
+}
+
+fn lex(s: &str) -> Result<Vec<Token>, Error> {
+    let mut result = vec![];
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => { result.push(Token::OpenParenthesis); },
+            ')' => { result.push(Token::CloseParenthesis); },
+            '"' => {
+                let mut quoted = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('"') => { break; },
+                        Some(c) => { quoted.push(c); },
+                        None => {
+                            return Err(Error::BooleanQueryError(format!("unterminated quote: `\"{quoted}`")));
+                        },
+                    }
+                }
+
+                result.push(Token::Quoted(quoted));
+            },
+            c if c.is_whitespace() => {},
+            c => {
+                let mut word = c.to_string();
+
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || *c == '(' || *c == ')' || *c == '"' {
+                        break;
+                    }
+
+                    word.push(*c);
+                    chars.next();
+                }
+
+                result.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            },
+        }
+    }
+
+    Ok(result)
+}
+
+fn parse_or(tokens: &[Token], cursor: &mut usize, tokenizer: &Tokenizer) -> Result<BooleanQuery, Error> {
+    let mut queries = vec![parse_and(tokens, cursor, tokenizer)?];
+
+    while let Some(Token::Or) = tokens.get(*cursor) {
+        *cursor += 1;
+        queries.push(parse_and(tokens, cursor, tokenizer)?);
+    }
+
+    if queries.len() == 1 {
+        Ok(queries.pop().unwrap())
+    }
+
+    else {
+        Ok(BooleanQuery::Or(queries))
+    }
+}
+
+fn parse_and(tokens: &[Token], cursor: &mut usize, tokenizer: &Tokenizer) -> Result<BooleanQuery, Error> {
+    let mut queries = vec![parse_not(tokens, cursor, tokenizer)?];
+
+    loop {
+        match tokens.get(*cursor) {
+            Some(Token::And) => {
+                *cursor += 1;
+                queries.push(parse_not(tokens, cursor, tokenizer)?);
+            },
+            // implicit `AND`
+            Some(Token::Not | Token::OpenParenthesis | Token::Word(_) | Token::Quoted(_)) => {
+                queries.push(parse_not(tokens, cursor, tokenizer)?);
+            },
+            _ => { break; },
+        }
+    }
+
+    if queries.len() == 1 {
+        Ok(queries.pop().unwrap())
+    }
+
+    else {
+        Ok(BooleanQuery::And(queries))
+    }
+}
+
+fn parse_not(tokens: &[Token], cursor: &mut usize, tokenizer: &Tokenizer) -> Result<BooleanQuery, Error> {
+    match tokens.get(*cursor) {
+        Some(Token::Not) => {
+            *cursor += 1;
+            Ok(BooleanQuery::Not(Box::new(parse_not(tokens, cursor, tokenizer)?)))
+        },
+        _ => parse_primary(tokens, cursor, tokenizer),
+    }
+}
+
+fn parse_primary(tokens: &[Token], cursor: &mut usize, tokenizer: &Tokenizer) -> Result<BooleanQuery, Error> {
+    match tokens.get(*cursor) {
+        Some(Token::OpenParenthesis) => {
+            *cursor += 1;
+            let result = parse_or(tokens, cursor, tokenizer)?;
+
+            match tokens.get(*cursor) {
+                Some(Token::CloseParenthesis) => {
+                    *cursor += 1;
+                    Ok(result)
+                },
+                _ => Err(Error::BooleanQueryError(String::from("unmatched `(`"))),
+            }
+        },
+        Some(Token::Word(s) | Token::Quoted(s)) => {
+            *cursor += 1;
+            let mut terms = tokenize(s, tokenizer);
+
+            match terms.len() {
+                0 => Err(Error::BooleanQueryError(format!("`{s}` doesn't have any searchable term"))),
+                1 => Ok(BooleanQuery::Term(terms.pop().unwrap())),
+                _ => Ok(BooleanQuery::Phrase(terms)),
+            }
+        },
+        Some(token) => Err(Error::BooleanQueryError(format!("unexpected token `{}`", token.render()))),
+        None => Err(Error::BooleanQueryError(String::from("unexpected end of expression"))),
+    }
+}
+
+struct BooleanSearchState {
+    uids_by_term: HashMap<String, HashSet<Uid>>,
+
+    // It's `Some` only if the expression has `NOT`.
+    all_uids: Option<HashSet<Uid>>,
+
+    // It's `Some` only if the ii has positional postings and the expression has a phrase.
+    positions_by_term: Option<HashMap<String, HashMap<Uid, Vec<usize>>>>,
+
+    // If `positions_by_term` is `None`, the positions are calculated with this.
+    tokenizer: Tokenizer,
+}
+
+impl Index {
+    /// It returns the chunks that match `query`, sorted by uid. It doesn't call any LLM.
+    pub fn search_boolean(&self, query: &BooleanQuery) -> Result<Vec<BooleanSearchResult>, Error> {
+        let mut terms = HashSet::new();
+        query.terms(&mut terms);
+        let mut state = BooleanSearchState {
+            uids_by_term: terms.iter().map(|term| (term.to_string(), HashSet::new())).collect(),
+            all_uids: None,
+            positions_by_term: None,
+            tokenizer: self.tokenizer(),
+        };
+
+        if self.query_config.enable_ii && self.is_ii_built() {
+            let terms = terms.iter().map(|term| term.to_string()).collect::<Vec<_>>();
+
+            for (term, uids) in self.search_ii_by_terms(&terms)?.into_iter() {
+                state.uids_by_term.insert(term, uids.into_iter().collect());
+            }
+
+            if self.ii_positions && query.has_phrase() {
+                state.positions_by_term = Some(self.get_positions_by_terms(&terms)?);
+            }
+
+            if query.has_not() {
+                state.all_uids = Some(self.get_file_chunk_uids()?.into_iter().collect());
+            }
+        }
+
+        else {
+            let mut all_uids = HashSet::new();
+
+            for uid in self.get_file_chunk_uids()? {
+                let processed_doc = self.get_tfidf_by_chunk_uid(uid)?;
+
+                for (term, uids) in state.uids_by_term.iter_mut() {
+                    if processed_doc.term_frequency.contains_key(term) {
+                        uids.insert(uid);
+                    }
+                }
+
+                all_uids.insert(uid);
+            }
+
+            state.all_uids = Some(all_uids);
+        }
+
+        let mut uids = self.eval_boolean_query(query, &state)?.into_iter().collect::<Vec<_>>();
+        uids.sort();
+        let mut result = Vec::with_capacity(uids.len());
+
+        for uid in uids.into_iter() {
+            result.push(BooleanSearchResult {
+                uid,
+                source: self.get_chunk_by_uid(uid)?.source,
+            });
+        }
+
+        Ok(result)
+    }
+
+    // Summary chunks (`ChunkSource::Chunks`) are not searchable, so the result of `NOT`
+    // must not include them. It's the universe of `NOT`.
+    fn get_file_chunk_uids(&self) -> Result<Vec<Uid>, Error> {
+        let mut result = vec![];
+
+        for file_uid in self.processed_files.values() {
+            result.extend(self.get_chunks_of_file(*file_uid)?);
+        }
+
+        Ok(result)
+    }
+
+    fn eval_boolean_query(&self, query: &BooleanQuery, state: &BooleanSearchState) -> Result<HashSet<Uid>, Error> {
+        match query {
+            BooleanQuery::Term(term) => Ok(state.uids_by_term.get(term).unwrap().clone()),
+            BooleanQuery::Phrase(terms) => {
+                let mut candidates = state.uids_by_term.get(&terms[0]).unwrap().clone();
+
+                for term in terms[1..].iter() {
+                    let uids = state.uids_by_term.get(term).unwrap();
+                    candidates.retain(|uid| uids.contains(uid));
+                }
+
+                let phrase = Phrase {
+                    terms: terms.clone(),
+                    kind: PhraseKind::Exact,
+                };
+                let mut result = HashSet::with_capacity(candidates.len());
+
+                for uid in candidates.into_iter() {
+                    let positions = match &state.positions_by_term {
+                        Some(positions_by_term) => terms.iter().filter_map(
+                            |term| positions_by_term.get(term).unwrap().get(&uid).map(
+                                |positions| (term.to_string(), positions.clone())
+                            )
+                        ).collect(),
+                        None => get_term_positions(&self.get_chunk_by_uid(uid)?.into_tfidf_haystack(&self.root_dir)?, &state.tokenizer),
+                    };
+
+                    if phrase.get_proximity(&positions).is_some() {
+                        result.insert(uid);
+                    }
+                }
+
+                Ok(result)
+            },
+            BooleanQuery::And(queries) => {
+                let mut result = self.eval_boolean_query(&queries[0], state)?;
+
+                for query in queries[1..].iter() {
+                    let uids = self.eval_boolean_query(query, state)?;
+                    result.retain(|uid| uids.contains(uid));
+                }
+
+                Ok(result)
+            },
+            BooleanQuery::Or(queries) => {
+                let mut result = HashSet::new();
+
+                for query in queries.iter() {
+                    result.extend(self.eval_boolean_query(query, state)?);
+                }
+
+                Ok(result)
+            },
+            BooleanQuery::Not(query) => {
+                let uids = self.eval_boolean_query(query, state)?;
+                Ok(state.all_uids.as_ref().unwrap().iter().filter(
+                    |uid| !uids.contains(uid)
+                ).copied().collect())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::TokenizerConfig;
+
+    #[test]
+    fn boolean_query_test() {
+        let config = TokenizerConfig::default().tokenizer();
+        let term = |s: &str| BooleanQuery::Term(tokenize(s, &config).pop().unwrap());
+
+        assert_eq!(BooleanQuery::parse("apple", &config).unwrap(), term("apple"));
+        assert_eq!(
+            BooleanQuery::parse("apple banana OR cherry", &config).unwrap(),
+            BooleanQuery::Or(vec![
+                BooleanQuery::And(vec![term("apple"), term("banana")]),
+                term("cherry"),
+            ]),
+        );
+        assert_eq!(
+            BooleanQuery::parse("apple AND (banana OR cherry)", &config).unwrap(),
+            BooleanQuery::And(vec![
+                term("apple"),
+                BooleanQuery::Or(vec![term("banana"), term("cherry")]),
+            ]),
+        );
+        assert_eq!(
+            BooleanQuery::parse("NOT NOT apple", &config).unwrap(),
+            BooleanQuery::Not(Box::new(BooleanQuery::Not(Box::new(term("apple"))))),
+        );
+        assert_eq!(
+            BooleanQuery::parse("\"connection pool\" NOT timeout", &config).unwrap(),
+            BooleanQuery::And(vec![
+                BooleanQuery::Phrase(tokenize("connection pool", &config)),
+                BooleanQuery::Not(Box::new(term("timeout"))),
+            ]),
+        );
+
+        // lowercase operators are terms
+        assert_eq!(
+            BooleanQuery::parse("apple or banana", &config).unwrap(),
+            BooleanQuery::And(vec![term("apple"), term("or"), term("banana")]),
+        );
+
+        for invalid in [
+            "",
+            "apple AND",
+            "OR apple",
+            "(apple",
+            "apple)",
+            "\"apple",
+            "NOT",
+            "()",
+        ] {
+            assert!(BooleanQuery::parse(invalid, &config).is_err(), "{invalid:?}");
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use crate::uid::Uid;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+lazy_static! {
+    static ref CITATION_RE: Regex = Regex::new(r"\[(\d{1,4})\]").unwrap();
+}
+
+/// `prompts/answer_query.pdl` numbers the documents and asks the model to cite
+/// them like `[1]`. A citation links a part of the response to a chunk that the
+/// model used.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Citation {
+    /// The number that the model wrote. If it cites a merged chunk, there
+    /// are multiple citations with the same marker.
+    pub marker: usize,
+
+    /// Byte range of `QueryResponse::response`. It's the sentence (or the
+    /// part of the sentence) that comes before the marker.
+    pub span: (usize, usize),
+    pub uid: Uid,
+}
+
+/// It parses `[n]` markers in `response`. `chunk_uids[n - 1]` is the uids of the `n`th chunk that
+/// `answer_query_with_chunks` has given to the model (a merged chunk has multiple uids). Markers that don't
+/// point to any chunk, or that point to chunks that are not retrieved, are ignored.
+/// Markers in code spans and code fences (e.g. `` `arr[1]` ``) are not markers at all.
+pub(crate) fn parse_citations(
+    response: &str,
+    chunk_uids: &[Vec<Uid>],
+    retrieved_uids: &HashSet<Uid>,
+) -> Vec<Citation> {
+    let mut result = vec![];
+    let mut prev_marker_end = 0;
+    let mut prev_span = None;
+    let code_ranges = get_code_ranges(response);
+
+    for cap in CITATION_RE.captures_iter(response) {
+        let marker_range = cap.get(0).unwrap().range();
+
+        // `\d{1,4}` always fits in usize
+        let marker = cap[1].parse::<usize>().unwrap();
+
+        if marker == 0 || marker > chunk_uids.len() || code_ranges.iter().any(
+            |(start, end)| *start <= marker_range.start && marker_range.start < *end
+        ) {
+            continue;
+        }
+
+        let between = &response[prev_marker_end..marker_range.start];
+
+        // `[1][2]` and `[1], [2]` share the same span
+        let span = match prev_span {
+            Some(span) if between.trim().trim_matches(',').trim().is_empty() => span,
+            _ => {
+                let end = prev_marker_end + between.trim_end().len();
+                let start = find_sentence_start(response, prev_marker_end, end);
+                (start, end)
+            },
+        };
+        prev_marker_end = marker_range.end;
+        prev_span = Some(span);
+
+        // an empty span is useless
+        if span.0 == span.1 {
+            continue;
+        }
+
+        for uid in chunk_uids[marker - 1].iter() {
+            let citation = Citation { marker, span, uid: *uid };
+
+            if retrieved_uids.contains(uid) && !result.contains(&citation) {
+                result.push(citation);
+            }
+        }
+    }
+
+    result
+}
+
+// Byte ranges of code fences and code spans in a markdown text. An unclosed
+// fence continues to the end of the text, and an unclosed backtick is just a backtick.
+fn get_code_ranges(s: &str) -> Vec<(usize, usize)> {
+    let mut result = vec![];
+    let mut fence: Option<(usize, char, usize)> = None;  // (start, char, len)
+    let mut line_start = 0;
+
+    for line in s.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let fence_char = trimmed.chars().next().unwrap_or(' ');
+        let fence_len = trimmed.chars().take_while(|c| *c == fence_char).count();
+        let is_fence = matches!(fence_char, '`' | '~') && fence_len >= 3;
+
+        match fence {
+            Some((start, open_char, open_len)) => {
+                // a closing fence doesn't have an info string
+                if is_fence && fence_char == open_char && fence_len >= open_len && trimmed[fence_len..].trim().is_empty() {
+                    result.push((start, line_start + line.len()));
+                    fence = None;
+                }
+            },
+            None if is_fence => {
+                fence = Some((line_start, fence_char, fence_len));
+            },
+            None => {
+                get_code_span_ranges(line, line_start, &mut result);
+            },
+        }
+
+        line_start += line.len();
+    }
+
+    if let Some((start, _, _)) = fence {
+        result.push((start, s.len()));
+    }
+
+    result
+}
+
+// A code span starts with a run of backticks and ends with a run of the same length.
+fn get_code_span_ranges(line: &str, offset: usize, result: &mut Vec<(usize, usize)>) {
+    let bytes = line.as_bytes();
+    let mut cursor = 0;
+
+    while let Some(start) = line[cursor..].find('`').map(|i| cursor + i) {
+        let run = bytes[start..].iter().take_while(|b| **b == b'`').count();
+        let mut end = None;
+        let mut i = start + run;
+
+        while let Some(next) = line[i..].find('`').map(|j| i + j) {
+            let next_run = bytes[next..].iter().take_while(|b| **b == b'`').count();
+
+            if next_run == run {
+                end = Some(next + next_run);
+                break;
+            }
+
+            i = next + next_run;
+        }
+
+        match end {
+            Some(end) => {
+                result.push((offset + start, offset + end));
+                cursor = end;
+            },
+            None => {
+                cursor = start + run;
+            },
+        }
+    }
+}
+
+// It doesn't look further than `lower_bound`, which is the end of the previous marker.
+// It ignores the last character because a sentence can end with a period before the marker.
+fn find_sentence_start(s: &str, lower_bound: usize, end: usize) -> usize {
+    let mut start = lower_bound;
+    let mut chars = s[lower_bound..end].char_indices().collect::<Vec<_>>();
+    chars.pop();
+
+    for (i, c) in chars.into_iter().rev() {
+        if matches!(c, '.' | '!' | '?' | '\n' | '。') {
+            start = lower_bound + i + c.len_utf8();
+            break;
+        }
+    }
+
+    start + (s[start..end].len() - s[start..end].trim_start().len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn citation_test() {
+        let uids = (0..4).map(|i| Uid::new_image(&[i])).collect::<Vec<_>>();
+        let chunk_uids = vec![
+            vec![uids[0]],
+            vec![uids[1], uids[2]],
+            vec![uids[3]],
+        ];
+        let retrieved_uids = uids[..3].iter().copied().collect::<HashSet<_>>();
+        let response = "Ragit is a RAG framework [1]. It's written in Rust.[2][9]\nIt has no server [1], [3] and that's it.";
+        let citations = parse_citations(response, &chunk_uids, &retrieved_uids);
+        let spans = citations.iter().map(
+            |Citation { marker, span, uid }| (*marker, &response[span.0..span.1], uids.iter().position(|u| u == uid).unwrap())
+        ).collect::<Vec<_>>();
+
+        assert_eq!(
+            spans,
+            vec![
+                (1, "Ragit is a RAG framework", 0),
+                (2, "It's written in Rust.", 1),
+                (2, "It's written in Rust.", 2),
+                // `[3]` is not retrieved
+                (1, "It has no server", 0),
+            ],
+        );
+
+        assert!(parse_citations("no citations [0] [x]", &chunk_uids, &retrieved_uids).is_empty());
+        assert!(parse_citations("[1] at the beginning", &chunk_uids, &retrieved_uids).is_empty());
+    }
+
+    #[test]
+    fn code_citation_test() {
+        let uids = (0..2).map(|i| Uid::new_image(&[i])).collect::<Vec<_>>();
+        let chunk_uids = uids.iter().map(|uid| vec![*uid]).collect::<Vec<_>>();
+        let retrieved_uids = uids.iter().copied().collect::<HashSet<_>>();
+        let response = "Use `arr[1]` or ``v[0] + `w[1]` `` to index it [2].\n\n```rust\nlet x = v[1];\n```\n\nThat's all[1].\n~~~\nv[2]\n";
+        let citations = parse_citations(response, &chunk_uids, &retrieved_uids);
+        let spans = citations.iter().map(
+            |Citation { marker, span, .. }| (*marker, &response[span.0..span.1])
+        ).collect::<Vec<_>>();
+
+        assert_eq!(
+            spans,
+            vec![
+                (2, "Use `arr[1]` or ``v[0] + `w[1]` `` to index it"),
+                (1, "That's all"),
+            ],
+        );
+
+        // `[3]` is out of range, so `[1]` doesn't share a span with it
+        let response = "It's a list [3]. It's a citation [1].";
+        let citations = parse_citations(response, &chunk_uids, &retrieved_uids);
+        assert_eq!(citations.len(), 1);
+        assert_eq!(&response[citations[0].span.0..citations[0].span.1], "It's a citation");
+
+        // an unclosed backtick is not a code span
+        assert_eq!(parse_citations("It costs `5 [1].", &chunk_uids, &retrieved_uids).len(), 1);
+    }
+}
@@ -1,4 +1,8 @@
+use crate::error::Error;
+use crate::index::tfidf::TfidfField;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 
 // This struct is used for loading partial configurations from ~/.config/ragit/query.json
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -7,6 +11,33 @@ pub struct PartialQueryConfig {
     pub max_summaries: Option<usize>,
     pub max_retrieval: Option<usize>,
     pub enable_ii: Option<bool>,
+    pub embedding_mode: Option<EmbeddingMode>,
+    pub embedding_model: Option<String>,
+    pub embedding_url: Option<String>,
+    pub embedding_api_env_var: Option<String>,
+    pub rrf_k: Option<usize>,
+    pub tfidf_weight: Option<usize>,
+    pub ii_weight: Option<usize>,
+    pub vector_weight: Option<usize>,
+    pub bm25_k1: Option<OrderedF32>,
+    pub bm25_b: Option<OrderedF32>,
+    pub ii_coeff: Option<usize>,
+    pub ii_positions: Option<bool>,
+    pub proximity_boost: Option<OrderedF32>,
+    pub context_chunks: Option<usize>,
+    pub context_budget: Option<usize>,
+    pub mmr_lambda: Option<OrderedF32>,
+    pub mmr_pool_coeff: Option<usize>,
+    pub max_chunks_per_file: Option<usize>,
+    pub fuzzy_search: Option<bool>,
+    pub fuzzy_max_distance: Option<usize>,
+    pub fuzzy_weight: Option<OrderedF32>,
+    pub fuzzy_max_expansions: Option<usize>,
+    pub title_field_weight: Option<OrderedF32>,
+    pub path_field_weight: Option<OrderedF32>,
+    pub summary_field_weight: Option<OrderedF32>,
+    pub data_field_weight: Option<OrderedF32>,
+    pub image_field_weight: Option<OrderedF32>,
 }
 
 impl PartialQueryConfig {
@@ -24,6 +55,87 @@ impl PartialQueryConfig {
         if let Some(enable_ii) = self.enable_ii {
             config.enable_ii = enable_ii;
         }
+        if let Some(embedding_mode) = self.embedding_mode {
+            config.embedding_mode = embedding_mode;
+        }
+        if let Some(embedding_model) = &self.embedding_model {
+            config.embedding_model = embedding_model.clone();
+        }
+        if let Some(embedding_url) = &self.embedding_url {
+            config.embedding_url = embedding_url.clone();
+        }
+        if let Some(embedding_api_env_var) = &self.embedding_api_env_var {
+            config.embedding_api_env_var = embedding_api_env_var.clone();
+        }
+        if let Some(rrf_k) = self.rrf_k {
+            config.rrf_k = rrf_k;
+        }
+        if let Some(tfidf_weight) = self.tfidf_weight {
+            config.tfidf_weight = tfidf_weight;
+        }
+        if let Some(ii_weight) = self.ii_weight {
+            config.ii_weight = ii_weight;
+        }
+        if let Some(vector_weight) = self.vector_weight {
+            config.vector_weight = vector_weight;
+        }
+        if let Some(bm25_k1) = self.bm25_k1 {
+            config.bm25_k1 = bm25_k1;
+        }
+        if let Some(bm25_b) = self.bm25_b {
+            config.bm25_b = bm25_b;
+        }
+        if let Some(ii_coeff) = self.ii_coeff {
+            config.ii_coeff = ii_coeff;
+        }
+        if let Some(ii_positions) = self.ii_positions {
+            config.ii_positions = ii_positions;
+        }
+        if let Some(proximity_boost) = self.proximity_boost {
+            config.proximity_boost = proximity_boost;
+        }
+        if let Some(context_chunks) = self.context_chunks {
+            config.context_chunks = context_chunks;
+        }
+        if let Some(context_budget) = self.context_budget {
+            config.context_budget = context_budget;
+        }
+        if let Some(mmr_lambda) = self.mmr_lambda {
+            config.mmr_lambda = mmr_lambda;
+        }
+        if let Some(mmr_pool_coeff) = self.mmr_pool_coeff {
+            config.mmr_pool_coeff = mmr_pool_coeff;
+        }
+        if let Some(max_chunks_per_file) = self.max_chunks_per_file {
+            config.max_chunks_per_file = max_chunks_per_file;
+        }
+        if let Some(fuzzy_search) = self.fuzzy_search {
+            config.fuzzy_search = fuzzy_search;
+        }
+        if let Some(fuzzy_max_distance) = self.fuzzy_max_distance {
+            config.fuzzy_max_distance = fuzzy_max_distance;
+        }
+        if let Some(fuzzy_weight) = self.fuzzy_weight {
+            config.fuzzy_weight = fuzzy_weight;
+        }
+        if let Some(fuzzy_max_expansions) = self.fuzzy_max_expansions {
+            config.fuzzy_max_expansions = fuzzy_max_expansions;
+        }
+        if let Some(title_field_weight) = self.title_field_weight {
+            config.title_field_weight = title_field_weight;
+        }
+        if let Some(path_field_weight) = self.path_field_weight {
+            config.path_field_weight = path_field_weight;
+        }
+        if let Some(summary_field_weight) = self.summary_field_weight {
+            config.summary_field_weight = summary_field_weight;
+        }
+        if let Some(data_field_weight) = self.data_field_weight {
+            config.data_field_weight = data_field_weight;
+        }
+        if let Some(image_field_weight) = self.image_field_weight {
+            config.image_field_weight = image_field_weight;
+        }
     }
 }
 
@@ -42,6 +154,282 @@ pub struct QueryConfig {
     /// It doesn't automatically build an inverted index when it's missing. You
     /// have to run `rag ii build` manually to build the index.
     pub enable_ii: bool,
+
+    /// If it's not `none`, it uses embedding vectors to select chunks.
+    /// `only` replaces tf-idf with vector search and `both` merges the
+    /// two with rank fusion. Run `rag embedding-build` to create the vectors.
+    #[serde(default)]
+    pub embedding_mode: EmbeddingMode,
+
+    /// It has to be a model name that the embedding api understands.
+    /// If you change this, you have to run `rag embedding-build` again.
+    #[serde(default = "default_embedding_model")]
+    pub embedding_model: String,
+
+    /// Any OpenAI-compatible `/embeddings` endpoint works.
+    #[serde(default = "default_embedding_url")]
+    pub embedding_url: String,
+
+    /// Name of the env var that has the api key. If it's empty or the env
+    /// var is not set, it sends requests without an api key.
+    #[serde(default = "default_embedding_api_env_var")]
+    pub embedding_api_env_var: String,
+
+    /// Candidates from tf-idf, the inverted index and embedding vectors are merged
+    /// by reciprocal rank fusion. A chunk at rank `r` (starting from 1) of a source
+    /// gets `weight / (rrf_k + r)` points from the source.
+    #[serde(default = "default_rrf_k")]
+    pub rrf_k: usize,
+
+    /// Weight of tf-idf scores in rank fusion. If it's 0, tf-idf is not used.
+    #[serde(default = "default_tfidf_weight")]
+    pub tfidf_weight: usize,
+
+    /// Weight of the inverted index's own ranking in rank fusion. If it's 0,
+    /// the inverted index is still used to speed up tf-idf (see `enable_ii`),
+    /// but its ranking doesn't affect the result. It's ignored if the ii is not built.
+    #[serde(default)]
+    pub ii_weight: usize,
+
+    /// Weight of embedding vectors in rank fusion. It's ignored if `embedding_mode` is `none`.
+    #[serde(default = "default_vector_weight")]
+    pub vector_weight: usize,
+
+    /// `k1` of BM25. The bigger it is, the more a term's repetition counts.
+    #[serde(default = "default_bm25_k1")]
+    pub bm25_k1: OrderedF32,
+
+    /// `b` of BM25, from 0.0 to 1.0. It decides how much long chunks are penalized.
+    #[serde(default = "default_bm25_b")]
+    pub bm25_b: OrderedF32,
+
+    /// When the inverted index is used, tf-idf scores only `limit * ii_coeff`
+    /// candidates from the inverted index instead of all the chunks.
+    #[serde(default = "default_ii_coeff")]
+    pub ii_coeff: usize,
+
+    /// If it's enabled, `rag ii-build` also records where each term appears
+    /// in each chunk. Phrase search (`"connection pool"`) and proximity search
+    /// (`"connection pool timeout"~5`) need the positions. If you change this,
+    /// `rag ii-build` builds the inverted index from scratch.
+    #[serde(default)]
+    pub ii_positions: bool,
+
+    /// If the positions are available, a chunk's tf-idf score is multiplied by
+    /// up to `1 + proximity_boost` when the terms of a keyword appear close to
+    /// each other. If it's 0, the order of the terms doesn't matter.
+    #[serde(default = "default_proximity_boost")]
+    pub proximity_boost: OrderedF32,
+
+    /// Before answering a query, each retrieved chunk is widened with up to this
+    /// amount of chunks before and after it in the same file. If it's 0, the
+    /// model only sees the retrieved chunks.
+    #[serde(default)]
+    pub context_chunks: usize,
+
+    /// The widening of a retrieved chunk stops when its neighbours exceed this
+    /// amount of characters.
+    #[serde(default = "default_context_budget")]
+    pub context_budget: usize,
+
+    /// From 0.0 to 1.0. If it's less than 1.0, hybrid search results are diversified
+    /// with maximal marginal relevance: a chunk that is similar to the already selected
+    /// chunks is pushed back. The lower it is, the more diverse the result is.
+    #[serde(default = "default_mmr_lambda")]
+    pub mmr_lambda: OrderedF32,
+
+    /// When the results are diversified, it selects `max_summaries` chunks out of
+    /// `max_summaries * mmr_pool_coeff` hybrid search results.
+    /// If a file dominates the search results, a bigger value lets other files in.
+    #[serde(default = "default_mmr_pool_coeff")]
+    pub mmr_pool_coeff: usize,
+
+    /// If it's not 0, hybrid search results have at most this amount of chunks
+    /// from a file. It's applied with `mmr_lambda`. If the other files don't have
+    /// enough chunks, the cap is lifted, so that there're still `max_summaries` chunks.
+    #[serde(default)]
+    pub max_chunks_per_file: usize,
+
+    /// If it's enabled, tf-idf also searches terms that are similar to the query
+    /// terms: the terms that start with a query term, and the terms that are
+    /// within a small edit distance of a query term that is not in the knowledge-base.
+    #[serde(default)]
+    pub fuzzy_search: bool,
+
+    /// Terms with 4~7 characters allow 1 edit and longer terms allow 2 edits,
+    /// but not more than this. If it's 0, only the prefixes are searched.
+    #[serde(default = "default_fuzzy_max_distance")]
+    pub fuzzy_max_distance: usize,
+
+    /// An expanded term's weight is multiplied by this.
+    #[serde(default = "default_fuzzy_weight")]
+    pub fuzzy_weight: OrderedF32,
+
+    /// A query term is expanded to at most this amount of terms.
+    #[serde(default = "default_fuzzy_max_expansions")]
+    pub fuzzy_max_expansions: usize,
+
+    /// When tf-idf counts a term in a chunk, an occurrence in the title counts as
+    /// `title_field_weight` occurrences. The other fields work the same way. The
+    /// weights are applied at query time, so you don't have to rebuild anything.
+    #[serde(default = "default_title_field_weight")]
+    pub title_field_weight: OrderedF32,
+
+    /// The path of the file and the position of the chunk in the file, like "3rd chunk of docs/intro.md".
+    #[serde(default = "default_field_weight")]
+    pub path_field_weight: OrderedF32,
+
+    /// The summary that the model has written.
+    #[serde(default = "default_field_weight")]
+    pub summary_field_weight: OrderedF32,
+
+    /// The contents of the chunk.
+    #[serde(default = "default_field_weight")]
+    pub data_field_weight: OrderedF32,
+
+    /// Descriptions of the images in the chunk.
+    #[serde(default = "default_field_weight")]
+    pub image_field_weight: OrderedF32,
+}
+
+impl QueryConfig {
+    pub fn field_weights(&self) -> HashMap<TfidfField, f32> {
+        [
+            (TfidfField::Path, self.path_field_weight.0),
+            (TfidfField::Title, self.title_field_weight.0),
+            (TfidfField::Summary, self.summary_field_weight.0),
+            (TfidfField::Data, self.data_field_weight.0),
+            (TfidfField::Image, self.image_field_weight.0),
+        ].into_iter().collect()
+    }
+
+    /// `rag config --set` calls this function. A field weight has to be a finite number
+    /// that is not negative, otherwise tf-idf scores are not comparable.
+    pub fn check_field_weights(&self) -> Result<(), Error> {
+        for (key, weight) in [
+            ("title_field_weight", self.title_field_weight),
+            ("path_field_weight", self.path_field_weight),
+            ("summary_field_weight", self.summary_field_weight),
+            ("data_field_weight", self.data_field_weight),
+            ("image_field_weight", self.image_field_weight),
+        ] {
+            if !weight.0.is_finite() || weight.0 < 0.0 {
+                return Err(Error::InvalidConfigValue {
+                    key: key.to_string(),
+                    message: format!("{} is not a valid field weight. It has to be a finite number that is not negative.", weight.0),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An `f32` that can be a field of a struct with `Eq` and `Hash`. It's compared
+/// and hashed by its bits, so `NaN == NaN` and `0.0 != -0.0`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct OrderedF32(pub f32);
+
+impl PartialEq for OrderedF32 {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for OrderedF32 {}
+
+impl Hash for OrderedF32 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl From<f32> for OrderedF32 {
+    fn from(f: f32) -> Self {
+        OrderedF32(f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingMode {
+    #[default]
+    None,
+    Only,
+    Both,
+}
+
+fn default_embedding_model() -> String {
+    String::from("text-embedding-3-small")
+}
+
+fn default_embedding_url() -> String {
+    String::from("https://api.openai.com/v1/embeddings")
+}
+
+fn default_embedding_api_env_var() -> String {
+    String::from("OPENAI_API_KEY")
+}
+
+fn default_rrf_k() -> usize {
+    60
+}
+
+fn default_tfidf_weight() -> usize {
+    1
+}
+
+fn default_vector_weight() -> usize {
+    1
+}
+
+fn default_bm25_k1() -> OrderedF32 {
+    OrderedF32(1.2)
+}
+
+fn default_bm25_b() -> OrderedF32 {
+    OrderedF32(0.75)
+}
+
+fn default_ii_coeff() -> usize {
+    20
+}
+
+fn default_proximity_boost() -> OrderedF32 {
+    OrderedF32(1.0)
+}
+
+fn default_context_budget() -> usize {
+    4_000
+}
+
+fn default_mmr_lambda() -> OrderedF32 {
+    OrderedF32(1.0)
+}
+
+fn default_mmr_pool_coeff() -> usize {
+    3
+}
+
+fn default_fuzzy_max_distance() -> usize {
+    2
+}
+
+fn default_fuzzy_weight() -> OrderedF32 {
+    OrderedF32(0.5)
+}
+
+fn default_fuzzy_max_expansions() -> usize {
+    5
+}
+
+fn default_title_field_weight() -> OrderedF32 {
+    OrderedF32(2.0)
+}
+
+fn default_field_weight() -> OrderedF32 {
+    OrderedF32(1.0)
 }
 
 impl Default for QueryConfig {
@@ -51,6 +439,33 @@ impl Default for QueryConfig {
             max_summaries: 10,
             max_retrieval: 3,
             enable_ii: true,
+            embedding_mode: EmbeddingMode::None,
+            embedding_model: default_embedding_model(),
+            embedding_url: default_embedding_url(),
+            embedding_api_env_var: default_embedding_api_env_var(),
+            rrf_k: default_rrf_k(),
+            tfidf_weight: default_tfidf_weight(),
+            ii_weight: 0,
+            vector_weight: default_vector_weight(),
+            bm25_k1: default_bm25_k1(),
+            bm25_b: default_bm25_b(),
+            ii_coeff: default_ii_coeff(),
+            ii_positions: false,
+            proximity_boost: default_proximity_boost(),
+            context_chunks: 0,
+            context_budget: default_context_budget(),
+            mmr_lambda: default_mmr_lambda(),
+            mmr_pool_coeff: default_mmr_pool_coeff(),
+            max_chunks_per_file: 0,
+            fuzzy_search: false,
+            fuzzy_max_distance: default_fuzzy_max_distance(),
+            fuzzy_weight: default_fuzzy_weight(),
+            fuzzy_max_expansions: default_fuzzy_max_expansions(),
+            title_field_weight: default_title_field_weight(),
+            path_field_weight: default_field_weight(),
+            summary_field_weight: default_field_weight(),
+            data_field_weight: default_field_weight(),
+            image_field_weight: default_field_weight(),
         }
     }
 }
@@ -0,0 +1,205 @@
+use crate::chunk::{Chunk, ChunkSource};
+use crate::error::Error;
+use crate::index::{CorpusStats, Index, ProcessedDoc};
+use std::collections::HashMap;
+
+type TermVector = HashMap<String, f32>;
+
+impl Index {
+    /// It selects `limit` chunks out of hybrid search results, with maximal marginal
+    /// relevance. A candidate's relevance is its score divided by the best score, and
+    /// its redundancy is the highest cosine similarity (of tf-idf term vectors) between
+    /// it and the chunks that are already selected. It greedily picks the candidate
+    /// with the highest `mmr_lambda * relevance - (1 - mmr_lambda) * redundancy`.
+    ///
+    /// If `max_chunks_per_file` is not 0, it selects at most that amount of chunks from a file.
+    /// If there aren't enough candidates from the other files, the remaining slots are
+    /// filled with the rest of the candidates, so it returns `limit` chunks if it can.
+    pub(crate) fn select_diverse_chunks(
+        &self,
+        candidates: Vec<(Chunk, f32)>,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>, Error> {
+        let mut docs = Vec::with_capacity(candidates.len());
+
+        for (chunk, _) in candidates.iter() {
+            docs.push(self.get_tfidf_by_chunk_uid(chunk.uid)?);
+        }
+
+        let corpus_stats = self.get_corpus_stats()?;
+        let selected = select_by_mmr(
+            &candidates.iter().map(|(_, score)| *score).collect::<Vec<_>>(),
+            &candidates.iter().map(
+                |(chunk, _)| match &chunk.source {
+                    ChunkSource::File { path, .. } => Some(path.as_str()),
+                    ChunkSource::Chunks { .. } => None,
+                }
+            ).collect::<Vec<_>>(),
+            &term_vectors(&docs, corpus_stats.as_deref()),
+            self.query_config.mmr_lambda.0,
+            self.query_config.max_chunks_per_file,
+            limit,
+        );
+        let mut candidates = candidates.into_iter().map(Some).collect::<Vec<_>>();
+
+        Ok(selected.into_iter().map(|index| candidates[index].take().unwrap()).collect())
+    }
+}
+
+// Term frequencies weighted by idf. If `stats` is `None`, document frequencies
+// are counted in `docs`.
+fn term_vectors(docs: &[ProcessedDoc], stats: Option<&CorpusStats>) -> Vec<TermVector> {
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+
+    if stats.is_none() {
+        for doc in docs.iter() {
+            for term in doc.term_frequency.keys() {
+                *document_frequency.entry(term.as_str()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    // the same idf as `TfidfState::get_top`
+    let idf = |term: &str| {
+        let (doc_count, df) = match stats {
+            Some(stats) => (stats.doc_count, stats.document_frequency.get(term).copied().unwrap_or(0)),
+            None => (docs.len(), document_frequency.get(term).copied().unwrap_or(0)),
+        };
+
+        ((doc_count + 1) as f32 / (df + 1) as f32).log2().max(0.1)
+    };
+
+    docs.iter().map(
+        |doc| doc.term_frequency.iter().map(
+            |(term, count)| (term.to_string(), *count as f32 * idf(term))
+        ).collect()
+    ).collect()
+}
+
+// It returns the indexes of the selected candidates, in the order of selection.
+fn select_by_mmr(
+    scores: &[f32],
+    files: &[Option<&str>],
+    vectors: &[TermVector],
+    lambda: f32,
+    max_chunks_per_file: usize,
+    limit: usize,
+) -> Vec<usize> {
+    let max_score = scores.iter().copied().fold(0.0, f32::max);
+    let relevances = scores.iter().map(
+        |score| if max_score > 0.0 { *score / max_score } else { 0.0 }
+    ).collect::<Vec<_>>();
+    let norms = vectors.iter().map(norm).collect::<Vec<_>>();
+
+    // redundancies[i]: the highest similarity between candidate `i` and the selected ones
+    let mut redundancies = vec![0.0; scores.len()];
+    let mut is_selected = vec![false; scores.len()];
+    let mut chunks_per_file: HashMap<&str, usize> = HashMap::new();
+    let mut result = vec![];
+
+    // If every file has reached `max_chunks_per_file`, the cap is lifted for the remaining slots.
+    let mut is_capped = max_chunks_per_file > 0;
+
+    while result.len() < limit {
+        let mut best: Option<(usize, f32)> = None;
+
+        for i in 0..scores.len() {
+            if is_selected[i] {
+                continue;
+            }
+
+            if let Some(file) = files[i] {
+                if is_capped && chunks_per_file.get(file).copied().unwrap_or(0) >= max_chunks_per_file {
+                    continue;
+                }
+            }
+
+            let mmr = lambda * relevances[i] - (1.0 - lambda) * redundancies[i];
+
+            // it prefers the earlier one, which has a higher score
+            match best {
+                Some((_, best_mmr)) if best_mmr >= mmr => {},
+                _ => { best = Some((i, mmr)); },
+            }
+        }
+
+        let Some((selected, _)) = best else {
+            if is_capped && result.len() < scores.len() {
+                is_capped = false;
+                continue;
+            }
+
+            break;
+        };
+        is_selected[selected] = true;
+        result.push(selected);
+
+        if let Some(file) = files[selected] {
+            *chunks_per_file.entry(file).or_insert(0) += 1;
+        }
+
+        for i in 0..scores.len() {
+            if !is_selected[i] {
+                let similarity = cosine_similarity(&vectors[i], &vectors[selected], norms[i], norms[selected]);
+                redundancies[i] = redundancies[i].max(similarity);
+            }
+        }
+    }
+
+    result
+}
+
+fn norm(vector: &TermVector) -> f32 {
+    vector.values().map(|weight| weight * weight).sum::<f32>().sqrt()
+}
+
+fn cosine_similarity(a: &TermVector, b: &TermVector, norm_a: f32, norm_b: f32) -> f32 {
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    let (short, long) = if a.len() < b.len() { (a, b) } else { (b, a) };
+    let dot = short.iter().map(
+        |(term, weight)| weight * long.get(term).copied().unwrap_or(0.0)
+    ).sum::<f32>();
+
+    dot / (norm_a * norm_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::TokenizerConfig;
+    use crate::uid::Uid;
+
+    #[test]
+    fn mmr_test() {
+        let docs = vec![
+            "rust compiler borrow checker",
+            "rust compiler borrow checker error",
+            "rust compiler borrow checker lifetime",
+            "python interpreter garbage collector",
+        ].into_iter().map(
+            |doc| ProcessedDoc::new(Uid::dummy(), doc, &TokenizerConfig::default().tokenizer())
+        ).collect::<Vec<_>>();
+        let vectors = term_vectors(&docs, None);
+        let scores = vec![1.0, 0.9, 0.8, 0.5];
+        let files = vec![Some("a.md"), Some("a.md"), Some("b.md"), Some("c.md")];
+
+        // relevance only
+        assert_eq!(select_by_mmr(&scores, &files, &vectors, 1.0, 0, 3), vec![0, 1, 2]);
+
+        // near-duplicates are pushed back
+        assert_eq!(select_by_mmr(&scores, &files, &vectors, 0.5, 0, 3), vec![0, 3, 1]);
+
+        // per-file cap
+        assert_eq!(select_by_mmr(&scores, &files, &vectors, 1.0, 1, 3), vec![0, 2, 3]);
+
+        // if the other files don't have enough candidates, the remaining slots are filled anyway
+        assert_eq!(select_by_mmr(&scores, &files, &vectors, 1.0, 1, 4), vec![0, 2, 3, 1]);
+        assert_eq!(select_by_mmr(&scores, &files, &vectors, 1.0, 1, 10), vec![0, 2, 3, 1]);
+        assert_eq!(select_by_mmr(&scores[..2], &files[..2], &vectors[..2], 1.0, 1, 3), vec![0, 1]);
+
+        assert!(select_by_mmr(&[], &[], &[], 0.5, 0, 3).is_empty());
+    }
+}
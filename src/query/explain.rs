@@ -0,0 +1,192 @@
+use super::Keywords;
+use crate::chunk::Chunk;
+use crate::error::Error;
+use crate::index::TfidfResult;
+use crate::schema::prettify_uid;
+use crate::uid::Uid;
+use serde::Serialize;
+use serde_json::Value;
+
+/// A report of `Index::retrieve_chunks_with_explanation`. It has the candidates
+/// of each stage of the retrieval pipeline, so that you can tell which stage
+/// dropped a chunk.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RetrievalExplanation {
+    pub query: String,
+
+    /// `None` if the first stage doesn't use keywords.
+    pub keywords: Option<Keywords>,
+
+    /// The number of chunks that pass the `RetrievalFilter`. `None` if there's no filter.
+    pub filtered_chunks: Option<usize>,
+    pub stages: Vec<RetrievalStage>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RetrievalStage {
+    /// `"all chunks"`, `"filter"`, `"hybrid search"`, `"diversity"`, `"titles_to_summaries"` or `"summaries_to_chunks"`
+    pub name: String,
+
+    /// 1-based indexes of the previous stage's candidates that the LLM chose.
+    /// It's `None` if the stage doesn't use an LLM.
+    pub picked: Option<Vec<usize>>,
+    pub candidates: Vec<RetrievalCandidate>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RetrievalCandidate {
+    pub uid: Uid,
+    pub source: String,
+    pub title: String,
+
+    /// The score of the hybrid search (reciprocal rank fusion).
+    pub score: Option<f32>,
+
+    /// `None` if tf-idf is not used, or the chunk is not in the top results of tf-idf.
+    pub tfidf_score: Option<f32>,
+
+    /// How much each term contributed to `tfidf_score`.
+    pub term_scores: Vec<(String, f32)>,
+}
+
+impl RetrievalCandidate {
+    pub(crate) fn new(chunk: &Chunk, score: Option<f32>, tfidf: Option<&TfidfResult<Uid>>) -> Self {
+        RetrievalCandidate {
+            uid: chunk.uid,
+            source: chunk.render_source(),
+            title: chunk.title.clone(),
+            score,
+            tfidf_score: tfidf.map(|tfidf| tfidf.score),
+            term_scores: tfidf.map(|tfidf| tfidf.term_scores.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+impl RetrievalStage {
+    pub(crate) fn new(name: &str, chunks: &[Chunk]) -> Self {
+        RetrievalStage {
+            name: name.to_string(),
+            picked: None,
+            candidates: chunks.iter().map(|chunk| RetrievalCandidate::new(chunk, None, None)).collect(),
+        }
+    }
+
+    /// An LLM reranking stage. `picked` is calculated from the candidates of the
+    /// previous stage, because the rerankers drop chunks without reordering them.
+    pub(crate) fn reranked(name: &str, prev: &RetrievalStage, chunks: &[Chunk]) -> Self {
+        let mut result = RetrievalStage::new(name, chunks);
+        result.picked = Some(chunks.iter().filter_map(
+            |chunk| prev.candidates.iter().position(|candidate| candidate.uid == chunk.uid).map(|index| index + 1)
+        ).collect());
+        result
+    }
+}
+
+impl RetrievalExplanation {
+    pub fn to_json(&self) -> Result<Value, Error> {
+        let mut result = serde_json::to_value(self)?;
+
+        if let Some(Value::Array(stages)) = result.get_mut("stages") {
+            for stage in stages.iter_mut() {
+                if let Some(Value::Array(candidates)) = stage.get_mut("candidates") {
+                    for candidate in candidates.iter_mut() {
+                        if let Some(uid) = candidate.get_mut("uid") {
+                            *uid = prettify_uid(uid);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn render_table(&self) -> String {
+        let mut lines = vec![format!("query: {}", self.query)];
+
+        if let Some(keywords) = &self.keywords {
+            lines.push(format!("keywords: {}", keywords.keywords.join(", ")));
+
+            if !keywords.extra.is_empty() {
+                lines.push(format!("extra keywords: {}", keywords.extra.join(", ")));
+            }
+        }
+
+        if let Some(filtered_chunks) = self.filtered_chunks {
+            lines.push(format!("chunks that pass the filter: {filtered_chunks}"));
+        }
+
+        for (index, stage) in self.stages.iter().enumerate() {
+            lines.push(String::new());
+            lines.push(format!("stage {}: {} ({} candidates)", index + 1, stage.name, stage.candidates.len()));
+
+            match &stage.picked {
+                Some(picked) if picked.is_empty() => {
+                    lines.push(String::from("LLM picked: none"));
+                },
+                Some(picked) => {
+                    lines.push(format!("LLM picked: {}", picked.iter().map(|index| index.to_string()).collect::<Vec<_>>().join(", ")));
+                },
+                None => {},
+            }
+
+            let has_scores = stage.candidates.iter().any(|candidate| candidate.score.is_some() || candidate.tfidf_score.is_some());
+            let rows = stage.candidates.iter().enumerate().map(
+                |(index, candidate)| {
+                    let mut row = vec![
+                        (index + 1).to_string(),
+                        candidate.uid.get_short_name(),
+                    ];
+
+                    if has_scores {
+                        row.push(candidate.score.map(|score| format!("{score:.4}")).unwrap_or_else(|| String::from("-")));
+                        row.push(candidate.tfidf_score.map(|score| format!("{score:.3}")).unwrap_or_else(|| String::from("-")));
+                        row.push(candidate.term_scores.iter().map(|(term, score)| format!("{term}:{score:.3}")).collect::<Vec<_>>().join(" "));
+                    }
+
+                    row.push(candidate.source.clone());
+                    row
+                }
+            ).collect::<Vec<_>>();
+            let header = if has_scores {
+                vec!["#", "uid", "score", "tf-idf", "terms", "source"]
+            } else {
+                vec!["#", "uid", "source"]
+            };
+
+            lines.extend(render_rows(&header, &rows));
+        }
+
+        lines.join("\n")
+    }
+}
+
+fn render_rows(header: &[&str], rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths = header.iter().map(|h| h.chars().count()).collect::<Vec<_>>();
+
+    for row in rows.iter() {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    let render_row = |row: Vec<String>| {
+        row.iter().enumerate().map(
+            |(i, cell)| if i + 1 == row.len() {
+                // the last column is not padded
+                cell.to_string()
+            } else {
+                format!("{cell}{}", " ".repeat(widths[i] - cell.chars().count()))
+            }
+        ).collect::<Vec<_>>().join(" | ")
+    };
+
+    let mut result = vec![render_row(header.iter().map(|h| h.to_string()).collect())];
+    result.push(widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("-+-"));
+
+    for row in rows.iter() {
+        result.push(render_row(row.clone()));
+    }
+
+    result
+}
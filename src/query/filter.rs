@@ -0,0 +1,113 @@
+use crate::error::Error;
+use crate::index::Index;
+use crate::uid::{Uid, UidQueryConfig};
+use chrono::{DateTime, Local, NaiveDate};
+use ragit_fs::extension;
+use ragit_ignore::Pattern;
+use std::collections::HashSet;
+
+/// It restricts retrieval to a subset of the knowledge-base. A chunk has to pass
+/// all the conditions that are set. The filter is applied before ranking, so
+/// `max_summaries` and `max_retrieval` chunks are selected from the chunks
+/// that pass the filter.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RetrievalFilter {
+    /// A pattern like the ones in `.ragignore`. `docs/api/**` matches
+    /// `docs/api/a.md` and `x/docs/api/a.md`, but `/docs/api/**` matches only the former.
+    pub path: Option<String>,
+
+    /// Extensions of the files, without dots (e.g. `["md", "txt"]`).
+    pub file_ext: Vec<String>,
+
+    /// Chunks created before this (unix timestamp, in seconds) are filtered out.
+    /// It compares `Chunk::timestamp`.
+    pub after: Option<i64>,
+
+    /// A uid query (see `rag help uid-query`). If it matches a file, all the chunks
+    /// of the file pass the filter.
+    pub uid: Option<String>,
+}
+
+impl RetrievalFilter {
+    pub fn is_empty(&self) -> bool {
+        self.path.is_none() && self.file_ext.is_empty() && self.after.is_none() && self.uid.is_none()
+    }
+
+    /// `2025-01-01` is the midnight in the local timezone. It also accepts rfc 3339
+    /// (`2025-01-01T09:00:00+09:00`).
+    pub fn parse_date(s: &str) -> Option<i64> {
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return date.and_hms_opt(0, 0, 0)?.and_local_timezone(Local).earliest().map(|date| date.timestamp());
+        }
+
+        DateTime::parse_from_rfc3339(s).ok().map(|date| date.timestamp())
+    }
+
+    fn is_path_match(&self, pattern: &Option<Pattern>, path: &str) -> bool {
+        if let Some(pattern) = pattern {
+            if !pattern.is_match(path) {
+                return false;
+            }
+        }
+
+        if !self.file_ext.is_empty() {
+            let ext = extension(path).unwrap_or(None).unwrap_or_default().to_ascii_lowercase();
+
+            if !self.file_ext.iter().any(|e| e.trim_start_matches('.').to_ascii_lowercase() == ext) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl Index {
+    /// It returns the uids of the chunks that pass the filter. It returns `None` if
+    /// the filter is empty, so that callers don't have to iterate all the chunks.
+    pub(crate) fn resolve_retrieval_filter(&self, filter: &RetrievalFilter) -> Result<Option<HashSet<Uid>>, Error> {
+        if filter.is_empty() {
+            return Ok(None);
+        }
+
+        let mut candidates = if filter.path.is_some() || !filter.file_ext.is_empty() {
+            let pattern = filter.path.as_ref().map(|path| Pattern::parse(path));
+            let mut result = vec![];
+
+            for (path, file_uid) in self.processed_files.iter() {
+                if filter.is_path_match(&pattern, path) {
+                    result.extend(self.get_chunks_of_file(*file_uid)?);
+                }
+            }
+
+            result
+        } else {
+            self.get_all_chunk_uids()?
+        };
+
+        if let Some(uid) = &filter.uid {
+            let query_result = self.uid_query(&[uid.to_string()], UidQueryConfig::new().file_or_chunk_only().no_staged_file())?;
+            let mut matched = query_result.get_chunk_uids().into_iter().collect::<HashSet<_>>();
+
+            for file_uid in query_result.get_file_uids() {
+                matched.extend(self.get_chunks_of_file(file_uid)?);
+            }
+
+            candidates.retain(|uid| matched.contains(uid));
+        }
+
+        if let Some(after) = filter.after {
+            let mut result = Vec::with_capacity(candidates.len());
+
+            for uid in candidates.into_iter() {
+                if self.get_chunk_by_uid(uid)?.timestamp >= after {
+                    result.push(uid);
+                }
+            }
+
+            candidates = result;
+        }
+
+        Ok(Some(candidates.into_iter().collect()))
+    }
+}
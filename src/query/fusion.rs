@@ -0,0 +1,154 @@
+use super::{EmbeddingMode, Keywords, RetrievalFilter};
+use crate::error::Error;
+use crate::index::Index;
+use crate::uid::Uid;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+impl Index {
+    /// It collects candidates from tf-idf, the inverted index and embedding vectors,
+    /// and merges them with reciprocal rank fusion. Each source contributes
+    /// `weight / (rrf_k + rank)` to a chunk's score, where `rank` starts from 1.
+    /// A source is skipped if its weight is 0 or if it's not available (e.g. ii
+    /// is not built or `embedding_mode` is `none`).
+    ///
+    /// If `keywords` is `None` and tf-idf or ii is used, it extracts keywords from `query`.
+    /// It returns at most `limit` chunks, in descending order of the fused score.
+    pub async fn run_hybrid_search(
+        &self,
+        query: &str,
+        keywords: Option<Keywords>,
+        limit: usize,
+    ) -> Result<Vec<(Uid, f32)>, Error> {
+        self.run_hybrid_search_worker(query, keywords, limit, None).await
+    }
+
+    /// It's like `run_hybrid_search`, but only the chunks that pass `filter` are ranked.
+    pub async fn run_hybrid_search_with_filter(
+        &self,
+        query: &str,
+        keywords: Option<Keywords>,
+        limit: usize,
+        filter: &RetrievalFilter,
+    ) -> Result<Vec<(Uid, f32)>, Error> {
+        let allowed = self.resolve_retrieval_filter(filter)?;
+        self.run_hybrid_search_worker(query, keywords, limit, allowed.as_ref()).await
+    }
+
+    pub(crate) async fn run_hybrid_search_worker(
+        &self,
+        query: &str,
+        keywords: Option<Keywords>,
+        limit: usize,
+        allowed: Option<&HashSet<Uid>>,
+    ) -> Result<Vec<(Uid, f32)>, Error> {
+        if let Some(allowed) = allowed {
+            if allowed.is_empty() {
+                return Ok(vec![]);
+            }
+        }
+
+        let config = &self.query_config;
+        let use_lexical = config.embedding_mode != EmbeddingMode::Only;
+        let use_tfidf = use_lexical && config.tfidf_weight > 0;
+        let use_ii = use_lexical && config.ii_weight > 0 && self.is_ii_built();
+        let use_vector = config.embedding_mode != EmbeddingMode::None && config.vector_weight > 0;
+        let mut rankings = vec![];
+
+        if use_tfidf || use_ii {
+            let keywords = match keywords {
+                Some(keywords) => keywords,
+                None => self.extract_keywords(query).await?,
+            };
+
+            if use_ii {
+                let tokenizer = self.tokenizer();
+                let phrase_matches = self.match_phrases(&keywords.phrases(&tokenizer))?;
+                rankings.push((
+                    self.get_search_candidates_worker(&keywords.tokenize(&tokenizer), phrase_matches.as_ref(), allowed, limit)?,
+                    config.ii_weight,
+                ));
+            }
+
+            if use_tfidf {
+                rankings.push((
+                    self.run_tfidf_worker(keywords, limit, allowed)?.into_iter().map(|result| result.id).collect(),
+                    config.tfidf_weight,
+                ));
+            }
+        }
+
+        if use_vector {
+            rankings.push((
+                self.run_vector_search_worker(query, limit, allowed).await?.into_iter().map(|(uid, _)| uid).collect(),
+                config.vector_weight,
+            ));
+        }
+
+        let mut result = reciprocal_rank_fusion(&rankings, config.rrf_k);
+        result.truncate(limit);
+        Ok(result)
+    }
+}
+
+/// `rankings` is a list of (ranking, weight). A ranking is sorted from the most relevant item.
+/// The result is sorted by the fused score. Ties are broken by the order in which the
+/// items first appear in `rankings`, so the result is deterministic.
+pub fn reciprocal_rank_fusion<T: Clone + Eq + Hash>(
+    rankings: &[(Vec<T>, usize)],
+    k: usize,
+) -> Vec<(T, f32)> {
+    let mut scores: HashMap<T, (f32, usize)> = HashMap::new();
+    let mut first_seen = 0;
+
+    for (ranking, weight) in rankings.iter() {
+        for (rank, item) in ranking.iter().enumerate() {
+            let score = *weight as f32 / (k + rank + 1) as f32;
+
+            match scores.get_mut(item) {
+                Some((score_, _)) => { *score_ += score; },
+                None => {
+                    scores.insert(item.clone(), (score, first_seen));
+                    first_seen += 1;
+                },
+            }
+        }
+    }
+
+    let mut result = scores.into_iter().collect::<Vec<_>>();
+    result.sort_by(
+        |(_, (score_a, seen_a)), (_, (score_b, seen_b))| score_b.partial_cmp(score_a).unwrap_or(std::cmp::Ordering::Equal).then(seen_a.cmp(seen_b))
+    );
+    result.into_iter().map(|(item, (score, _))| (item, score)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reciprocal_rank_fusion;
+
+    #[test]
+    fn rrf_test() {
+        // a single ranking is preserved
+        let result = reciprocal_rank_fusion(&[(vec!["a", "b", "c"], 1)], 60);
+        assert_eq!(result.iter().map(|(item, _)| *item).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+
+        // an item that every ranking agrees on wins
+        let result = reciprocal_rank_fusion(&[(vec!["a", "b"], 1), (vec!["c", "b"], 1)], 60);
+        assert_eq!(result[0].0, "b");
+        assert_eq!(result.len(), 3);
+
+        // ties are broken by the first appearance
+        assert_eq!(result[1].0, "a");
+        assert_eq!(result[2].0, "c");
+
+        // weights
+        let result = reciprocal_rank_fusion(&[(vec!["a", "b"], 1), (vec!["b", "a"], 3)], 60);
+        assert_eq!(result[0].0, "b");
+
+        // a ranking with weight 0 doesn't change the order
+        let result = reciprocal_rank_fusion(&[(vec!["a", "b"], 1), (vec!["b", "c"], 0)], 60);
+        assert_eq!(result.iter().map(|(item, _)| *item).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+
+        assert!(reciprocal_rank_fusion::<u32>(&[], 60).is_empty());
+    }
+}
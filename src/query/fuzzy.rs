@@ -0,0 +1,247 @@
+use super::QueryConfig;
+use crate::error::Error;
+use crate::index::{CorpusStats, Index, tfidf};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// A prefix shorter than this is too ambiguous.
+const MIN_PREFIX_LEN: usize = 3;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpansionKind {
+    /// The query term is a prefix of the expanded term.
+    Prefix,
+
+    /// The expanded term is within the edit distance of the query term.
+    Typo,
+}
+
+/// Terms in the knowledge-base and their document frequencies. The terms are
+/// decomposed (see `ragit_korean::decompose`) when it's created, so that it
+/// doesn't have to decompose them for every query term.
+pub struct TermDictionary {
+    // (term, decomposed term, document frequency), sorted by term
+    entries: Vec<(String, Vec<char>, usize)>,
+}
+
+impl TermDictionary {
+    pub fn new(frequencies: &HashMap<String, usize>) -> Self {
+        let mut entries = frequencies.iter().map(
+            |(term, frequency)| (term.to_string(), ragit_korean::decompose(term).chars().collect(), *frequency)
+        ).collect::<Vec<_>>();
+        entries.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+        TermDictionary { entries }
+    }
+
+    pub fn contains(&self, term: &str) -> bool {
+        self.entries.binary_search_by(|(term_, _, _)| term_.as_str().cmp(term)).is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// A term in the knowledge-base that a query term is expanded to.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ExpandedTerm {
+    pub term: String,
+    pub kind: ExpansionKind,
+
+    /// Edit distance between the query term and `term`. It's 0 for prefixes.
+    pub distance: usize,
+
+    /// The expanded term's weight is the query term's weight multiplied by this.
+    pub weight: f32,
+}
+
+impl Index {
+    /// It expands each term in `terms` (`TfidfState::terms`) to similar terms
+    /// in the knowledge-base. See `expand_term` for the rules.
+    pub fn expand_terms(&self, terms: &HashMap<String, f32>) -> Result<HashMap<String, Vec<ExpandedTerm>>, Error> {
+        let dictionary = self.get_term_dictionary()?;
+
+        Ok(terms.keys().map(
+            |term| (term.to_string(), expand_term(term, &dictionary, &self.query_config))
+        ).collect())
+    }
+
+    /// Every term in the knowledge-base and its document frequency. It reads
+    /// `corpus_stats.json` if it's available, otherwise it reads all the tfidf files.
+    /// The result is cached until the knowledge-base changes.
+    pub fn get_term_dictionary(&self) -> Result<Arc<TermDictionary>, Error> {
+        if let Some(dictionary) = self.get_cached_term_dictionary() {
+            return Ok(dictionary);
+        }
+
+        let dictionary = match self.get_corpus_stats()? {
+            Some(stats) => TermDictionary::new(&stats.document_frequency),
+            None => {
+                let mut stats = CorpusStats::default();
+
+                for tfidf_file in self.get_all_tfidf_files()? {
+                    stats.add_doc(&tfidf::load_from_file(&tfidf_file)?);
+                }
+
+                TermDictionary::new(&stats.document_frequency)
+            },
+        };
+        let dictionary = Arc::new(dictionary);
+        self.cache_term_dictionary(dictionary.clone());
+        Ok(dictionary)
+    }
+}
+
+/// It expands `term` to the terms in `dictionary`.
+///
+/// 1. prefix: if `term` has at least 3 characters, the terms that start with `term`.
+/// 2. typo: if `term` is not in `dictionary`, the terms within a bounded edit distance.
+///    It allows 1 edit for terms with 4~7 characters and 2 edits for longer terms,
+///    but not more than `query_config.fuzzy_max_distance`. Swapping two adjacent
+///    characters is 1 edit.
+///
+/// 한글 terms are compared in 자모s, so "한그" is a prefix of "한글". The expanded
+/// terms are weighted down by `query_config.fuzzy_weight`, and at most
+/// `query_config.fuzzy_max_expansions` terms are returned, the most similar ones first.
+pub fn expand_term(term: &str, dictionary: &TermDictionary, query_config: &QueryConfig) -> Vec<ExpandedTerm> {
+    let query = ragit_korean::decompose(term).chars().collect::<Vec<_>>();
+    let max_distance = match query.len() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }.min(query_config.fuzzy_max_distance);
+    let check_typo = max_distance > 0 && !dictionary.contains(term);
+    let mut result = vec![];
+
+    for (candidate, candidate_chars, frequency) in dictionary.entries.iter() {
+        if candidate == term {
+            continue;
+        }
+
+        if query.len() >= MIN_PREFIX_LEN && candidate_chars.starts_with(&query) {
+            result.push((
+                ExpandedTerm {
+                    term: candidate.to_string(),
+                    kind: ExpansionKind::Prefix,
+                    distance: 0,
+
+                    // The longer the rest is, the less relevant it is.
+                    weight: query_config.fuzzy_weight.0 * query.len() as f32 / candidate_chars.len() as f32,
+                },
+                *frequency,
+            ));
+        }
+
+        else if check_typo {
+            if let Some(distance) = bounded_edit_distance(&query, candidate_chars, max_distance) {
+                result.push((
+                    ExpandedTerm {
+                        term: candidate.to_string(),
+                        kind: ExpansionKind::Typo,
+                        distance,
+                        weight: query_config.fuzzy_weight.0 / distance as f32,
+                    },
+                    *frequency,
+                ));
+            }
+        }
+    }
+
+    // the result has to be deterministic
+    result.sort_by(
+        |(a, freq_a), (b, freq_b)| b.weight.total_cmp(&a.weight).then(
+            freq_b.cmp(freq_a)
+        ).then(
+            a.term.cmp(&b.term)
+        )
+    );
+
+    result.into_iter().take(query_config.fuzzy_max_expansions).map(|(term, _)| term).collect()
+}
+
+// Optimal string alignment distance. It returns `None` if the distance is greater than `max_distance`.
+fn bounded_edit_distance(a: &[char], b: &[char], max_distance: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max_distance {
+        return None;
+    }
+
+    let mut prev_prev: Vec<usize> = vec![];
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+
+    for i in 1..=a.len() {
+        let mut curr = vec![i; b.len() + 1];
+
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            curr[j] = (prev[j] + 1).min(curr[j - 1] + 1).min(prev[j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                curr[j] = curr[j].min(prev_prev[j - 2] + 1);
+            }
+        }
+
+        // every path goes through this row
+        if curr.iter().min().copied().unwrap_or(0) > max_distance {
+            return None;
+        }
+
+        prev_prev = prev;
+        prev = curr;
+    }
+
+    let distance = prev[b.len()];
+
+    if distance <= max_distance {
+        Some(distance)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_test() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(bounded_edit_distance(&chars("retreiv"), &chars("retriev"), 2), Some(1));
+        assert_eq!(bounded_edit_distance(&chars("kitten"), &chars("sitting"), 3), Some(3));
+        assert_eq!(bounded_edit_distance(&chars("kitten"), &chars("sitting"), 2), None);
+        assert_eq!(bounded_edit_distance(&chars(""), &chars("ab"), 2), Some(2));
+
+        let dictionary = [
+            ("retriev", 3),
+            ("retain", 1),
+            ("configur", 5),
+            ("config", 2),
+            ("한글", 1),
+            ("cat", 9),
+        ].into_iter().map(|(term, frequency)| (term.to_string(), frequency)).collect::<HashMap<_, _>>();
+        let dictionary = TermDictionary::new(&dictionary);
+        assert!(dictionary.contains("config"));
+        assert!(!dictionary.contains("confi"));
+        let config = QueryConfig::default();
+        let expand = |term: &str| expand_term(term, &dictionary, &config).into_iter().map(
+            |ExpandedTerm { term, kind, distance, .. }| (term, kind, distance)
+        ).collect::<Vec<_>>();
+
+        assert_eq!(expand("retreiv"), vec![(String::from("retriev"), ExpansionKind::Typo, 1)]);
+        assert_eq!(expand("retr"), vec![(String::from("retriev"), ExpansionKind::Prefix, 0)]);
+
+        // "config" is in the dictionary, so it's not expanded to typos
+        assert_eq!(expand("config"), vec![(String::from("configur"), ExpansionKind::Prefix, 0)]);
+        assert_eq!(expand("한그"), vec![(String::from("한글"), ExpansionKind::Prefix, 0)]);
+
+        // too short
+        assert!(expand("ca").is_empty());
+        assert!(expand("car").is_empty());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::Index;
 use crate::error::Error;
-use crate::index::tfidf::tokenize;
+use crate::index::tfidf::{Tokenizer, tokenize};
+use lazy_static::lazy_static;
 use ragit_api::{
     RecordAt,
     Request,
@@ -10,8 +11,14 @@ use ragit_pdl::{
     escape_pdl_tokens,
     parse_pdl,
 };
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+lazy_static! {
+    static ref QUOTE_RE: Regex = Regex::new(r#""([^"]*)"(~(\d+))?"#).unwrap();
+    static ref PROXIMITY_RE: Regex = Regex::new(r#""~\d+"#).unwrap();
+}
 
 #[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct Keywords {
@@ -43,11 +50,11 @@ impl Keywords {
 
     // keywords can be any string. it can be fed by user or ai
     // keywords are tokenized and deduplicated before tfidf
-    pub fn tokenize(&self) -> HashMap<String, f32> {  // HashMap<Token, weight>
+    pub fn tokenize(&self, tokenizer: &Tokenizer) -> HashMap<String, f32> {  // HashMap<Token, weight>
         let mut tokens = HashMap::new();
 
         for (keyword, weight) in self.with_weights(4.0) {
-            for token in tokenize(&keyword) {
+            for token in tokenize(&remove_proximity_operators(&keyword), tokenizer) {
                 match tokens.get_mut(&token) {
                     Some(w) => {
                         *w += weight;
@@ -61,6 +68,134 @@ impl Keywords {
 
         tokens
     }
+
+    /// A keyword with multiple terms is a phrase. See `PhraseKind` for the syntax.
+    pub fn phrases(&self, tokenizer: &Tokenizer) -> Vec<Phrase> {
+        let mut result = vec![];
+
+        for keyword in self.keywords.iter().chain(self.extra.iter()) {
+            result.extend(parse_phrases(keyword, tokenizer));
+        }
+
+        result
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Phrase {
+    /// tokenized terms, in order
+    pub terms: Vec<String>,
+    pub kind: PhraseKind,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PhraseKind {
+    /// `"connection pool"`: the terms must appear next to each other, in order.
+    Exact,
+
+    /// `"connection pool timeout"~5`: the terms must appear in a window of
+    /// `(number of terms) + 5` terms, in any order.
+    Near(usize),
+
+    /// `connection pool timeout`: it doesn't filter out any chunk, but chunks
+    /// whose terms are close to each other get a higher score.
+    Loose,
+}
+
+impl Phrase {
+    /// `positions` are the positions of the terms in a chunk, which are sorted.
+    /// It returns `None` if the chunk doesn't match the phrase. Otherwise, it returns
+    /// how close the terms are, from 0.0 (exclusive) to 1.0 (adjacent).
+    pub fn get_proximity(&self, positions: &HashMap<String, Vec<usize>>) -> Option<f32> {
+        for term in self.terms.iter() {
+            if positions.get(term).map(|p| p.is_empty()).unwrap_or(true) {
+                return None;
+            }
+        }
+
+        if let PhraseKind::Exact = self.kind {
+            for start in positions.get(&self.terms[0]).unwrap().iter() {
+                if self.terms.iter().enumerate().skip(1).all(
+                    |(i, term)| positions.get(term).unwrap().binary_search(&(start + i)).is_ok()
+                ) {
+                    return Some(1.0);
+                }
+            }
+
+            return None;
+        }
+
+        // the smallest window that contains all the terms
+        let terms = self.terms.iter().collect::<HashSet<_>>().into_iter().collect::<Vec<_>>();
+        let mut merged = vec![];
+
+        for (index, term) in terms.iter().enumerate() {
+            for position in positions.get(*term).unwrap().iter() {
+                merged.push((*position, index));
+            }
+        }
+
+        merged.sort();
+        let mut counts = vec![0; terms.len()];
+        let mut covered = 0;
+        let mut start = 0;
+        let mut window = usize::MAX;
+
+        for end in 0..merged.len() {
+            if counts[merged[end].1] == 0 {
+                covered += 1;
+            }
+
+            counts[merged[end].1] += 1;
+
+            while covered == terms.len() {
+                window = window.min(merged[end].0 - merged[start].0 + 1);
+                counts[merged[start].1] -= 1;
+
+                if counts[merged[start].1] == 0 {
+                    covered -= 1;
+                }
+
+                start += 1;
+            }
+        }
+
+        match self.kind {
+            PhraseKind::Near(n) if window > terms.len() + n => None,
+            _ => Some(terms.len() as f32 / window as f32),
+        }
+    }
+}
+
+fn parse_phrases(keyword: &str, tokenizer: &Tokenizer) -> Vec<Phrase> {
+    let mut result = vec![];
+
+    for cap in QUOTE_RE.captures_iter(keyword) {
+        let terms = tokenize(&cap[1], tokenizer);
+
+        if terms.len() < 2 {
+            continue;
+        }
+
+        let kind = match cap.get(3) {
+            Some(n) => PhraseKind::Near(n.as_str().parse::<usize>().unwrap_or(usize::MAX / 2)),
+            None => PhraseKind::Exact,
+        };
+        result.push(Phrase { terms, kind });
+    }
+
+    let terms = tokenize(&remove_proximity_operators(keyword), tokenizer);
+
+    if terms.iter().collect::<HashSet<_>>().len() > 1 {
+        result.push(Phrase { terms, kind: PhraseKind::Loose });
+    }
+
+    result
+}
+
+// `"a b"~5` -> `"a b"`, so that "5" is not treated as a term
+fn remove_proximity_operators(keyword: &str) -> String {
+    PROXIMITY_RE.replace_all(keyword, "\"").to_string()
 }
 
 impl Index {
@@ -99,3 +234,40 @@ impl Index {
         Ok(request.send_and_validate::<Keywords>(Keywords::from_raw(query.split(" ").map(|k| k.to_string()).collect())).await?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Keywords, Phrase, PhraseKind};
+    use crate::index::tfidf::TokenizerConfig;
+    use std::collections::HashMap;
+
+    #[test]
+    fn phrase_test() {
+        let config = TokenizerConfig::default().tokenizer();
+        let phrases = Keywords::from_raw(vec![String::from("\"connection pool\"~3 timeout")]).phrases(&config);
+        assert_eq!(phrases[0], Phrase { terms: vec![String::from("connect"), String::from("pool")], kind: PhraseKind::Near(3) });
+        assert_eq!(phrases[1].kind, PhraseKind::Loose);
+
+        // "3" is an operator, not a term
+        assert_eq!(phrases[1].terms.len(), 3);
+        assert!(!Keywords::from_raw(vec![String::from("\"a b\"~3")]).tokenize(&config).contains_key("3"));
+
+        // a single term is not a phrase
+        assert!(Keywords::from_raw(vec![String::from("\"pool\""), String::from("timeout")]).phrases(&config).is_empty());
+
+        let positions = vec![(String::from("a"), vec![3, 10]), (String::from("b"), vec![4, 20])].into_iter().collect::<HashMap<_, _>>();
+        let phrase = |kind| Phrase { terms: vec![String::from("a"), String::from("b")], kind };
+
+        assert_eq!(phrase(PhraseKind::Exact).get_proximity(&positions), Some(1.0));
+        assert_eq!(Phrase { terms: vec![String::from("b"), String::from("a")], kind: PhraseKind::Exact }.get_proximity(&positions), None);
+        assert_eq!(phrase(PhraseKind::Near(0)).get_proximity(&positions), Some(1.0));
+        assert_eq!(phrase(PhraseKind::Loose).get_proximity(&positions), Some(1.0));
+
+        let positions = vec![(String::from("a"), vec![3]), (String::from("b"), vec![6])].into_iter().collect::<HashMap<_, _>>();
+        assert_eq!(phrase(PhraseKind::Exact).get_proximity(&positions), None);
+        assert_eq!(phrase(PhraseKind::Near(1)).get_proximity(&positions), None);
+        assert_eq!(phrase(PhraseKind::Near(2)).get_proximity(&positions), Some(0.5));
+        assert_eq!(phrase(PhraseKind::Loose).get_proximity(&positions), Some(0.5));
+        assert_eq!(phrase(PhraseKind::Loose).get_proximity(&HashMap::new()), None);
+    }
+}
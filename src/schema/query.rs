@@ -1,7 +1,7 @@
-use super::Prettify;
+use super::{Prettify, prettify_uid};
 use crate::chunk::Chunk;
 use crate::error::Error;
-use crate::query::QueryResponse;
+use crate::query::{Citation, QueryResponse};
 use serde_json::Value;
 
 pub type QueryResponseSchema = QueryResponse;
@@ -20,6 +20,23 @@ impl Prettify for QueryResponseSchema {
                 },
                 _ => {},
             }
+
+            // so that you can render footnotes without looking up `retrieved_chunks`
+            if let Some(Value::Array(citations)) = obj.get_mut("citations") {
+                for citation in citations.iter_mut() {
+                    let source = self.get_citation_source(&serde_json::from_value::<Citation>(citation.clone())?).map(|source| source.render());
+
+                    if let Value::Object(citation) = citation {
+                        if let Some(source) = source {
+                            citation.insert(String::from("source"), Value::String(source));
+                        }
+
+                        if let Some(uid) = citation.get_mut("uid") {
+                            *uid = prettify_uid(uid);
+                        }
+                    }
+                }
+            }
         }
 
         Ok(result)
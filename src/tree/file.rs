@@ -91,7 +91,7 @@ impl Index {
                 self.build_config.compression_threshold,
                 self.build_config.compression_level,
                 &self.root_dir,
-                true,  // create tfidf
+                Some(&self.tokenizer()),  // create tfidf
             )?;
             self.chunk_count += 1;
             Ok(new_chunk.uid)
@@ -110,6 +110,8 @@ impl Index {
                 title: chunks[0].title.clone(),
                 summary: chunks[0].summary.clone(),
                 source: ChunkSource::Chunks { uids: vec![chunks[0].uid] },
+                heading: None,
+                symbol: None,
                 searchable: false,
                 uid: Uid::new_group(&[chunks[0].uid]),
                 build_info: chunks[0].build_info.clone(),
@@ -170,6 +172,8 @@ impl Index {
                     title: response.title,
                     summary: response.summary,
                     source: ChunkSource::Chunks { uids: chunk_uids.clone() },
+                    heading: None,
+                    symbol: None,
                     searchable: false,
                     uid: Uid::dummy(),
                     build_info: ChunkBuildInfo::new(
@@ -0,0 +1,78 @@
+from glob import glob
+import json
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, write_string
+
+def search(expression: str) -> list[str]:
+    output = cargo_run(["search", "--json", expression], stdout=True)
+    return sorted([result["path"] for result in json.loads(output.strip())])
+
+def boolean_search():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    write_string("adjacent.txt", "The connection pool timeout is 30 seconds.")
+    write_string("reversed.txt", "The pool connection is reused.")
+    write_string("cache.txt", "Cache entries expire after a timeout.")
+    write_string("sample.txt", "Apples and bananas are yellow or red.")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["add", "adjacent.txt", "reversed.txt", "cache.txt", "sample.txt"])
+    cargo_run(["build"])
+
+    cases = [
+        ("connection", ["adjacent.txt", "reversed.txt"]),
+        ("connections", ["adjacent.txt", "reversed.txt"]),  # stemmed
+        ("connection AND timeout", ["adjacent.txt"]),
+        ("connection timeout", ["adjacent.txt"]),
+        ("connection OR cache", ["adjacent.txt", "cache.txt", "reversed.txt"]),
+        ("timeout NOT connection", ["cache.txt"]),
+        ("NOT timeout", ["reversed.txt", "sample.txt"]),
+        ("(pool OR cache) AND NOT (reused OR expire)", ["adjacent.txt"]),
+        ("pool OR cache AND expire", ["adjacent.txt", "cache.txt", "reversed.txt"]),
+        ('"connection pool"', ["adjacent.txt"]),
+        ('"pool connection"', ["reversed.txt"]),
+        ('"connection pool" OR "cache entries"', ["adjacent.txt", "cache.txt"]),
+        ('connection NOT "connection pool"', ["reversed.txt"]),
+        ("yellow and red", ["sample.txt"]),  # lowercase `and` is a term
+        ("nonexistent", []),
+        ("NOT nonexistent", ["adjacent.txt", "cache.txt", "reversed.txt", "sample.txt"]),
+    ]
+
+    # step 1: full scan, without ii, with ii, and with positional ii
+    #         all of them must give the same result
+    for step in ["full-scan", "ii", "ii-positions"]:
+        if step == "ii":
+            cargo_run(["ii-build"])
+
+        elif step == "ii-positions":
+            cargo_run(["config", "--set", "ii_positions", "true"])
+            cargo_run(["ii-build"])
+
+        for expression, expected in cases:
+            assert search(expression) == expected, (step, expression, search(expression))
+
+    # step 2: ii is disabled, so it falls back to full scan
+    cargo_run(["config", "--set", "enable_ii", "false"])
+
+    for expression, expected in cases:
+        assert search(expression) == expected
+
+    # step 3: output formats
+    uids = json.loads(cargo_run(["search", "--json", "--uid-only", "connection"], stdout=True))
+    assert len(uids) == 2
+    assert cargo_run(["search", "--uid-only", "connection"], stdout=True).split() == uids
+    assert "1st chunk of adjacent.txt" in cargo_run(["search", "connection"], stdout=True)
+
+    # step 4: invalid expressions
+    for expression in ["", "connection AND", "(connection", "connection)", '"connection', "NOT"]:
+        assert cargo_run(["search", expression], check=False) != 0
+
+    # step 5: summary chunks are not searchable, even with NOT
+    # `rag summary-file` saves a summary chunk, but fails to load it (see `ChunkSource::Chunks`)
+    cargo_run(["summary-file"], check=False)
+    assert len(glob(".ragit/chunks/**/*.chunk", recursive=True)) > 4
+
+    for enable_ii in ["false", "true"]:
+        cargo_run(["config", "--set", "enable_ii", enable_ii])
+
+        for expression, expected in cases:
+            assert search(expression) == expected, (enable_ii, expression, search(expression))
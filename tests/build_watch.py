@@ -0,0 +1,83 @@
+import os
+import shutil
+import subprocess
+import time
+from utils import (
+    cargo_run,
+    count_files,
+    goto_root,
+    mk_and_cd_tmp_dir,
+    write_string,
+)
+
+# It waits until `count_files()` returns `expected`, because `rag build --watch` runs in the background.
+def wait_for(expected: tuple[int, int, int], timeout: float = 60):
+    started_at = time.time()
+
+    while time.time() - started_at < timeout:
+        # `extra_check` runs `ls-files` twice, and the index might change between the two runs.
+        if count_files(extra_check=False) == expected:
+            return
+
+        time.sleep(0.5)
+
+    raise AssertionError(f"timeout: expected {expected}, got {count_files(extra_check=False)}")
+
+def build_watch():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    os.mkdir("docs")
+    write_string("docs/a.txt", "Hello, world!")
+    write_string("ignored.txt", "You cannot see me.")
+    write_string(".ragignore", "ignored.txt\nlogs/")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["build"])  # compile ragit before it starts the watcher
+
+    watcher = subprocess.Popen(["cargo", "run", "--release", "--", "build", "--watch", "--debounce", "200"])
+
+    try:
+        # step 1: it builds the existing files when it starts
+        wait_for((1, 0, 1))
+
+        # step 2: new files, including new directories
+        write_string("docs/b.txt", "Goodbye, world!")
+        os.mkdir("docs/sub")
+        write_string("docs/sub/c.txt", "Hello again!")
+        wait_for((3, 0, 3))
+
+        # step 3: ignored files are not added
+        write_string("ignored.txt", "You still cannot see me.")
+        os.mkdir("logs")
+        write_string("logs/d.txt", "This is a log.")
+        time.sleep(2)
+        assert count_files(extra_check=False) == (3, 0, 3)
+
+        # step 4: modified and deleted files
+        write_string("docs/a.txt", "Hello, world! I'm modified.")
+        os.remove("docs/b.txt")
+        wait_for((2, 0, 2))
+
+        # it waits until `docs/a.txt` is built again
+        started_at = time.time()
+
+        while cargo_run(["status", "--json"], stdout=True).count("a.txt") > 0:
+            assert time.time() - started_at < 60
+            time.sleep(0.5)
+
+        # step 5: a directory that's removed and created again is watched again
+        shutil.rmtree("docs/sub")
+        wait_for((1, 0, 1))
+        os.mkdir("docs/sub")
+        write_string("docs/sub/e.txt", "I'm back!")
+        wait_for((2, 0, 2))
+        time.sleep(1)
+        write_string("docs/sub/f.txt", "Me too!")
+        wait_for((3, 0, 3))
+
+    finally:
+        watcher.kill()
+        watcher.wait()
+
+    cargo_run(["check"])
+    assert "up to date" in cargo_run(["status"], stdout=True)
@@ -0,0 +1,74 @@
+import json
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, read_string, write_string
+
+def search(keywords: str) -> list[str]:
+    output = cargo_run(["tfidf", "--json", keywords], stdout=True)
+    return sorted([result["source"] for result in json.loads(output.strip())])
+
+# A multi-character word also matches the chunks that share a character with it,
+# but the chunk that has the entire word comes first.
+def top(keywords: str) -> str:
+    output = cargo_run(["tfidf", "--json", keywords], stdout=True)
+    return json.loads(output.strip())[0]["source"]
+
+def cjk():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    write_string("japanese.txt", "東京の天気は晴れです。コンピュータで検索します。")
+    write_string("chinese.txt", "我爱北京天安门。")
+    write_string("korean.txt", "나는 비빔인간입니다.")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["add", "japanese.txt", "chinese.txt", "korean.txt"])
+    cargo_run(["build"])
+
+    # step 1: words in a sentence are searchable
+    assert top("天気") == "1st chunk of japanese.txt"
+    assert search("コンピュータ") == ["1st chunk of japanese.txt"]
+    assert top("北京") == "1st chunk of chinese.txt"
+    assert top("天安门") == "1st chunk of chinese.txt"
+    assert search("비빔인간") == ["1st chunk of korean.txt"]
+    assert search("大阪") == []
+
+    # a single-character word matches the longer words that contain it
+    assert search("京") == ["1st chunk of chinese.txt", "1st chunk of japanese.txt"]
+
+    terms = json.loads(cargo_run(["ls-terms", "--json"], stdout=True))
+    assert "東京" in terms and "天気" in terms and "北京" in terms
+    assert "。" not in "".join(terms.keys())
+
+    # step 2: boolean search and the inverted index
+    cargo_run(["ii-build"])
+    assert "japanese.txt" in cargo_run(["search", "東京 AND 天気"], stdout=True)
+    assert "chinese.txt" not in cargo_run(["search", "東京 AND 天気"], stdout=True)
+    assert top("天気") == "1st chunk of japanese.txt"
+
+    # step 3: tfidf files of older versions don't split Chinese and Japanese
+    index = json.loads(read_string(".ragit/index.json"))
+    index.pop("tokenizer_config")
+    write_string(".ragit/index.json", json.dumps(index))
+    assert "tokenizer config has changed" in cargo_run(["tfidf", "天気"], stdout=True)
+
+    # `rag build` rebuilds the tfidf files
+    cargo_run(["build"])
+    assert json.loads(read_string(".ragit/index.json"))["tokenizer_config"]["split_cjk"] == True
+    assert top("天気") == "1st chunk of japanese.txt"
+    assert "tokenizer config has changed" not in cargo_run(["tfidf", "天気"], stdout=True)
+    assert cargo_run(["ii-status"], stdout=True).strip() == "outdated"
+    cargo_run(["check"])
+
+    # step 4: knowledge-bases of older versions keep their tokenizer until `split_cjk` is set
+    build_config = json.loads(read_string(".ragit/configs/build.json"))
+    build_config.pop("split_cjk")
+    write_string(".ragit/configs/build.json", json.dumps(build_config))
+    index = json.loads(read_string(".ragit/index.json"))
+    index.pop("tokenizer_config")
+    write_string(".ragit/index.json", json.dumps(index))
+    assert "tokenizer config has changed" not in cargo_run(["tfidf", "天気"], stdout=True)
+
+    cargo_run(["config", "--set", "split_cjk", "true"])
+    assert "tokenizer config has changed" in cargo_run(["tfidf", "天気"], stdout=True)
+    cargo_run(["build"])
+    assert json.loads(read_string(".ragit/index.json"))["tokenizer_config"]["split_cjk"] == True
+    assert search("京") == ["1st chunk of chinese.txt", "1st chunk of japanese.txt"]
+    cargo_run(["check"])
@@ -0,0 +1,95 @@
+import json
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, write_string
+
+sample_rust = """use std::collections::HashMap;
+
+/// A tiny key-value store.
+pub struct Store {
+    data: HashMap<String, String>,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Store { data: HashMap::new() }
+    }
+
+    /// It returns the previous value.
+    pub fn insert_value(&mut self, key: &str, value: &str) -> Option<String> {
+""" + "".join([f"        // step {i}: {{ this brace is in a comment\n        let _ = \"}}\";\n" for i in range(20)]) + """        self.data.insert(key.to_string(), value.to_string())
+    }
+
+    pub fn remove_value(&mut self, key: &str) -> Option<String> {
+        self.data.remove(key)
+    }
+}
+
+fn main() {
+    let mut store = Store::new();
+    store.insert_value("a", "b");
+}
+"""
+
+sample_python = """import os
+
+class Parser:
+    def __init__(self, text):
+        self.text = text
+
+    def parse_expression(self):
+""" + "".join([f"        # step {i}\n        self.text = self.text.strip()\n" for i in range(20)]) + """        return self.text
+
+def tokenize_input(s):
+    return s.split()
+
+if __name__ == "__main__":
+    print(tokenize_input("a b"))
+"""
+
+def code_reader():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["config", "--set", "chunk_size", "400"])
+    cargo_run(["config", "--set", "slide_len", "100"])
+    cargo_run(["config", "--set", "strict_file_reader", "true"])
+
+    write_string("store.rs", sample_rust)
+    write_string("parser.py", sample_python)
+    cargo_run(["add", "store.rs", "parser.py"])
+    cargo_run(["build"])
+    cargo_run(["check"])
+
+    # the chunks have the exact content of the files
+    # `cat-file` escapes pdl tokens, so `&` becomes `&amp;`
+    assert cargo_run(["cat-file", "store.rs"], stdout=True).strip().replace("&amp;", "&") == sample_rust.strip()
+    assert cargo_run(["cat-file", "parser.py"], stdout=True).strip() == sample_python.strip()
+
+    chunks = json.loads(cargo_run(["ls-chunks", "--json"], stdout=True))
+    assert all(chunk["build_info"]["file_reader_key"] == "code_reader_v0" for chunk in chunks)
+    symbols = set(chunk.get("symbol") for chunk in chunks)
+    assert "Store::insert_value" in symbols
+    assert "Parser.parse_expression" in symbols
+
+    for chunk in chunks:
+        # If a chunk starts in the middle of a function, it knows the signature of the function.
+        if chunk["data"].lstrip().startswith("// step") or chunk["data"].lstrip().startswith("let _"):
+            assert chunk["heading"] == "impl Store > pub fn insert_value(&mut self, key: &str, value: &str) -> Option<String>"
+
+        if chunk["data"].lstrip().startswith("# step") or chunk["data"].lstrip().startswith("self.text = self.text.strip()"):
+            assert chunk["heading"] == "class Parser > def parse_expression(self)"
+
+        # a chunk never has 2 long functions
+        assert not ("fn insert_value" in chunk["data"] and "fn remove_value" in chunk["data"])
+
+    # The heading is a part of the source, so it's searchable. The chunks in the middle of
+    # `parse_expression` do not have the name in their data, but they're still found.
+    middle_chunks = [
+        chunk["uid"] for chunk in chunks
+        if chunk.get("symbol") == "Parser.parse_expression" and "parse_expression" not in chunk["data"]
+    ]
+    assert len(middle_chunks) > 0
+    result = json.loads(cargo_run(["tfidf", "--json", "--limit", "20", "parse_expression"], stdout=True))
+    result = set(r["uid"] for r in result)
+    assert all(uid in result for uid in middle_chunks)
+    assert "(class Parser > def parse_expression(self))" in cargo_run(["ls-chunks", "parser.py"], stdout=True)
@@ -0,0 +1,53 @@
+import os
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, read_string, write_string
+
+fruits = ["apple", "banana", "cherry", "durian", "elder", "fig", "grape", "honeydew"]
+
+# It returns the documents that `answer_query_with_chunks` has given to the model.
+def query_and_read_documents(query: str) -> str:
+    for f in os.listdir(".ragit/logs"):
+        os.remove(os.path.join(".ragit/logs", f))
+
+    cargo_run(["query", query])
+    logs = [f for f in os.listdir(".ragit/logs") if f.startswith("answer_query_with_chunks")]
+    assert len(logs) == 1
+    return read_string(os.path.join(".ragit/logs", logs[0]))
+
+def context_expansion():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    write_string("fruits.txt", "".join(f"Paragraph about {fruit}. " + f"{fruit} is a fruit. " * 6 + "\n" for fruit in fruits))
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["config", "--set", "dump_log", "true"])
+    cargo_run(["config", "--set", "chunk_size", "200"])
+    cargo_run(["config", "--set", "slide_len", "30"])
+
+    # tf-idf retrieves exactly 1 chunk
+    cargo_run(["config", "--set", "max_titles", "1"])
+    cargo_run(["config", "--set", "max_summaries", "1"])
+    cargo_run(["config", "--set", "max_retrieval", "1"])
+    cargo_run(["add", "fruits.txt"])
+    cargo_run(["build"])
+
+    # step 1: no expansion
+    documents = query_and_read_documents("durian")
+    assert "Paragraph about durian" in documents
+    assert "Paragraph about apple" not in documents
+    assert "Paragraph about fig" not in documents
+
+    # step 2: the neighbours are stitched to the retrieved chunk, without duplicating the sliding windows
+    cargo_run(["config", "--set", "context_chunks", "1"])
+    documents = query_and_read_documents("durian")
+    assert "Paragraph about fig" in documents
+    assert "[2] source" not in documents
+
+    for fruit in ["cherry", "durian", "elder"]:
+        assert documents.count(f"Paragraph about {fruit}") == 1
+        assert documents.count(f"{fruit} is a fruit.") == 6
+
+    # step 3: budget
+    cargo_run(["config", "--set", "context_budget", "250"])
+    documents = query_and_read_documents("durian")
+    assert "Paragraph about cherry" in documents
+    assert "Paragraph about fig" not in documents
@@ -0,0 +1,127 @@
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+import json
+import os
+import re
+from threading import Thread
+from utils import cargo_run, count_chunks, goto_root, mk_and_cd_tmp_dir, write_string
+
+from tfidf import lorem_ipsum1, lorem_ipsum2
+
+# A mock of OpenAI's `/embeddings` api. It hashes each word into one of 64
+# dimensions, so texts that share words have similar vectors.
+class MockEmbeddingHandler(BaseHTTPRequestHandler):
+    requests = []
+
+    def do_POST(self):
+        body = json.loads(self.rfile.read(int(self.headers["Content-Length"])))
+        MockEmbeddingHandler.requests.append({
+            "model": body["model"],
+            "input": body["input"],
+            "authorization": self.headers.get("Authorization"),
+        })
+
+        # it returns the vectors in reverse order, to make sure that ragit sorts them by index
+        data = [
+            {"object": "embedding", "index": i, "embedding": embed(text)}
+            for i, text in enumerate(body["input"])
+        ][::-1]
+        response = json.dumps({
+            "object": "list",
+            "data": data,
+            "model": body["model"],
+            "usage": {"prompt_tokens": 0, "total_tokens": 0},
+        }).encode("utf-8")
+        self.send_response(200)
+        self.send_header("Content-Type", "application/json")
+        self.send_header("Content-Length", str(len(response)))
+        self.end_headers()
+        self.wfile.write(response)
+
+    def log_message(self, *args):
+        pass
+
+def embed(text: str) -> list[float]:
+    vector = [0.0 for _ in range(64)]
+
+    for word in re.findall(r"\w+", text.lower()):
+        vector[sum(ord(c) * (i + 1) for i, c in enumerate(word)) % 64] += 1.0
+
+    return vector
+
+def retrieve_sources(query: str, max_summaries: int) -> list[str]:
+    output = cargo_run(["retrieve-chunks", "--no-rerank", f"--max-summaries={max_summaries}", query], stdout=True)
+    return [line[len("source: "):] for line in output.split("\n") if line.startswith("source: ")]
+
+def embedding():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    server = ThreadingHTTPServer(("127.0.0.1", 0), MockEmbeddingHandler)
+    Thread(target=server.serve_forever, daemon=True).start()
+
+    try:
+        write_string("lorem_ipsum1.txt", lorem_ipsum1)
+        write_string("lorem_ipsum2.txt", lorem_ipsum2)
+        write_string("korean.txt", "나는 비빔인간입니다.")
+        cargo_run(["init"])
+        cargo_run(["config", "--set", "model", "dummy"])
+        cargo_run(["config", "--set", "embedding_url", f"http://127.0.0.1:{server.server_port}/v1/embeddings"])
+        cargo_run(["config", "--set", "embedding_model", "mock-embedding-v1"])
+        cargo_run(["config", "--set", "embedding_api_env_var", ""])
+        cargo_run(["config", "--set", "max_titles", "0"])
+        assert cargo_run(["config", "--get", "embedding_mode"], stdout=True).strip() == '"none"'
+        cargo_run(["add", "lorem_ipsum1.txt", "lorem_ipsum2.txt", "korean.txt"])
+        cargo_run(["build"])
+
+        # step 1: it creates a vector for each chunk
+        cargo_run(["embedding-build"])
+        chunks = count_chunks()
+        embeddings = [f for d in os.listdir(".ragit/chunks") for f in os.listdir(f".ragit/chunks/{d}") if f.endswith(".embedding")]
+        assert len(embeddings) == chunks
+        assert all(request["authorization"] is None for request in MockEmbeddingHandler.requests)
+        assert all(request["model"] == "mock-embedding-v1" for request in MockEmbeddingHandler.requests)
+
+        # step 2: it doesn't create vectors that already exist
+        request_count = len(MockEmbeddingHandler.requests)
+        cargo_run(["embedding-build"])
+        assert len(MockEmbeddingHandler.requests) == request_count
+
+        # step 3: vector search only
+        cargo_run(["config", "--set", "embedding_mode", "only"])
+        assert retrieve_sources("Praesent neque magna", 1) == ["1st chunk of lorem_ipsum1.txt"]
+        assert retrieve_sources("Pellentesque tincidunt", 1) == ["1st chunk of lorem_ipsum2.txt"]
+        assert len(retrieve_sources("Pellentesque tincidunt", 3)) == 3
+
+        # step 4: rank fusion of tf-idf, ii and vector search
+        cargo_run(["config", "--set", "embedding_mode", "both"])
+        assert retrieve_sources("Pellentesque tincidunt", 1) == ["1st chunk of lorem_ipsum2.txt"]
+        assert len(retrieve_sources("Pellentesque tincidunt", 3)) == 3
+        cargo_run(["ii-build"])
+        cargo_run(["config", "--set", "ii_weight", "2"])
+        assert retrieve_sources("Praesent neque magna", 1) == ["1st chunk of lorem_ipsum1.txt"]
+
+        # a source with weight 0 is not used at all
+        cargo_run(["config", "--set", "tfidf_weight", "0"])
+        cargo_run(["config", "--set", "ii_weight", "0"])
+        cargo_run(["config", "--set", "embedding_mode", "only"])
+        vector_only = retrieve_sources("Pellentesque tincidunt", 3)
+        cargo_run(["config", "--set", "embedding_mode", "both"])
+        assert retrieve_sources("Pellentesque tincidunt", 3) == vector_only
+        cargo_run(["config", "--set", "tfidf_weight", "1"])
+
+        # step 5: `rag query` uses the vectors, too
+        cargo_run(["query", "Pellentesque tincidunt"])
+
+        # step 6: a new model invalidates all the vectors
+        cargo_run(["config", "--set", "embedding_model", "mock-embedding-v2"])
+        request_count = len(MockEmbeddingHandler.requests)
+        cargo_run(["embedding-build"])
+        assert sum(len(request["input"]) for request in MockEmbeddingHandler.requests[request_count:]) == chunks
+
+        # step 7: `rag rm` removes vectors
+        cargo_run(["rm", "korean.txt"])
+        embeddings = [f for d in os.listdir(".ragit/chunks") for f in os.listdir(f".ragit/chunks/{d}") if f.endswith(".embedding")]
+        assert len(embeddings) == chunks - 1
+        cargo_run(["check"])
+
+    finally:
+        server.shutdown()
@@ -0,0 +1,61 @@
+import json
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, read_string, write_string
+
+def search(keywords: str) -> list[str]:
+    output = cargo_run(["tfidf", "--json", keywords], stdout=True)
+    return [result["source"] for result in json.loads(output.strip())]
+
+def field_weights():
+    goto_root()
+    mk_and_cd_tmp_dir()
+
+    # "pool" is in the path of `pool.txt`, and in the data of `other.txt`
+    # the dummy model copies the data to the summary, so "pool" is also in the summary of `other.txt`
+    write_string("pool.txt", "There is nothing to see here.")
+    write_string("other.txt", "A connection pool keeps the connections. The pool is small.")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["add", "pool.txt", "other.txt"])
+    cargo_run(["build"])
+
+    # step 1: default weights
+    assert search("pool") == ["1st chunk of other.txt", "1st chunk of pool.txt"]
+
+    # step 2: the weights are applied without rebuilding the tfidf files
+    cargo_run(["config", "--set", "path_field_weight", "10"])
+    assert search("pool") == ["1st chunk of pool.txt", "1st chunk of other.txt"]
+
+    cargo_run(["config", "--set", "path_field_weight", "0"])
+    assert search("pool") == ["1st chunk of other.txt"]
+
+    cargo_run(["config", "--set", "path_field_weight", "1"])
+    cargo_run(["config", "--set", "data_field_weight", "0"])
+    assert search("pool") == ["1st chunk of other.txt", "1st chunk of pool.txt"]
+    cargo_run(["config", "--set", "summary_field_weight", "0"])
+    assert search("pool") == ["1st chunk of pool.txt"]
+
+    # step 3: it works with the inverted index
+    cargo_run(["ii-build"])
+    assert search("pool") == ["1st chunk of pool.txt"]
+    cargo_run(["config", "--set", "summary_field_weight", "1"])
+    assert search("pool") == ["1st chunk of other.txt", "1st chunk of pool.txt"]
+    cargo_run(["check"])
+
+    # step 4: invalid weights are rejected, and the config is not changed
+    for weight in ["-1", "1e39"]:
+        assert cargo_run(["config", "--set", "title_field_weight", weight], check=False) != 0
+        assert json.loads(cargo_run(["config", "--get", "title_field_weight"], stdout=True)) == 2.0
+    assert search("pool") == ["1st chunk of other.txt", "1st chunk of pool.txt"]
+
+    # step 5: the tfidf files of an older version of ragit are rebuilt by `rag migrate`, not by `rag build`
+    index = json.loads(read_string(".ragit/index.json"))
+    index.pop("tfidf_fields")
+    write_string(".ragit/index.json", json.dumps(index))
+    assert "rag migrate" in cargo_run(["tfidf", "pool"], stdout=True)
+    assert "rebuilt" not in cargo_run(["build"], stdout=True)
+    assert json.loads(read_string(".ragit/index.json"))["tfidf_fields"] == False
+    assert "rebuilt 2 tfidf files" in cargo_run(["migrate"], stdout=True)
+    assert json.loads(read_string(".ragit/index.json"))["tfidf_fields"] == True
+    assert "rag migrate" not in cargo_run(["tfidf", "pool"], stdout=True)
+    assert "rebuilt" not in cargo_run(["migrate"], stdout=True)
+    cargo_run(["check"])
@@ -0,0 +1,52 @@
+import json
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, write_string
+
+def search(keywords: str) -> list[str]:
+    output = cargo_run(["tfidf", "--json", keywords], stdout=True)
+    return sorted([result["source"] for result in json.loads(output.strip())])
+
+def expand(keyword: str) -> dict:
+    return json.loads(cargo_run(["ls-terms", "--expand", keyword, "--json"], stdout=True))
+
+def fuzzy_search():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    write_string("retrieval.txt", "The retrieval pipeline reads a configuration file.")
+    write_string("korean.txt", "한글 문서도 검색할 수 있습니다.")
+    write_string("fruit.txt", "Apples and bananas are yellow or red.")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["add", "retrieval.txt", "korean.txt", "fruit.txt"])
+    cargo_run(["build"])
+
+    # step 1: exact terms only
+    assert search("retreival") == []
+    assert search("config") == []
+
+    # step 2: `ls-terms --expand` works even if `fuzzy_search` is disabled
+    expanded = expand("retreival config 한그")
+    assert [(e["term"], e["kind"], e["distance"]) for e in expanded["retreiv"]] == [("retriev", "typo", 1)]
+    assert [(e["term"], e["kind"]) for e in expanded["config"]] == [("configur", "prefix")]
+    assert [(e["term"], e["kind"]) for e in expanded["한그"]] == [("한글", "prefix")]
+
+    # short terms are not expanded to typos
+    assert expand("red")["red"] == []
+
+    # step 3: fuzzy search
+    cargo_run(["config", "--set", "fuzzy_search", "true"])
+    assert search("retreival") == ["1st chunk of retrieval.txt"]
+    assert search("config") == ["1st chunk of retrieval.txt"]
+    assert search("한그") == ["1st chunk of korean.txt"]
+    assert search("bananas") == ["1st chunk of fruit.txt"]
+
+    # step 4: an exact match is better than an expanded one
+    write_string("config.txt", "The config file is written in json.")
+    cargo_run(["add", "config.txt"])
+    cargo_run(["build"])
+    output = json.loads(cargo_run(["tfidf", "--json", "config"], stdout=True))
+    assert [result["source"] for result in output] == ["1st chunk of config.txt", "1st chunk of retrieval.txt"]
+
+    # step 5: prefixes only
+    cargo_run(["config", "--set", "fuzzy_max_distance", "0"])
+    assert search("retreival") == []
+    assert search("retr") == ["1st chunk of retrieval.txt"]
@@ -124,6 +124,21 @@ def ii_worker():
     assert cargo_run(["ii-status"], stdout=True).strip() == "complete"
     assert "self-introduction.txt" in cargo_run(["tfidf", "Hi, my name is baehyunsol."], stdout=True)
 
+    # a modified file: its old chunks are removed from the ii and the new ones are added
+    write_string("self-introduction.txt", "Hello, I am a ragit developer.")
+    cargo_run(["add", "self-introduction.txt"])
+    cargo_run(["build"])
+    cargo_run(["check"])
+    assert cargo_run(["ii-status"], stdout=True).strip() == "complete"
+    assert "self-introduction.txt" not in cargo_run(["tfidf", "baehyunsol"], stdout=True)
+    assert "self-introduction.txt" in cargo_run(["tfidf", "Hello, I am a ragit developer."], stdout=True)
+
+    # a removed file
+    cargo_run(["rm", "self-introduction.txt"])
+    cargo_run(["check"])
+    assert cargo_run(["ii-status"], stdout=True).strip() == "complete"
+    assert "self-introduction.txt" not in cargo_run(["tfidf", "Hello, I am a ragit developer."], stdout=True)
+
 def generate_terms():
     dictionary = []
     words = set()
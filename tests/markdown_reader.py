@@ -1,3 +1,4 @@
+import json
 from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, write_string
 
 # fixed by 8e06403f
@@ -71,6 +72,31 @@ kube-scheduler [flags]
 
 """
 
+# structural_markdown_chunking
+sample6 = "\n".join([
+    "# Install",
+    "",
+    "Ragit is a git-like rag pipeline. " * 5,
+    "",
+    "## Linux",
+    "",
+    "You can install ragit with cargo. " * 5,
+    "",
+    "```sh",
+    *([f"cargo install ragit  # step {i}" for i in range(20)]),
+    "```",
+    "",
+    "## Windows",
+    "",
+    "| os | arch | supported |",
+    "|----|------|:---------:|",
+    *([f"| windows {i} | x86_64 | yes |" for i in range(12)]),
+    "",
+    "# Usage",
+    "",
+    *(["Run rag build to build a knowledge-base."] * 30),
+])
+
 # fixed by fe26f156
 sample5 = """
 2. some title
@@ -129,3 +155,34 @@ def markdown_reader():
 
     # If pdl-escaping was successful, summay of its chunk must have this substring
     assert "<|media(PATH/TO/YOUR/MEDIA/FILE)|>" in cargo_run(["ls-chunks", "sample5.md"], stdout=True)
+
+    # structural mode: each chunk knows its heading path, and code fences and tables are not splitted
+    cargo_run(["rm", "--all"])
+    cargo_run(["config", "--set", "structural_markdown_chunking", "true"])
+    cargo_run(["config", "--set", "chunk_size", "400"])
+    cargo_run(["config", "--set", "slide_len", "100"])
+    write_string("sample6.md", sample6)
+    cargo_run(["add", "sample1.md", "sample2.md", "sample3.md", "sample4.md", "sample5.md", "sample6.md"])
+    cargo_run(["build"], timeout=20.0)
+    cargo_run(["check"])
+
+    chunks = json.loads(cargo_run(["ls-chunks", "--json", "sample6.md"], stdout=True))
+    headings = [chunk.get("heading") for chunk in chunks]
+    assert len(chunks) > 3
+    assert all(heading is not None for heading in headings)
+    assert "# Install > ## Linux" in headings
+    assert "# Install > ## Windows" in headings
+    assert "# Usage" in headings
+
+    for chunk in chunks:
+        data = chunk["data"]
+        assert data.count("```") % 2 == 0
+
+        if "| os | arch | supported |" in data:
+            assert "| windows 11 | x86_64 | yes |" in data
+
+        # a chunk never has contents of 2 different sections
+        assert not ("## Linux" in data and "## Windows" in data)
+
+    # `render_source` shows the heading path
+    assert "(# Install > ## Linux)" in cargo_run(["ls-chunks", "sample6.md"], stdout=True)
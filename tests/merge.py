@@ -1,5 +1,8 @@
+from http.server import ThreadingHTTPServer
+import json
 import os
 import shutil
+from threading import Thread
 from utils import (
     cargo_run,
     count_chunks,
@@ -7,9 +10,12 @@ from utils import (
     goto_root,
     mk_and_cd_tmp_dir,
     rand_word,
+    read_string,
     write_string,
 )
 
+from embedding import MockEmbeddingHandler, retrieve_sources
+
 def merge():
     goto_root()
     mk_and_cd_tmp_dir()
@@ -99,9 +105,13 @@ def merge():
     os.mkdir("base3")
     os.chdir("base3")
     cargo_run(["init"])
+
+    # `rag merge` updates the ii in place
+    cargo_run(["ii-build"])
     cargo_run(["merge", "../sub-base1", "--prefix", "sub1"])
     cargo_run(["merge", "../sub-base2", "--prefix=sub2"])
     cargo_run(["check"])
+    assert cargo_run(["ii-status"], stdout=True).strip() == "complete"
 
     # some checks
     assert count_files() == (7, 0, 7)
@@ -144,3 +154,54 @@ def merge():
     # the merge was successful, but the count does not change because
     # the files are the same
     assert count_files() == (10, 0, 10)
+    os.chdir("..")
+
+    # base4: `rag merge` copies the embedding vectors
+    server = ThreadingHTTPServer(("127.0.0.1", 0), MockEmbeddingHandler)
+    Thread(target=server.serve_forever, daemon=True).start()
+
+    try:
+        os.chdir("sub-base1")
+        set_embedding_config(server.server_port)
+        cargo_run(["embedding-build"])
+        os.chdir("..")
+
+        os.mkdir("base4")
+        os.chdir("base4")
+        cargo_run(["init"])
+        cargo_run(["config", "--set", "model", "dummy"])
+        set_embedding_config(server.server_port)
+        cargo_run(["embedding-build"])
+        cargo_run(["merge", "../sub-base1", "--prefix", "sub1"])
+        cargo_run(["check"])
+        assert count_embeddings() == count_chunks()
+
+        # the vectors are not created again
+        request_count = len(MockEmbeddingHandler.requests)
+        cargo_run(["embedding-build"])
+        assert len(MockEmbeddingHandler.requests) == request_count
+
+        cargo_run(["config", "--set", "embedding_mode", "only"])
+        query = " ".join(docs[0].split(" ")[:20])
+        assert retrieve_sources(query, 1) == ["1st chunk of sub1/doc_0.md"]
+
+        # `rag check --recover` removes the vectors of orphan chunks
+        index = json.loads(read_string(".ragit/index.json"))
+        index["processed_files"].pop("sub1/doc_0.md")
+        write_string(".ragit/index.json", json.dumps(index))
+        cargo_run(["check", "--recover"])
+        cargo_run(["check"])
+        assert count_embeddings() == count_chunks()
+        assert "doc_0.md" not in "".join(retrieve_sources(query, 3))
+
+    finally:
+        server.shutdown()
+
+def set_embedding_config(port: int):
+    cargo_run(["config", "--set", "embedding_url", f"http://127.0.0.1:{port}/v1/embeddings"])
+    cargo_run(["config", "--set", "embedding_model", "mock-embedding-v1"])
+    cargo_run(["config", "--set", "embedding_api_env_var", ""])
+    cargo_run(["config", "--set", "max_titles", "0"])
+
+def count_embeddings() -> int:
+    return len([f for d in os.listdir(".ragit/chunks") for f in os.listdir(f".ragit/chunks/{d}") if f.endswith(".embedding")])
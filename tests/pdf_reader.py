@@ -0,0 +1,141 @@
+import json
+import os
+import shutil
+import zlib
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir
+
+# It writes a minimal pdf file, so that the test doesn't need any python package.
+# Each page is a tuple of a content stream and `{image name: (width, height, rgb bytes)}`.
+def make_pdf(pages: list[tuple[str, dict]]) -> bytes:
+    objects = []
+
+    def add(obj: bytes) -> int:
+        objects.append(obj)
+        return len(objects)
+
+    font = add(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>")
+    pages_id = add(b"")  # placeholder
+    page_ids = []
+
+    for content, images in pages:
+        xobjects = []
+
+        for name, (width, height, pixels) in images.items():
+            data = zlib.compress(pixels)
+            image = add(f"<< /Type /XObject /Subtype /Image /Width {width} /Height {height} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode /Length {len(data)} >>\nstream\n".encode() + data + b"\nendstream")
+            xobjects.append(f"/{name} {image} 0 R")
+
+        content = content.encode()
+        content_id = add(f"<< /Length {len(content)} >>\nstream\n".encode() + content + b"\nendstream")
+        page_ids.append(add(f"<< /Type /Page /Parent {pages_id} 0 R /MediaBox [0 0 612 792] /Resources << /Font << /F1 {font} 0 R >> /XObject << {' '.join(xobjects)} >> >> /Contents {content_id} 0 R >>".encode()))
+
+    objects[pages_id - 1] = f"<< /Type /Pages /Kids [{' '.join(f'{i} 0 R' for i in page_ids)}] /Count {len(page_ids)} >>".encode()
+    catalog = add(f"<< /Type /Catalog /Pages {pages_id} 0 R >>".encode())
+    result = b"%PDF-1.4\n"
+    offsets = []
+
+    for i, obj in enumerate(objects):
+        offsets.append(len(result))
+        result += f"{i + 1} 0 obj\n".encode() + obj + b"\nendobj\n"
+
+    xref = len(result)
+    result += f"xref\n0 {len(objects) + 1}\n0000000000 65535 f \n".encode()
+
+    for offset in offsets:
+        result += f"{offset:010} 00000 n \n".encode()
+
+    result += f"trailer\n<< /Size {len(objects) + 1} /Root {catalog} 0 R >>\nstartxref\n{xref}\n%%EOF\n".encode()
+    return result
+
+def gradient(width: int, height: int) -> bytes:
+    return bytes([(x * 4) % 256 if c == 0 else (y * 4) % 256 if c == 1 else 128 for y in range(height) for x in range(width) for c in range(3)])
+
+def write_pdf(path: str, pages: list[tuple[str, dict]]):
+    with open(path, "wb") as f:
+        f.write(make_pdf(pages))
+
+def get_chunks(file: str) -> list[dict]:
+    chunks = json.loads(cargo_run(["ls-chunks", "--json", file], stdout=True))
+    return sorted(chunks, key=lambda chunk: chunk["source"]["index"])
+
+def pdf_reader():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["config", "--set", "strict_file_reader", "true"])
+
+    # step 1: text and images
+    write_pdf("text.pdf", [
+        (
+            "BT /F1 12 Tf 72 720 Td (Native pdf reader) Tj 0 -14 Td (extracts the text layer.) Tj ET\n"
+            "BT /F1 12 Tf 72 690 Td [(Words)-300(are)-300(separ)-20(ated)-300(by)-300(gaps.)] TJ ET\n"
+            "q 100 0 0 100 72 500 cm /Im1 Do Q\n"
+            "BT /F1 12 Tf 72 480 Td 14 TL (After the image.) Tj (The second line.) ' ( Still the second line.) Tj ET",
+            {"Im1": (64, 64, gradient(64, 64))},
+        ),
+
+        # a scanned page: there's no text, but an image
+        ("q 500 0 0 700 50 50 cm /Im1 Do Q", {"Im1": (80, 100, gradient(80, 100))}),
+
+        # a tiny image (e.g. a bullet) is not extracted
+        ("q 8 0 0 8 60 720 cm /Im1 Do Q BT /F1 12 Tf 72 720 Td (The last page.) Tj ET", {"Im1": (8, 8, gradient(8, 8))}),
+    ])
+    cargo_run(["add", "text.pdf"])
+    cargo_run(["build"])
+    cargo_run(["check"])
+
+    chunks = get_chunks("text.pdf")
+    assert all(chunk["build_info"]["file_reader_key"] == "pdf_reader_v1" for chunk in chunks)
+    assert chunks[0]["heading"] == "page 1"
+    data = "".join(chunk["data"] for chunk in chunks)
+
+    for line in [
+        "Native pdf reader\nextracts the text layer.\n",
+        "Words are separated by gaps.\n",
+        "After the image.\nThe second line. Still the second line.\n",
+        "The last page.",
+    ]:
+        assert line in data
+
+    images = set(image for chunk in chunks for image in chunk["images"])
+    assert len(images) == 2
+
+    for image in images:
+        assert f"\nimg_{image}\n" in data
+
+    assert "(page 1)" in cargo_run(["ls-chunks", "text.pdf"], stdout=True)
+
+    # step 2: if there are rasters, a page without text uses them instead of embedded images
+    write_pdf("scanned.pdf", [
+        ("BT /F1 12 Tf 72 720 Td (This page has text.) Tj ET q 100 0 0 100 72 500 cm /Im1 Do Q", {"Im1": (64, 64, gradient(64, 64))}),
+        ("q 500 0 0 700 50 50 cm /Im1 Do Q", {"Im1": (80, 100, gradient(80, 100))}),
+    ])
+    os.mkdir("scanned.pdf-pages")
+
+    # `pdf.py` uses 0-based page numbers
+    shutil.copyfile("../tests/images/empty.png", "scanned.pdf-pages/page-0000.png")
+    shutil.copyfile("../tests/images/empty.jpg", "scanned.pdf-pages/page-0001.jpg")
+
+    cargo_run(["add", "scanned.pdf"])
+    cargo_run(["build"])
+    cargo_run(["check"])
+
+    chunks = get_chunks("scanned.pdf")
+    assert len(chunks) == 2
+    assert chunks[0]["heading"] == "page 1"
+    assert "This page has text." in chunks[0]["data"]
+    assert len(chunks[0]["images"]) == 1  # the embedded image, not the raster
+
+    # a raster is a chunk by itself
+    assert chunks[1]["heading"] == "page 2"
+    assert chunks[1]["data"] == f"img_{chunks[1]['images'][0]}"
+
+    images = json.loads(cargo_run(["ls-images", "--json", "scanned.pdf"], stdout=True))
+    assert chunks[1]["images"][0] not in [image for chunk in get_chunks("text.pdf") for image in chunk["images"]]
+    assert len(images) == 2
+
+    # step 3: a pdf file without text and images cannot be built
+    write_pdf("empty.pdf", [("0 0 m 100 100 l S", {})])
+    cargo_run(["add", "empty.pdf"])
+    assert cargo_run(["build"], check=False) != 0
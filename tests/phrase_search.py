@@ -0,0 +1,132 @@
+import json
+import os
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, read_string, write_string
+
+def search(keywords: str) -> list[str]:
+    output = cargo_run(["tfidf", "--json", keywords], stdout=True)
+    return [result["source"] for result in json.loads(output.strip())]
+
+def count_positions_files() -> int:
+    return len([f for d in os.listdir(".ragit/ii") if os.path.isdir(f".ragit/ii/{d}") for f in os.listdir(f".ragit/ii/{d}") if f.endswith(".pos")])
+
+# first bytes of the ii files and the positions files
+def ii_first_bytes() -> list[int]:
+    result = []
+
+    for d in os.listdir(".ragit/ii"):
+        if os.path.isdir(f".ragit/ii/{d}"):
+            for f in os.listdir(f".ragit/ii/{d}"):
+                with open(f".ragit/ii/{d}/{f}", "rb") as file:
+                    result.append(file.read(1)[0])
+
+    return result
+
+def chunk_table_len() -> int:
+    # a marker byte, then 32 bytes per chunk
+    return (os.path.getsize(".ragit/ii/chunk_table") - 1) // 32
+
+def phrase_search():
+    goto_root()
+    mk_and_cd_tmp_dir()
+
+    # the same terms, but in different orders and distances
+    write_string("adjacent.txt", "The connection pool timeout is 30 seconds. Apples and bananas are yellow or red.")
+    write_string("reversed.txt", "The pool connection timeout is 30 seconds. Apples and bananas are yellow or red.")
+    write_string("far.txt", "The connection is 30 seconds. Apples and bananas are yellow or red. pool timeout")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["add", "adjacent.txt", "reversed.txt", "far.txt"])
+    cargo_run(["build"])
+
+    # step 1: without positions, phrases are just terms
+    cargo_run(["ii-build"])
+    assert count_positions_files() == 0
+    assert len(search('"connection pool"')) == 3
+
+    # step 2: `ii-build` builds the ii from scratch if `ii_positions` has changed
+    cargo_run(["config", "--set", "ii_positions", "true"])
+    cargo_run(["ii-build"])
+    cargo_run(["check"])
+    assert cargo_run(["ii-status"], stdout=True).strip() == "complete"
+    assert count_positions_files() > 0
+
+    # step 3: phrase search
+    assert search('"connection pool"') == ["1st chunk of adjacent.txt"]
+    assert search('"pool connection"') == ["1st chunk of reversed.txt"]
+    assert search('"connection pool" timeout') == ["1st chunk of adjacent.txt"]
+    assert search('"connection apples"') == []
+
+    # step 4: proximity search
+    assert set(search('"pool connection"~0')) == {"1st chunk of adjacent.txt", "1st chunk of reversed.txt"}
+    assert len(search('"connection timeout"~20')) == 3
+
+    # step 5: without quotes, nothing is filtered out, but chunks whose terms are close to each other win
+    assert search("connection pool timeout")[0] in ["1st chunk of adjacent.txt", "1st chunk of reversed.txt"]
+    assert search("connection pool timeout")[-1] == "1st chunk of far.txt"
+    cargo_run(["config", "--set", "proximity_boost", "0"])
+    assert len(search("connection pool timeout")) == 3
+    cargo_run(["config", "--set", "proximity_boost", "1"])
+
+    # step 6: `rag build` updates positions incrementally
+    write_string("new.txt", "Please increase the connection pool size.")
+    cargo_run(["add", "new.txt"])
+    cargo_run(["build"])
+    cargo_run(["check"])
+    assert set(search('"connection pool"')) == {"1st chunk of adjacent.txt", "1st chunk of new.txt"}
+
+    # step 7: `rag rm` removes positions incrementally
+    cargo_run(["rm", "adjacent.txt"])
+    cargo_run(["check"])
+    assert cargo_run(["ii-status"], stdout=True).strip() == "complete"
+    assert search('"connection pool"') == ["1st chunk of new.txt"]
+    assert set(search('"pool connection"~0')) == {"1st chunk of new.txt", "1st chunk of reversed.txt"}
+
+    # the removed chunk stays in the chunk table until `ii-build` builds the ii from scratch
+    assert chunk_table_len() == 4
+    cargo_run(["ii-build"])
+    cargo_run(["check"])
+    assert chunk_table_len() == 3
+    assert search('"connection pool"') == ["1st chunk of new.txt"]
+
+    # step 8: `ii-reset` removes positions
+    cargo_run(["ii-reset"])
+    cargo_run(["config", "--set", "ii_positions", "false"])
+    cargo_run(["ii-build"])
+    cargo_run(["check"])
+    assert count_positions_files() == 0
+    assert len(search('"connection pool"')) == 3
+
+    # step 9: `rag migrate` converts an ii in the old format (a list of uids per term)
+    #         there's no way to build an ii in the old format, so it makes an empty
+    #         ii in the old format and lets `rag build` update it incrementally
+    cargo_run(["config", "--set", "ii_positions", "true"])
+    cargo_run(["ii-build"])
+    assert all(first_byte == 2 for first_byte in ii_first_bytes())
+    compact_result = search('"connection pool"')
+    files = {file: read_string(file) for file in ["reversed.txt", "far.txt", "new.txt"]}
+    goto_root()
+    mk_and_cd_tmp_dir()
+
+    for file, content in files.items():
+        write_string(file, content)
+
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["config", "--set", "ii_positions", "true"])
+    index = json.loads(read_string(".ragit/index.json"))
+    index["ii_status"] = {"type": "Complete"}
+    index["ii_positions"] = True
+    del index["ii_format"]
+    write_string(".ragit/index.json", json.dumps(index))
+    cargo_run(["add", "reversed.txt", "far.txt", "new.txt"])
+    cargo_run(["build"])
+    cargo_run(["check"])
+    assert all(first_byte != 2 for first_byte in ii_first_bytes())
+    assert search('"connection pool"') == compact_result
+
+    cargo_run(["migrate"])
+    cargo_run(["check"])
+    assert cargo_run(["ii-status"], stdout=True).strip() == "complete"
+    assert all(first_byte == 2 for first_byte in ii_first_bytes())
+    assert json.loads(read_string(".ragit/index.json"))["ii_format"] == "Compact"
+    assert search('"connection pool"') == compact_result
@@ -0,0 +1,29 @@
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, write_string
+
+def query_image():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    write_string("sample.txt", "There is nothing to see here.")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["add", "sample.txt"])
+    cargo_run(["build"])
+
+    # step 1: the dummy model cannot read images
+    stderr = cargo_run(["query", "--image", "../tests/images/empty.png", "What is this?"], stderr=True, check=False)
+    assert "cannot read images" in stderr
+
+    # the query text is optional if there's an image
+    stderr = cargo_run(["query", "--image", "../tests/images/empty.jpg"], stderr=True, check=False)
+    assert "cannot read images" in stderr
+
+    stderr = cargo_run(["query", "--json", "--image", "../tests/images/empty.webp"], stderr=True, check=False)
+    assert "cannot read images" in stderr
+
+    # step 2: invalid inputs
+    assert cargo_run(["query", "--image", "no_such_image.png", "What is this?"], check=False) != 0
+    assert cargo_run(["query", "--image", "sample.txt", "What is this?"], check=False) != 0
+    assert cargo_run(["query", "--image", "../tests/images/empty.png", "-i"], check=False) != 0
+
+    # step 3: a query without an image works as before
+    assert cargo_run(["query", "What is this?"], check=False) == 0
@@ -0,0 +1,39 @@
+import json
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, write_string
+
+def query_schema():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    write_string("sample.txt", "There is nothing to see here.")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+
+    # step 1: there's no chunk, so it uses `prompts/raw.pdl`
+    # the dummy model always returns "dummy", which is a valid string
+    assert json.loads(cargo_run(["query", "--schema", "str", "What is this?"], stdout=True)) == "dummy"
+
+    cargo_run(["add", "sample.txt"])
+    cargo_run(["build"])
+
+    # step 2: it uses `prompts/answer_query.pdl`
+    assert json.loads(cargo_run(["query", "--schema", "str", "nothing"], stdout=True)) == "dummy"
+    response = json.loads(cargo_run(["query", "--json", "--schema", "str { max: 10 }", "nothing"], stdout=True))
+    assert json.loads(response["response"]) == "dummy"
+
+    # the chunks are given to the model, but a json value doesn't have citations
+    assert len(response["retrieved_chunks"]) == 1
+    assert response["citations"] == []
+
+    # step 3: "dummy" is not an integer, nor an object
+    stderr = cargo_run(["query", "--schema", "int", "nothing"], stderr=True, check=False)
+    assert "doesn't match the schema" in stderr
+    stderr = cargo_run(["query", "--schema", "{ name: str, age: int }", "nothing"], stderr=True, check=False)
+    assert "doesn't match the schema" in stderr
+    stderr = cargo_run(["query", "--schema", "str { min: 10 }", "nothing"], stderr=True, check=False)
+    assert "doesn't match the schema" in stderr
+
+    # step 4: invalid schemas and invalid combinations of options
+    stderr = cargo_run(["query", "--schema", "{ name: ", "nothing"], stderr=True, check=False)
+    assert "invalid schema" in stderr
+    assert cargo_run(["query", "--schema", "str", "-i"], check=False) != 0
+    assert cargo_run(["query", "--schema", "str", "--image", "../tests/images/empty.png", "nothing"], check=False) != 0
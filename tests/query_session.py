@@ -0,0 +1,46 @@
+import json
+import subprocess
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, write_string
+
+def query_session():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    write_string("sample.txt", "Ragit is a RAG framework. It stores knowledge-bases in a git-like way.")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["add", "sample.txt"])
+    cargo_run(["build"])
+
+    # step 1: a query without `--session` doesn't create a session
+    cargo_run(["query", "What is ragit?"])
+    assert json.loads(cargo_run(["session", "--list", "--json"], stdout=True)) == []
+
+    # step 2: `--session` creates a session, and the next query continues it
+    cargo_run(["query", "--session", "research", "What is ragit?"])
+    response = json.loads(cargo_run(["query", "--json", "--session", "research", "How does it store data?"], stdout=True))
+    assert response["multi_turn_schema"] is not None  # it's a multi-turn query
+    sessions = json.loads(cargo_run(["session", "--list", "--json"], stdout=True))
+    assert [(s["name"], s["turns"]) for s in sessions] == [("research", 2)]
+    turns = json.loads(cargo_run(["session", "--show", "research", "--json"], stdout=True))
+    assert [turn["query"] for turn in turns] == ["What is ragit?", "How does it store data?"]
+
+    # step 3: interactive mode saves the session after every turn, and ends with EOF
+    subprocess.run(
+        ["cargo", "run", "--release", "--", "query", "--interactive", "--session", "chat"],
+        input="first question\n\nsecond question\n",
+        text=True,
+        check=True,
+    )
+    turns = json.loads(cargo_run(["session", "--show", "chat", "--json"], stdout=True))
+    assert [turn["query"] for turn in turns] == ["first question", "second question"]
+
+    # step 4: invalid names
+    assert cargo_run(["query", "--session", "../index", "What is ragit?"], check=False) != 0
+    assert cargo_run(["session", "--show", "no-such-session"], check=False) != 0
+
+    # step 5: remove sessions
+    cargo_run(["session", "--remove", "research"])
+    assert [s["name"] for s in json.loads(cargo_run(["session", "--list", "--json"], stdout=True))] == ["chat"]
+    assert cargo_run(["session", "--remove", "research"], check=False) != 0
+    cargo_run(["session", "--remove-all"])
+    assert json.loads(cargo_run(["session", "--list", "--json"], stdout=True)) == []
@@ -0,0 +1,76 @@
+import json
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, write_string
+
+def explain(args: list[str]) -> dict:
+    return json.loads(cargo_run(["retrieve-chunks", "--explain", "--json", *args], stdout=True))
+
+def retrieval_explain():
+    goto_root()
+    mk_and_cd_tmp_dir()
+
+    for i in range(6):
+        write_string(f"server{i}.txt", f"The server configuration number {i} is here.")
+
+    write_string("fruit.txt", "Apples and bananas are not related to anything.")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["add", *[f"server{i}.txt" for i in range(6)], "fruit.txt"])
+    cargo_run(["build"])
+
+    # step 1: a small knowledge-base doesn't need tf-idf
+    report = explain(["server configuration"])
+    assert report["stages"][0]["name"] == "all chunks"
+    assert len(report["stages"][0]["candidates"]) == 7
+
+    # step 2: the dummy model picks nothing, so rerank stages are empty
+    cargo_run(["config", "--set", "max_titles", "3"])
+    cargo_run(["config", "--set", "max_summaries", "3"])
+    cargo_run(["config", "--set", "max_retrieval", "2"])
+    report = explain(["server configuration"])
+    assert report["keywords"] is not None
+    assert [stage["name"] for stage in report["stages"]] == ["hybrid search", "summaries_to_chunks"]
+    hybrid = report["stages"][0]["candidates"]
+    assert len(hybrid) == 3
+    assert all("fruit" not in candidate["source"] for candidate in hybrid)
+
+    # the terms contribute to the tf-idf score
+    for candidate in hybrid:
+        if candidate["tfidf_score"] is not None:
+            assert abs(sum(score for _, score in candidate["term_scores"]) - candidate["tfidf_score"]) < 0.01
+            assert {term for term, _ in candidate["term_scores"]} <= {"server", "configur"}
+
+    assert report["stages"][1]["picked"] == []
+
+    # step 3: filters
+    report = explain(["--file-ext", "txt", "--path", "fruit.txt", "server configuration"])
+    assert report["filtered_chunks"] == 1
+    assert report["stages"][0]["name"] == "filter"
+
+    # step 4: a table
+    table = cargo_run(["retrieve-chunks", "--explain", "server configuration"], stdout=True)
+    assert "stage 1: hybrid search" in table
+    assert "LLM picked: none" in table
+
+    # step 5: diversity
+    write_string("server_manual.txt", "\n\n".join(f"The server configuration section {i}. " * 8 for i in range(8)))
+    cargo_run(["config", "--set", "chunk_size", "300"])
+    cargo_run(["config", "--set", "slide_len", "50"])
+    cargo_run(["add", "server_manual.txt"])
+    cargo_run(["build"])
+    cargo_run(["config", "--set", "max_chunks_per_file", "1"])
+    report = explain(["server configuration"])
+    assert [stage["name"] for stage in report["stages"]][:2] == ["hybrid search", "diversity"]
+
+    # `max_summaries * mmr_pool_coeff` candidates, and all of them are from the manual
+    assert len(report["stages"][0]["candidates"]) == 9
+    assert all("server_manual.txt" in candidate["source"] for candidate in report["stages"][0]["candidates"])
+
+    # there's no other file in the pool, so the cap can't be kept
+    assert len(report["stages"][1]["candidates"]) == 3
+
+    # a bigger pool has other files
+    cargo_run(["config", "--set", "mmr_pool_coeff", "10"])
+    report = explain(["server configuration"])
+    diverse = [candidate["source"] for candidate in report["stages"][1]["candidates"]]
+    assert len(diverse) == 3
+    assert sum("server_manual.txt" in source for source in diverse) == 1
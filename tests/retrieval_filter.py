@@ -0,0 +1,59 @@
+from datetime import date, timedelta
+import json
+import os
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, write_string
+
+def retrieve(filters: list[str], query: str = "server configuration") -> list[str]:
+    output = cargo_run(["retrieve-chunks", "--no-rerank", "--json", *filters, query], stdout=True)
+    return sorted([chunk["source"].split(" chunk of ")[1] for chunk in json.loads(output.strip())])
+
+def retrieval_filter():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    os.makedirs("docs/api")
+    os.makedirs("docs/guide")
+    os.makedirs("src")
+    write_string("docs/api/endpoints.md", "The server configuration has endpoints.")
+    write_string("docs/api/errors.txt", "The server configuration has error codes.")
+    write_string("docs/guide/install.md", "Install the server and edit the configuration.")
+    write_string("src/server.txt", "The server reads its configuration at startup.")
+    all_files = ["docs/api/endpoints.md", "docs/api/errors.txt", "docs/guide/install.md", "src/server.txt"]
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["add", "--all"])
+    cargo_run(["build"])
+
+    # step 1: if there're only a few chunks, it loads all the chunks that pass the filter,
+    #         and step 2: otherwise, it runs tfidf on the chunks that pass the filter
+    for max_titles in ["32", "0"]:
+        cargo_run(["config", "--set", "max_titles", max_titles])
+        assert retrieve([]) == all_files
+        assert retrieve(["--path", "docs/api/**"]) == ["docs/api/endpoints.md", "docs/api/errors.txt"]
+        assert retrieve(["--path", "docs"]) == ["docs/api/endpoints.md", "docs/api/errors.txt", "docs/guide/install.md"]
+        assert retrieve(["--path", "/api/**"]) == []
+        assert retrieve(["--file-ext", "md"]) == ["docs/api/endpoints.md", "docs/guide/install.md"]
+        assert retrieve(["--file-ext", "md,.txt"]) == all_files
+        assert retrieve(["--path", "docs/**", "--file-ext", "txt"]) == ["docs/api/errors.txt"]
+        assert retrieve(["--uid", "src/server.txt"]) == ["src/server.txt"]
+
+        tomorrow = (date.today() + timedelta(days=1)).isoformat()
+        yesterday = (date.today() - timedelta(days=1)).isoformat()
+        assert retrieve(["--after", tomorrow]) == []
+        assert retrieve(["--after", yesterday]) == all_files
+        assert retrieve(["--after", yesterday, "--file-ext", "txt"]) == ["docs/api/errors.txt", "src/server.txt"]
+
+    # step 3: a uid prefix
+    chunk_uid = json.loads(cargo_run(["retrieve-chunks", "--no-rerank", "--json", "--uid-only", "--path", "src/**", "server"], stdout=True))[0]
+    assert retrieve(["--uid", chunk_uid[:8]]) == ["src/server.txt"]
+
+    # step 4: `rag query` uses the same filters
+    response = json.loads(cargo_run(["query", "--json", "--file-ext", "md", "server configuration"], stdout=True))
+    assert len(response["retrieved_chunks"]) == 2
+
+    # the dummy model doesn't cite anything
+    assert response["citations"] == []
+    response = json.loads(cargo_run(["query", "--json", "--after", tomorrow, "server configuration"], stdout=True))
+    assert response["retrieved_chunks"] == []
+
+    # step 5: an invalid date
+    assert cargo_run(["retrieve-chunks", "--after", "yesterday", "server"], check=False) != 0
@@ -0,0 +1,107 @@
+import json
+import os
+from utils import (
+    cargo_run,
+    count_files,
+    goto_root,
+    mk_and_cd_tmp_dir,
+    write_string,
+)
+
+def get_status() -> dict:
+    return json.loads(cargo_run(["status", "--json"], stdout=True))
+
+def status():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    os.mkdir("sub")
+    write_string("a.txt", "Hello, world!")
+    write_string("b.txt", "Goodbye, world!")
+    write_string("sub/c.txt", "Hello again!")
+    write_string("ignored.txt", "You cannot see me.")
+    write_string(".ragignore", "ignored.txt")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+
+    # step 1: new files and staged files
+    assert get_status() == {
+        "staged": [],
+        "modified": [],
+        "deleted": [],
+        "new": ["a.txt", "b.txt", "sub/c.txt"],
+    }
+    cargo_run(["add", "a.txt", "b.txt"])
+    assert get_status() == {
+        "staged": ["a.txt", "b.txt"],
+        "modified": [],
+        "deleted": [],
+        "new": ["sub/c.txt"],
+    }
+
+    cargo_run(["build"])
+    cargo_run(["add", "--all"])
+    cargo_run(["build"])
+    assert get_status() == {
+        "staged": [],
+        "modified": [],
+        "deleted": [],
+        "new": [],
+    }
+    assert "up to date" in cargo_run(["status"], stdout=True)
+
+    # step 2: modified and deleted files
+    write_string("a.txt", "Hello, world! I'm modified.")
+    os.remove("b.txt")
+    assert get_status() == {
+        "staged": [],
+        "modified": ["a.txt"],
+        "deleted": ["b.txt"],
+        "new": [],
+    }
+    status_text = cargo_run(["status"], stdout=True)
+    assert "modified: a.txt" in status_text
+    assert "deleted:  b.txt" in status_text
+
+    # modifying a file back to its original content is not a modification
+    write_string("sub/c.txt", "Hello again?")
+    assert get_status()["modified"] == ["a.txt", "sub/c.txt"]
+    write_string("sub/c.txt", "Hello again!")
+    assert get_status()["modified"] == ["a.txt"]
+
+    # step 3: `rag add --update`
+    assert cargo_run(["add", "--update", "a.txt"], check=False) != 0
+    assert cargo_run(["add", "--update", "--force"], check=False) != 0
+
+    cargo_run(["add", "--update", "--dry-run"])
+    assert get_status()["modified"] == ["a.txt"]
+    assert count_files() == (3, 0, 3)
+
+    cargo_run(["add", "-u"])
+    assert get_status() == {
+        "staged": ["a.txt"],
+        "modified": [],
+        "deleted": [],
+        "new": [],
+    }
+    assert count_files() == (3, 1, 2)  # `a.txt` is both staged and processed
+    cargo_run(["check"])
+
+    cargo_run(["build"])
+    assert get_status()["staged"] == []
+    assert count_files() == (2, 0, 2)
+    cargo_run(["check"])
+
+    # step 4: a staged file is deleted before it's processed
+    write_string("d.txt", "I'll be gone soon.")
+    cargo_run(["add", "d.txt"])
+    os.remove("d.txt")
+    assert get_status()["deleted"] == ["d.txt"]
+    cargo_run(["add", "--update"])
+    assert get_status() == {
+        "staged": [],
+        "modified": [],
+        "deleted": [],
+        "new": [],
+    }
+    cargo_run(["build"])
+    cargo_run(["check"])
@@ -0,0 +1,60 @@
+import json
+from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, read_string, write_string
+
+def search(keywords: str) -> list[str]:
+    output = cargo_run(["tfidf", "--json", keywords], stdout=True)
+    return sorted([result["source"] for result in json.loads(output.strip())])
+
+def ii_status() -> str:
+    return cargo_run(["ii-status"], stdout=True).strip()
+
+def stemmer():
+    goto_root()
+    mk_and_cd_tmp_dir()
+    write_string("houses.txt", "Die Häuser sind alt.")
+    write_string("connections.txt", "Die Verbindungen sind schnell.")
+    cargo_run(["init"])
+    cargo_run(["config", "--set", "model", "dummy"])
+    cargo_run(["add", "houses.txt", "connections.txt"])
+    cargo_run(["build"])
+    cargo_run(["ii-build"])
+    assert ii_status() == "complete"
+
+    # step 1: the english stemmer doesn't know german
+    # (file names are also searchable, so they're in english)
+    assert search("Haus") == []
+    assert search("die") == ["1st chunk of connections.txt", "1st chunk of houses.txt"]
+    assert json.loads(read_string(".ragit/index.json"))["tokenizer_config"] == { "stemmer": "english", "stopwords": [], "split_cjk": True }
+
+    # step 2: invalid configs are rejected
+    assert cargo_run(["config", "--set", "stemmer", "klingon"], check=False) != 0
+    assert cargo_run(["config", "--set", "stopwords", "die"], check=False) != 0
+    assert json.loads(cargo_run(["config", "--get", "stemmer"], stdout=True)) == "english"
+
+    # step 3: the new config is recorded, but not applied until the next build
+    cargo_run(["config", "--set", "stemmer", "german"])
+    cargo_run(["config", "--set", "stopwords", "[\"die\", \"sind\"]"])
+    assert ii_status() == "outdated"
+    assert search("Haus") == []
+    assert json.loads(read_string(".ragit/index.json"))["tokenizer_config"]["stemmer"] == "english"
+
+    # step 4: `rag build` rebuilds the tfidf files
+    cargo_run(["build"])
+    assert json.loads(read_string(".ragit/index.json"))["tokenizer_config"] == { "stemmer": "german", "stopwords": ["die", "sind"], "split_cjk": True }
+    assert search("Haus") == ["1st chunk of houses.txt"]
+    assert search("Verbindung") == ["1st chunk of connections.txt"]
+    assert search("Die") == []
+    cargo_run(["check"])
+
+    # step 5: the ii uses the new tokenizer
+    cargo_run(["ii-build"])
+    assert ii_status() == "complete"
+    assert "houses.txt" in cargo_run(["search", "Haus"], stdout=True)
+    assert cargo_run(["search", "sind"], check=False) != 0
+
+    # step 6: new files are tokenized with the new config
+    write_string("trains.txt", "Die Bahnen sind voll.")
+    cargo_run(["add", "trains.txt"])
+    cargo_run(["build"])
+    assert search("Bahn") == ["1st chunk of trains.txt"]
+    assert search("sind") == []
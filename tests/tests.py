@@ -1,16 +1,24 @@
 from add_and_rm import add_and_rm
 from add_and_rm2 import add_and_rm2
 from archive import archive
+from boolean_search import boolean_search
+from build_watch import build_watch
 from cargo_tests import cargo_tests
 from cat_file import cat_file
+from cjk import cjk
 from cli import cli
 from clone import clone
 from clone2 import clone2
+from code_reader import code_reader
+from context_expansion import context_expansion
 from csv_reader import csv_reader
+from embedding import embedding
 from empty import empty
 from end_to_end import end_to_end
 from external_bases import external_bases
 from extract_keywords import extract_keywords
+from field_weights import field_weights
+from fuzzy_search import fuzzy_search
 from ignore import ignore
 from ii import ii
 from images import images
@@ -26,10 +34,19 @@ from migrate import migrate
 from migrate2 import migrate2
 from models_init import models_init, test_home_config_override
 from orphan_process import orphan_process
+from pdf_reader import pdf_reader
+from phrase_search import phrase_search
 from prompts import prompts
+from query_image import query_image
+from query_schema import query_schema
+from query_session import query_session
 from ragit_api import ragit_api
 from recover import recover
+from retrieval_explain import retrieval_explain
+from retrieval_filter import retrieval_filter
 from server import server
+from status import status
+from stemmer import stemmer
 from subdir import subdir
 from symlink import symlink
 from tfidf import tfidf
@@ -145,6 +162,10 @@ Commands
                                 handle the files correctly. It also tests interrupting
                                 `rag build`.
 
+    build_watch                 run `build_watch` test
+                                It runs `rag build --watch` in the background and modifies
+                                files in the knowledge-base.
+
     many_jobs [model=dummy] [jobs=999]
                                 run `many_jobs` test
                                 `rag build` by default runs with many processes, and a
@@ -156,6 +177,10 @@ Commands
                                 It runs `ls-files`, `ls-chunks`, and `tfidf` with bunch
                                 of different options.
 
+    status                      run `status` test
+                                It runs `rag status` and `rag add --update` with modified,
+                                deleted and new files.
+
     meta                        run `meta` test
                                 It runs `rag meta`-family commands and see if it works.
 
@@ -169,6 +194,16 @@ Commands
     ii                          run `ii` test
                                 It creates an inverted index and test it.
 
+    phrase_search               run `phrase_search` test
+                                It tests phrase and proximity search, with and without
+                                positions in the inverted index.
+
+    boolean_search              run `boolean_search` test
+                                It runs `rag search` with and without an inverted index.
+
+    fuzzy_search                run `fuzzy_search` test
+                                It expands misspelt and partial terms.
+
     cat_file                    run `cat_file` test
 
     images                      run `images` test
@@ -207,10 +242,31 @@ Commands
 
     csv_reader                  run `csv_reader` test
 
+    code_reader                 run `code_reader` test
+                                It checks the heading path and the symbol of the chunks
+                                of rust and python files.
+
+    pdf_reader                  run `pdf_reader` test
+                                It checks if ragit extracts texts and images of pdf files
+                                page by page.
+
     prompts [model=dummy]       run `prompts` test
                                 It's the smallest set of commands that parses and executes
                                 all the `.pdl` files in `prompts/` directory.
 
+    query_image                 run `query_image` test
+                                It only checks the errors of `rag query --image`, because
+                                the dummy model cannot read images.
+
+    query_schema                run `query_schema` test
+                                It checks if `rag query --schema` validates the response.
+
+    query_session               run `query_session` test
+                                It runs `rag query --session` and `rag session`.
+
+    context_expansion           run `context_expansion` test
+                                It widens retrieved chunks with their neighbours.
+
     subdir                      run `subdir` test
                                 It checks whether `ragit` is smart enough to find `.ragit/`
                                 in any directory.
@@ -220,6 +276,26 @@ Commands
                                 `rag tfidf` can retrieve files correctly. It also tests
                                 tfidf searches on cjk strings.
 
+    stemmer                     run `stemmer` test
+                                It changes the stemmer and stopwords of a knowledge-base.
+
+    cjk                         run `cjk` test
+                                It runs tfidf and boolean search on Chinese and Japanese
+                                documents.
+
+    field_weights               run `field_weights` test
+                                It changes the weights of the tfidf fields at query time.
+
+    embedding                   run `embedding` test
+                                It runs a mock embedding server and tests vector search.
+
+    retrieval_filter            run `retrieval_filter` test
+                                It restricts retrieval with path, extension, date and uid
+                                filters.
+
+    retrieval_explain           run `retrieval_explain` test
+                                It checks the candidates that `--explain` reports.
+
     ragit_api [model]           run `ragit_api` test
                                 It asks "what's your name" to the model. It returns OK
                                 if the api call was successful. It doesn't care about the
@@ -299,6 +375,9 @@ if __name__ == "__main__":
         elif command == "many_chunks":
             many_chunks()
 
+        elif command == "build_watch":
+            build_watch()
+
         elif command == "many_jobs":
             jobs = args[3] if len(args) > 3 else 999
             test_model = test_model if test_model else "dummy"
@@ -307,6 +386,9 @@ if __name__ == "__main__":
         elif command == "ls":
             ls()
 
+        elif command == "status":
+            status()
+
         elif command == "meta":
             meta()
 
@@ -320,6 +402,15 @@ if __name__ == "__main__":
         elif command == "ii":
             ii()
 
+        elif command == "phrase_search":
+            phrase_search()
+
+        elif command == "boolean_search":
+            boolean_search()
+
+        elif command == "fuzzy_search":
+            fuzzy_search()
+
         elif command == "cat_file":
             cat_file()
 
@@ -374,16 +465,52 @@ if __name__ == "__main__":
         elif command == "csv_reader":
             csv_reader()
 
+        elif command == "code_reader":
+            code_reader()
+
+        elif command == "pdf_reader":
+            pdf_reader()
+
         elif command == "prompts":
             test_model = test_model or "dummy"
             prompts(test_model=test_model)
 
+        elif command == "query_image":
+            query_image()
+
+        elif command == "query_schema":
+            query_schema()
+
+        elif command == "query_session":
+            query_session()
+
+        elif command == "context_expansion":
+            context_expansion()
+
         elif command == "subdir":
             subdir()
 
         elif command == "tfidf":
             tfidf()
 
+        elif command == "stemmer":
+            stemmer()
+
+        elif command == "cjk":
+            cjk()
+
+        elif command == "field_weights":
+            field_weights()
+
+        elif command == "embedding":
+            embedding()
+
+        elif command == "retrieval_filter":
+            retrieval_filter()
+
+        elif command == "retrieval_explain":
+            retrieval_explain()
+
         elif command == "ragit_api":
             if test_model is None:
                 print("Please specify which model to run the tests with.")
@@ -415,19 +542,36 @@ if __name__ == "__main__":
                 ("cli", cli),
                 ("archive", archive),
                 ("many_chunks", many_chunks),
+                ("build_watch", build_watch),
                 ("many_jobs", many_jobs),
                 ("ls", ls),
+                ("status", status),
                 ("meta", meta),
                 ("symlink", symlink),
                 ("ii", ii),
+                ("phrase_search", phrase_search),
+                ("boolean_search", boolean_search),
+                ("fuzzy_search", fuzzy_search),
                 ("cat_file", cat_file),
                 ("images", images),
                 ("markdown_reader", markdown_reader),
                 ("csv_reader", csv_reader),
+                ("code_reader", code_reader),
+                ("pdf_reader", pdf_reader),
                 ("subdir", subdir),
                 ("tfidf", tfidf),
+                ("stemmer", stemmer),
+                ("cjk", cjk),
+                ("field_weights", field_weights),
+                ("embedding", embedding),
+                ("retrieval_filter", retrieval_filter),
+                ("retrieval_explain", retrieval_explain),
                 ("merge", merge),
                 ("external_bases", external_bases),
+                ("query_image", query_image),
+                ("query_schema", query_schema),
+                ("query_session", query_session),
+                ("context_expansion", context_expansion),
                 ("end_to_end dummy", lambda: end_to_end(test_model="dummy")),
                 ("end_to_end llama3.3-70b", lambda: end_to_end(test_model="llama3.3-70b")),
                 ("prompts dummy", lambda: prompts(test_model="dummy")),
@@ -1,6 +1,6 @@
 import json
 import re
-from utils import cargo_run, goto_root, mk_and_cd_tmp_dir, write_string
+from utils import cargo_run, count_chunks, goto_root, mk_and_cd_tmp_dir, write_string
 
 def parse_tfidf_output(args: list[str], extra_check: bool = True) -> int:
     output = cargo_run(["tfidf"] + args, stdout=True)
@@ -75,3 +75,34 @@ def tfidf():
         assert parse_tfidf_output(["--limit", "15", "unique"]) == 15
         cargo_run(["ii-build"])
         cargo_run(["check"])
+
+    # step 6: corpus-wide statistics
+    def corpus_stats() -> dict:
+        with open(".ragit/corpus_stats.json", "r") as f:
+            return json.load(f)
+
+    assert corpus_stats()["doc_count"] == count_chunks()
+
+    # terms are stemmed: "unique" -> "uniqu"
+    assert corpus_stats()["document_frequency"]["uniqu"] == 20
+
+    # tf-idf scores don't depend on whether the inverted index narrowed down the candidates
+    cargo_run(["config", "--set", "ii_coeff", "2"])
+    cargo_run(["config", "--set", "enable_ii", "false"])
+    without_ii = cargo_run(["tfidf", "--uid-only", "--limit=3", "Praesent neque magna"], stdout=True)
+    cargo_run(["config", "--set", "enable_ii", "true"])
+    assert cargo_run(["tfidf", "--uid-only", "--limit=3", "Praesent neque magna"], stdout=True) == without_ii
+
+    # `rag rm` updates the statistics, and a stale file is recreated from scratch
+    cargo_run(["rm", "0.txt"])
+    assert corpus_stats()["doc_count"] == count_chunks()
+    assert corpus_stats()["document_frequency"]["uniqu"] == 19
+    write_string(".ragit/corpus_stats.json", json.dumps({"doc_count": 0, "total_len": 0, "document_frequency": {}}))
+    cargo_run(["build"])
+    assert corpus_stats()["doc_count"] == count_chunks()
+    assert corpus_stats()["document_frequency"]["uniqu"] == 19
+
+    cargo_run(["config", "--set", "bm25_k1", "2.0"])
+    cargo_run(["config", "--set", "bm25_b", "0.0"])
+    assert "lorem_ipsum1.txt" in cargo_run(["tfidf", "Praesent neque magna"], stdout=True)
+    cargo_run(["check"])